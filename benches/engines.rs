@@ -0,0 +1,176 @@
+// Times the Intcode engines against each other on day02- and day07-style workloads.
+//
+//   cargo bench --bench engines
+//
+// "interpreter" is how the days ran before there were other engines: one
+// `IntcodeComputer` per candidate, each on its own clone of the program, spread over
+// rayon's threads. The other engines should only replace it where they beat it.
+
+use advent_of_code_2019::days::day07;
+use advent_of_code_2019::intcode::{
+  self, lang, search, CompiledProgram, IntcodeComputer, IntcodeComputerState, IntcodeSequence,
+};
+use rayon::prelude::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 20;
+
+/// Runs `f` `RUNS` times after a warm-up, and prints the average time.
+fn time<T>(name: &str, mut f: impl FnMut() -> T) -> Duration {
+  black_box(f());
+  let start = Instant::now();
+  for _ in 0..RUNS {
+    black_box(f());
+  }
+  let average = start.elapsed() / RUNS;
+  println!("  {:<24} {:>10.3?}", name, average);
+  average
+}
+
+fn permutations(options: &[u8]) -> Vec<day07::PhaseSettingSequence> {
+  let mut permutations = vec![];
+  let mut settings = [0; 5];
+  fn fill(options: &[u8], depth: usize, settings: &mut [u8; 5], permutations: &mut Vec<[u8; 5]>) {
+    if depth == 5 {
+      permutations.push(*settings);
+      return;
+    }
+    for option in options {
+      if !settings[..depth].contains(option) {
+        settings[depth] = *option;
+        fill(options, depth + 1, settings, permutations);
+      }
+    }
+  }
+  fill(options, 0, &mut settings, &mut permutations);
+  permutations
+}
+
+fn amplifiers() {
+  // Loops a while per signal, to be closer to the real puzzle than the examples are
+  let program = lang::compile(
+    "
+    let phase = input();
+    let signal = input();
+    let i = 0;
+    while i < 40 {
+      signal = signal + phase * i;
+      i = i + 1;
+    }
+    output(signal);
+    ",
+  )
+  .unwrap();
+  let combinations = permutations(&[0, 1, 2, 3, 4]);
+  println!("day07 part one, {} words", program.len());
+
+  let interpreter = time("interpreter", || {
+    combinations
+      .par_iter()
+      .map(|settings| day07::compute_thruster_signal(&program, settings))
+      .max()
+  });
+  let compiled = time("compiled", || {
+    let compiled = CompiledProgram::new(&program);
+    combinations
+      .par_iter()
+      .map(|settings| {
+        settings.iter().fold(0, |signal, setting| {
+          let outputs = compiled
+            .start()
+            .run_to_halt(&[isize::from(*setting), signal]);
+          outputs.unwrap()[0]
+        })
+      })
+      .max()
+  });
+  let batched = time("batched", || {
+    day07::get_highest_phase_settings(&program, &[0, 1, 2, 3, 4])
+  });
+  summarize(interpreter, &[("compiled", compiled), ("batched", batched)]);
+}
+
+fn feedback_amplifiers() {
+  let program = lang::compile(
+    "
+    let phase = input();
+    let round = 0;
+    while round < 10 {
+      let signal = input();
+      let i = 0;
+      while i < 20 {
+        signal = signal + phase;
+        i = i + 1;
+      }
+      output(signal);
+      round = round + 1;
+    }
+    ",
+  )
+  .unwrap();
+  let combinations = permutations(&[5, 6, 7, 8, 9]);
+  println!("day07 part two, {} words", program.len());
+
+  let interpreter = time("interpreter", || {
+    combinations
+      .par_iter()
+      .map(|settings| day07::compute_thruster_signal_feedback(&program, settings))
+      .max()
+  });
+  let batched = time("batched", || {
+    day07::get_highest_feedback_phase_settings(&program, &[5, 6, 7, 8, 9])
+  });
+  summarize(interpreter, &[("batched", batched)]);
+}
+
+/// Every noun and verb of a day02-style program.
+fn noun_verb_search() {
+  let program: IntcodeSequence = intcode::parse(
+    "1,0,0,3,1,1,2,3,1,3,4,3,1,5,0,3,2,1,10,19,1,19,5,23,2,23,6,27,1,27,5,31,2,6,31,35,\
+     1,5,35,39,2,39,9,43,1,43,5,47,1,10,47,51,1,51,6,55,1,55,10,59,1,59,6,63,2,13,63,67,\
+     1,9,67,71,2,6,71,75,1,5,75,79,1,9,79,83,2,6,83,87,1,5,87,91,2,6,91,95,2,95,9,99,1,\
+     99,6,103,1,103,13,107,2,13,107,111,2,111,10,115,1,115,6,119,1,6,119,123,2,6,123,127,\
+     1,127,5,131,2,131,6,135,1,135,2,139,1,139,9,0,99,2,14,0,0",
+  );
+  let goal = |memory: &IntcodeSequence| memory[0] == 19690720;
+  let candidates: Vec<(isize, isize)> = (0..100)
+    .flat_map(|noun| (0..100).map(move |verb| (noun, verb)))
+    .collect();
+  println!("day02 search, {} candidates", candidates.len());
+
+  let interpreter = time("interpreter", || {
+    candidates.par_iter().find_map_first(|(noun, verb)| {
+      let mut sequence = program.clone();
+      sequence[1] = *noun;
+      sequence[2] = *verb;
+      let halted = IntcodeComputer::new(sequence).start().as_halt().ok()?;
+      Some((*noun, *verb)).filter(|_| goal(halted.borrow_memory()))
+    })
+  });
+  let sites = [
+    search::SearchSite::new(1, 0..100),
+    search::SearchSite::new(2, 0..100),
+  ];
+  let batched = time("batched", || {
+    search::search(&program, &sites, &[], |run| goal(&run.memory))
+  });
+  summarize(interpreter, &[("batched", batched)]);
+}
+
+fn summarize(baseline: Duration, others: &[(&str, Duration)]) {
+  for (name, duration) in others {
+    println!(
+      "  {} is {:.1}x the interpreter's speed",
+      name,
+      baseline.as_secs_f64() / duration.as_secs_f64()
+    );
+  }
+}
+
+fn main() {
+  println!("{} rayon threads", rayon::current_num_threads());
+  amplifiers();
+  feedback_amplifiers();
+  noun_verb_search();
+}
@@ -0,0 +1,155 @@
+// Full-screen debugger for Intcode programs.
+//
+// Usage:
+//   intcode-debug [--relative] <program> [inputs...]
+//
+// `--relative` runs the program with `InstructionSet::relative`, as `intcode asm` programs
+// that use the bundled library need. The program can be in the text or the binary format. Inputs are given as numbers or
+// comma-separated lists (e.g. a day07 phase setting and signal: `intcode-debug amp.txt 4,0`),
+// and are asked for once they run out.
+
+mod session;
+mod terminal;
+mod view;
+
+use advent_of_code_2019::intcode::{self, InstructionSet, IntcodeSequence};
+use session::Session;
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use terminal::{Key, Terminal};
+
+const USAGE: &str = "usage: intcode-debug [--relative] <program> [inputs...]";
+
+fn parse_inputs(args: &[String]) -> Result<Vec<isize>, String> {
+  args
+    .iter()
+    .flat_map(|arg| arg.split(','))
+    .filter(|value| !value.trim().is_empty())
+    .map(|value| {
+      value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid input {:?}", value))
+    })
+    .collect()
+}
+
+fn load(path: &str) -> Result<IntcodeSequence, String> {
+  let bytes = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+  intcode::load_program(&bytes).map_err(|err| format!("{}: {}", path, err))
+}
+
+/// Moves the disassembly cursor by `delta` lines.
+fn move_cursor(session: &mut Session, delta: isize) {
+  let lines = view::listing(session);
+  if lines.is_empty() {
+    return;
+  }
+  let current = lines
+    .iter()
+    .rposition(|(address, _)| *address <= session.cursor)
+    .unwrap_or(0);
+  let next = (current as isize + delta).clamp(0, lines.len() as isize - 1);
+  session.cursor = lines[next as usize].0;
+}
+
+fn run(terminal: &Terminal, session: &mut Session) -> io::Result<()> {
+  let mut message = String::new();
+  let mut input_buffer: Option<String> = None;
+  loop {
+    if session.needs_input() && input_buffer.is_none() {
+      input_buffer = Some(String::new());
+    }
+    let prompt = match &input_buffer {
+      Some(buffer) => format!("input> {}", buffer),
+      None => message.clone(),
+    };
+    let (rows, columns) = terminal.size();
+    terminal.draw(&view::render(session, &prompt, rows, columns))?;
+
+    let key = terminal.read_key()?;
+    message.clear();
+    if let Some(buffer) = input_buffer.as_mut() {
+      match key {
+        Key::Char('q') => return Ok(()),
+        Key::Char(c) if c.is_ascii_digit() || (c == '-' && buffer.is_empty()) => buffer.push(c),
+        Key::Backspace => {
+          buffer.pop();
+        }
+        Key::Enter => match buffer.parse() {
+          Ok(input) => {
+            input_buffer = None;
+            session.provide_input(input);
+          }
+          Err(_) => buffer.clear(),
+        },
+        _ => (),
+      }
+      continue;
+    }
+
+    match key {
+      Key::Char('q') => return Ok(()),
+      Key::Char('s') | Key::Char('c') | Key::Char('o') if session.is_finished() => {
+        message = "The program has finished".into();
+      }
+      Key::Char('s') => session.step(),
+      Key::Char('c') => session.resume(),
+      Key::Char('o') => session.run_to_output(),
+      Key::Char('b') => session.toggle_breakpoint(session.cursor),
+      Key::Char('j') | Key::Down => move_cursor(session, 1),
+      Key::Char('k') | Key::Up => move_cursor(session, -1),
+      Key::Char('[') | Key::PageUp => {
+        session.memory_offset = session.memory_offset.saturating_sub(view::MEMORY_COLUMNS);
+      }
+      Key::Char(']') | Key::PageDown => {
+        if session.memory_offset + view::MEMORY_COLUMNS < session.memory_len() {
+          session.memory_offset += view::MEMORY_COLUMNS;
+        }
+      }
+      Key::Char('x') => session.hex = !session.hex,
+      _ => message = view::HELP.into(),
+    }
+  }
+}
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let (instruction_set, args) = match args.split_first() {
+    Some((flag, rest)) if flag == "--relative" => (InstructionSet::relative(), rest),
+    _ => (InstructionSet::standard(), args.as_slice()),
+  };
+  let (program, inputs) = match args.split_first() {
+    Some((path, inputs)) => (load(path), parse_inputs(inputs)),
+    None => (Err(USAGE.into()), Ok(vec![])),
+  };
+  let mut session = match (program, inputs) {
+    (Ok(program), Ok(inputs)) => Session::new(program, inputs, instruction_set),
+    (Err(message), _) | (_, Err(message)) => {
+      eprintln!("{}", message);
+      process::exit(2);
+    }
+  };
+
+  let result = Terminal::new().and_then(|terminal| run(&terminal, &mut session));
+  if let Err(err) = result {
+    eprintln!("intcode-debug: {}", err);
+    process::exit(1);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn moves_cursor_through_empty_programs() {
+    let mut session = Session::new(vec![], vec![], InstructionSet::standard());
+    move_cursor(&mut session, 1);
+    move_cursor(&mut session, -1);
+    assert_eq!(session.cursor, 0);
+    assert_eq!(view::render(&session, "", 24, 80).len(), 24);
+  }
+}
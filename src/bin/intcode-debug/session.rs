@@ -0,0 +1,244 @@
+// Drives a computer through the debugger, keeping it paused between commands.
+
+use advent_of_code_2019::intcode::debugger::Debugger;
+use advent_of_code_2019::intcode::{BreakReason, IntcodeComputer, IntcodeComputerState};
+use advent_of_code_2019::intcode::{InstructionSet, IntcodeSequence};
+use std::collections::VecDeque;
+
+/// Instructions without I/O before the watchdog starts looking for infinite loops, so
+/// that `continue` can't hang the terminal.
+const WATCHDOG_QUIET_STEPS: u64 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoEvent {
+  Input(isize),
+  Output(isize),
+}
+
+pub struct Session {
+  computer: Option<IntcodeComputer>,
+  inputs: VecDeque<isize>,
+  pub instruction_set: InstructionSet,
+  pub log: Vec<IoEvent>,
+  /// The address selected in the disassembly pane
+  pub cursor: usize,
+  pub memory_offset: usize,
+  pub hex: bool,
+}
+
+impl Session {
+  pub fn new(
+    program: IntcodeSequence,
+    inputs: Vec<isize>,
+    instruction_set: InstructionSet,
+  ) -> Session {
+    let mut debugger = Debugger::new();
+    debugger.pause();
+    let computer = IntcodeComputer::new(program)
+      .with_instruction_set(instruction_set.clone())
+      .with_debugger(debugger)
+      .with_watchdog(WATCHDOG_QUIET_STEPS)
+      .start();
+    Session {
+      computer: Some(computer),
+      inputs: inputs.into(),
+      instruction_set,
+      log: vec![],
+      cursor: 0,
+      memory_offset: 0,
+      hex: false,
+    }
+  }
+
+  pub fn computer(&self) -> &IntcodeComputer {
+    self.computer.as_ref().unwrap()
+  }
+
+  fn computer_mut(&mut self) -> &mut IntcodeComputer {
+    self.computer.as_mut().unwrap()
+  }
+
+  pub fn memory_len(&self) -> usize {
+    self.computer().borrow_memory().len()
+  }
+
+  pub fn needs_input(&self) -> bool {
+    matches!(self.computer(), IntcodeComputer::Input(_))
+  }
+
+  /// Whether the computer can run any further.
+  pub fn is_finished(&self) -> bool {
+    matches!(
+      self.computer(),
+      IntcodeComputer::Halt(_) | IntcodeComputer::Fault(_)
+    )
+  }
+
+  pub fn breakpoints(&self) -> Vec<usize> {
+    self
+      .computer()
+      .debugger()
+      .map_or(vec![], |debugger| debugger.breakpoints().collect())
+  }
+
+  pub fn toggle_breakpoint(&mut self, address: usize) {
+    let debugger = self.computer_mut().debugger_mut().unwrap();
+    if !debugger.remove_breakpoint(address) {
+      debugger.add_breakpoint(address);
+    }
+  }
+
+  /// Executes one instruction. Outputs are logged rather than stopping the step.
+  pub fn step(&mut self) {
+    self.run(|computer| match computer {
+      IntcodeComputer::Break(state) => state.step_over_output(),
+      computer => computer,
+    });
+    if let IntcodeComputer::Break(state) = self.computer() {
+      if let BreakReason::Step {
+        output: Some(output),
+      } = state.reason
+      {
+        self.log.push(IoEvent::Output(output));
+      }
+    }
+    self.settle(false);
+  }
+
+  /// Runs until a breakpoint, or until the program halts, faults or runs out of input.
+  pub fn resume(&mut self) {
+    self.run(resume);
+    self.settle(false);
+  }
+
+  /// Runs until just after the next output.
+  pub fn run_to_output(&mut self) {
+    self.run(resume);
+    self.settle(true);
+  }
+
+  pub fn provide_input(&mut self, input: isize) {
+    self.inputs.push_back(input);
+    self.settle(false);
+  }
+
+  fn run(&mut self, f: impl FnOnce(IntcodeComputer) -> IntcodeComputer) {
+    let computer = self.computer.take().unwrap();
+    self.computer = Some(f(computer));
+  }
+
+  /// Handles I/O until the computer breaks, finishes or needs input that hasn't been given.
+  fn settle(&mut self, stop_after_output: bool) {
+    loop {
+      match self.computer.take().unwrap() {
+        IntcodeComputer::Output(mut state) => {
+          self.log.push(IoEvent::Output(state.output));
+          if stop_after_output {
+            state.debugger_mut().unwrap().pause();
+          }
+          self.computer = Some(state.execute());
+        }
+        IntcodeComputer::Input(state) => match self.inputs.pop_front() {
+          Some(input) => {
+            self.log.push(IoEvent::Input(input));
+            self.computer = Some(state.execute(input));
+          }
+          None => {
+            self.computer = Some(IntcodeComputer::Input(state));
+            break;
+          }
+        },
+        computer => {
+          self.computer = Some(computer);
+          break;
+        }
+      }
+    }
+    self.cursor = self.computer().get_pointer();
+  }
+}
+
+fn resume(computer: IntcodeComputer) -> IntcodeComputer {
+  match computer {
+    IntcodeComputer::Break(state) => state.resume(),
+    computer => computer,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use advent_of_code_2019::intcode::parse;
+
+  // Outputs double each input until it gets a zero
+  const DOUBLER: &str = "3,15,1006,15,14,1002,15,2,16,4,16,1105,1,0,99,0,0";
+
+  #[test]
+  fn starts_paused() {
+    let session = Session::new(parse(DOUBLER), vec![], InstructionSet::standard());
+    assert!(matches!(session.computer(), IntcodeComputer::Break(_)));
+    assert_eq!(session.computer().get_pointer(), 0);
+  }
+
+  #[test]
+  fn steps() {
+    let mut session = Session::new(parse(DOUBLER), vec![4], InstructionSet::standard());
+    session.step();
+    assert_eq!(session.computer().get_pointer(), 2);
+    assert_eq!(session.log, vec![IoEvent::Input(4)]);
+    session.step();
+    session.step();
+    session.step();
+    assert_eq!(session.cursor, 11);
+    assert_eq!(session.log, vec![IoEvent::Input(4), IoEvent::Output(8)]);
+  }
+
+  #[test]
+  fn waits_for_input() {
+    let mut session = Session::new(parse(DOUBLER), vec![], InstructionSet::standard());
+    session.resume();
+    assert!(session.needs_input());
+    session.provide_input(5);
+    assert!(session.needs_input());
+    session.provide_input(0);
+    assert!(session.is_finished());
+    assert_eq!(
+      session.log,
+      vec![IoEvent::Input(5), IoEvent::Output(10), IoEvent::Input(0)]
+    );
+  }
+
+  #[test]
+  fn runs_to_output() {
+    let mut session = Session::new(parse(DOUBLER), vec![1, 2, 0], InstructionSet::standard());
+    session.run_to_output();
+    assert_eq!(session.log, vec![IoEvent::Input(1), IoEvent::Output(2)]);
+    assert_eq!(session.computer().get_pointer(), 11);
+  }
+
+  #[test]
+  fn relative_programs() {
+    // Outputs its input through the relative base
+    let program = parse("109,7,203,0,204,0,99,0");
+    let mut session = Session::new(program.clone(), vec![7], InstructionSet::relative());
+    session.resume();
+    assert!(session.is_finished());
+    assert_eq!(session.log, vec![IoEvent::Input(7), IoEvent::Output(7)]);
+    let mut session = Session::new(program, vec![7], InstructionSet::standard());
+    session.resume();
+    assert!(matches!(session.computer(), IntcodeComputer::Fault(_)));
+  }
+
+  #[test]
+  fn breakpoints() {
+    let mut session = Session::new(parse(DOUBLER), vec![1, 2, 0], InstructionSet::standard());
+    session.toggle_breakpoint(9);
+    session.resume();
+    assert_eq!(session.computer().get_pointer(), 9);
+    assert_eq!(session.breakpoints(), vec![9]);
+    session.toggle_breakpoint(9);
+    session.resume();
+    assert!(session.is_finished());
+    assert_eq!(session.log.len(), 5);
+  }
+}
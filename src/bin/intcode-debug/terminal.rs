@@ -0,0 +1,101 @@
+// Just enough terminal handling for a full-screen UI, using stty and ANSI escapes so
+// that it works in any Linux terminal.
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+  Char(char),
+  Up,
+  Down,
+  PageUp,
+  PageDown,
+  Enter,
+  Backspace,
+  Other,
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+  let output = Command::new("stty")
+    .args(args)
+    .stdin(Stdio::inherit())
+    .output()?;
+  if !output.status.success() {
+    return Err(io::Error::other(
+      String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    ));
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Puts the terminal in raw mode on the alternate screen, restoring it when dropped.
+pub struct Terminal {
+  saved_settings: String,
+}
+
+impl Terminal {
+  pub fn new() -> io::Result<Terminal> {
+    let saved_settings = stty(&["-g"])?;
+    stty(&["raw", "-echo"])?;
+    print!("\x1b[?1049h\x1b[?25l");
+    io::stdout().flush()?;
+    Ok(Terminal { saved_settings })
+  }
+
+  /// (rows, columns)
+  pub fn size(&self) -> (usize, usize) {
+    stty(&["size"])
+      .ok()
+      .and_then(|size| {
+        let mut parts = size.split_whitespace().map(|part| part.parse().ok());
+        Some((parts.next()??, parts.next()??))
+      })
+      .unwrap_or((24, 80))
+  }
+
+  pub fn draw(&self, lines: &[String]) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[H{}", lines.join("\r\n"))?;
+    stdout.flush()
+  }
+
+  pub fn read_key(&self) -> io::Result<Key> {
+    let mut stdin = io::stdin();
+    let mut byte = [0];
+    stdin.read_exact(&mut byte)?;
+    Ok(match byte[0] {
+      b'\r' | b'\n' => Key::Enter,
+      0x7f | 0x08 => Key::Backspace,
+      0x1b => {
+        let mut sequence = [0; 2];
+        stdin.read_exact(&mut sequence)?;
+        match &sequence {
+          b"[A" => Key::Up,
+          b"[B" => Key::Down,
+          b"[5" | b"[6" => {
+            stdin.read_exact(&mut byte)?;
+            if sequence[1] == b'5' {
+              Key::PageUp
+            } else {
+              Key::PageDown
+            }
+          }
+          _ => Key::Other,
+        }
+      }
+      // Ctrl-C, since raw mode doesn't turn it into a signal
+      0x03 => Key::Char('q'),
+      byte if byte.is_ascii() => Key::Char(char::from(byte)),
+      _ => Key::Other,
+    })
+  }
+}
+
+impl Drop for Terminal {
+  fn drop(&mut self) {
+    print!("\x1b[?25h\x1b[?1049l");
+    let _ = io::stdout().flush();
+    let _ = stty(&[&self.saved_settings]);
+  }
+}
@@ -0,0 +1,262 @@
+// Lays out the debugger's panes as plain lines of text.
+
+use crate::session::{IoEvent, Session};
+use advent_of_code_2019::intcode::disasm::{decode_at, CodeMap};
+use advent_of_code_2019::intcode::{IntcodeComputer, IntcodeComputerState};
+use std::iter;
+
+pub const MEMORY_COLUMNS: usize = 8;
+const DISASSEMBLY_WIDTH: usize = 36;
+
+pub const HELP: &str =
+  "s step  c continue  o run to output  b breakpoint  j/k move  [/] memory  x hex  q quit";
+
+/// Disassembly lines as (address, text). Code reachable from 0 and the instruction at
+/// the pointer are decoded; everything else is shown as data.
+pub fn listing(session: &Session) -> Vec<(usize, String)> {
+  let memory = session.computer().borrow_memory();
+  let pointer = session.computer().get_pointer();
+  let code_map = CodeMap::new(memory, &session.instruction_set);
+  let mut lines = vec![];
+  let mut address = 0;
+  while address < memory.len() {
+    let instruction = code_map.instructions.get(&address).cloned().or_else(|| {
+      if address == pointer {
+        decode_at(memory, address, &session.instruction_set)
+      } else {
+        None
+      }
+    });
+    match instruction {
+      Some(instruction) => {
+        lines.push((address, instruction.to_string()));
+        address += instruction.len();
+      }
+      None => {
+        lines.push((address, format!("data {}", memory[address])));
+        address += 1;
+      }
+    }
+  }
+  lines
+}
+
+fn status(session: &Session) -> String {
+  let computer = session.computer();
+  let state = match computer {
+    IntcodeComputer::Break(state) => state.reason.to_string(),
+    IntcodeComputer::Input(_) => "Waiting for input".into(),
+    IntcodeComputer::Output(state) => format!("Output {}", state.output),
+    IntcodeComputer::Halt(_) => "Halted".into(),
+    IntcodeComputer::Fault(state) => format!("Fault: {}", state.fault),
+  };
+  format!(
+    "pointer {} | {} | {}",
+    computer.get_pointer(),
+    state,
+    computer.stats()
+  )
+}
+
+fn disassembly(session: &Session, height: usize) -> Vec<String> {
+  let lines = listing(session);
+  let pointer = session.computer().get_pointer();
+  let breakpoints = session.breakpoints();
+  let selected = lines
+    .iter()
+    .rposition(|(address, _)| *address <= session.cursor)
+    .unwrap_or(0);
+  let start = selected
+    .saturating_sub(height / 2)
+    .min(lines.len().saturating_sub(height));
+  lines
+    .iter()
+    .skip(start)
+    .take(height)
+    .map(|(address, text)| {
+      let marker = match (*address == pointer, breakpoints.contains(address)) {
+        (true, true) => ">*",
+        (true, false) => "> ",
+        (false, true) => " *",
+        (false, false) => "  ",
+      };
+      let cursor = if *address == lines[selected].0 {
+        "|"
+      } else {
+        " "
+      };
+      format!("{}{}{:>6}: {}", marker, cursor, address, text)
+    })
+    .collect()
+}
+
+fn format_word(value: isize, hex: bool) -> String {
+  match (hex, value < 0) {
+    (false, _) => value.to_string(),
+    (true, false) => format!("{:x}", value),
+    (true, true) => format!("-{:x}", value.unsigned_abs()),
+  }
+}
+
+fn memory(session: &Session, height: usize) -> Vec<String> {
+  let memory = session.computer().borrow_memory();
+  let pointer = session.computer().get_pointer();
+  (0..height)
+    .map(|row| session.memory_offset + row * MEMORY_COLUMNS)
+    .take_while(|start| *start < memory.len())
+    .map(|start| {
+      let words: Vec<String> = memory[start..memory.len().min(start + MEMORY_COLUMNS)]
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+          let marker = if start + i == pointer { ">" } else { " " };
+          format!("{}{:>7}", marker, format_word(*value, session.hex))
+        })
+        .collect();
+      format!("{:>6}:{}", start, words.concat())
+    })
+    .collect()
+}
+
+fn io_log(session: &Session, height: usize) -> Vec<String> {
+  let skip = session.log.len().saturating_sub(height);
+  session.log[skip..]
+    .iter()
+    .map(|event| match event {
+      IoEvent::Input(value) => format!("in  {}", value),
+      IoEvent::Output(value) => format!("out {}", value),
+    })
+    .collect()
+}
+
+fn fit(line: &str, width: usize) -> String {
+  let line: String = line.chars().take(width).collect();
+  format!("{:<width$}", line, width = width)
+}
+
+/// Stacks titled panes, giving each the rows it asks for.
+fn column(panes: Vec<(&str, Vec<String>, usize)>, width: usize) -> Vec<String> {
+  panes
+    .into_iter()
+    .flat_map(|(title, lines, rows)| {
+      let padding = rows.saturating_sub(lines.len());
+      iter::once(fit(&format!("-- {} ", title), width))
+        .chain(
+          lines
+            .into_iter()
+            .take(rows)
+            .map(move |line| fit(&line, width)),
+        )
+        .chain(iter::repeat_n(" ".repeat(width), padding))
+    })
+    .collect()
+}
+
+/// The whole screen, as exactly `rows` lines of `columns` characters.
+pub fn render(session: &Session, prompt: &str, rows: usize, columns: usize) -> Vec<String> {
+  let body_rows = rows.saturating_sub(3);
+  let right_width = columns.saturating_sub(DISASSEMBLY_WIDTH + 1);
+  let memory_rows = (body_rows.saturating_sub(3) / 2).max(1);
+  let log_rows = body_rows.saturating_sub(memory_rows + 4);
+  let breakpoints = session
+    .breakpoints()
+    .iter()
+    .map(|address| address.to_string())
+    .collect::<Vec<_>>()
+    .join(", ");
+
+  let left = column(
+    vec![(
+      "disassembly",
+      disassembly(session, body_rows.saturating_sub(1)),
+      body_rows.saturating_sub(1),
+    )],
+    DISASSEMBLY_WIDTH,
+  );
+  let right = column(
+    vec![
+      ("memory", memory(session, memory_rows), memory_rows),
+      ("I/O", io_log(session, log_rows), log_rows),
+      ("breakpoints", vec![breakpoints], 1),
+    ],
+    right_width,
+  );
+
+  let mut screen = vec![fit(&status(session), columns)];
+  screen.extend(
+    left
+      .into_iter()
+      .zip(right)
+      .map(|(left, right)| format!("{} {}", left, right)),
+  );
+  screen.push(fit(HELP, columns));
+  screen.push(fit(prompt, columns));
+  screen.truncate(rows);
+  screen
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use advent_of_code_2019::intcode::{parse, InstructionSet};
+
+  fn session() -> Session {
+    Session::new(
+      parse("1,9,10,3,2,3,11,0,99,30,40,50"),
+      vec![],
+      InstructionSet::standard(),
+    )
+  }
+
+  #[test]
+  fn lists_code_and_data() {
+    let lines: Vec<_> = listing(&session()).into_iter().map(|(a, _)| a).collect();
+    assert_eq!(lines, vec![0, 4, 8, 9, 10, 11]);
+  }
+
+  #[test]
+  fn marks_pointer_and_breakpoints() {
+    let mut session = session();
+    session.toggle_breakpoint(4);
+    session.step();
+    assert_eq!(
+      disassembly(&session, 3),
+      vec![
+        "        0: add [9], [10], [70]",
+        ">*|     4: mul [3], [11], [0]",
+        "        8: halt",
+      ]
+    );
+  }
+
+  #[test]
+  fn centers_on_cursor() {
+    let mut session = session();
+    session.cursor = 10;
+    let lines = disassembly(&session, 3);
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].contains("10: data 40"));
+  }
+
+  #[test]
+  fn memory_rows() {
+    let mut session = session();
+    session.hex = true;
+    assert_eq!(
+      memory(&session, 5),
+      vec![
+        "     0:>      1       9       a       3       2       3       b       0",
+        "     8:      63      1e      28      32",
+      ]
+    );
+    assert_eq!(format_word(-31, true), "-1f");
+  }
+
+  #[test]
+  fn fills_screen() {
+    let screen = render(&session(), "", 24, 80);
+    assert_eq!(screen.len(), 24);
+    assert!(screen.iter().all(|line| line.chars().count() == 80));
+    assert!(screen[0].starts_with("pointer 0 | Step | 0 instructions"));
+  }
+}
@@ -0,0 +1,107 @@
+// Command-line tools for working with Intcode programs.
+//
+// Usage:
+//   intcode diff <old> <new>    List the words that differ between two programs or snapshots
+//   intcode disasm [--relative] <program>
+//                               Print a listing of a program
+//   intcode c <program>         Print a standalone C version of a program (see `transpile`)
+//   intcode optimize [--relative] <program>
+//                               Print an optimized program, with a report on stderr
+//   intcode asm <source>        Assemble a program (see `asm`), with includes looked up
+//                               next to the source
+//   intcode compile <source>    Compile a program in the tiny language (see `lang`)
+//
+// `--relative` reads programs with `InstructionSet::relative` instead of the standard set.
+// Files can be in the text or the binary format. `diff` exits with status 1 when the
+// memories differ, like diff(1).
+
+use advent_of_code_2019::intcode::{
+  self, disasm, lang, optimize, transpile, Assembler, InstructionSet, IntcodeSequence,
+};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str =
+  "usage: intcode diff <old> <new>\n       intcode disasm [--relative] <program>\n       intcode c <program>\n       intcode optimize [--relative] <program>\n       intcode asm <source>\n       intcode compile <source>";
+
+fn load(path: &str) -> Result<IntcodeSequence, String> {
+  let bytes = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+  intcode::load_program(&bytes).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn run(args: &[String]) -> Result<i32, String> {
+  let (instruction_set, args) = match args {
+    [command, flag, rest @ ..]
+      if flag == "--relative" && (command == "disasm" || command == "optimize") =>
+    {
+      let args = [std::slice::from_ref(command), rest].concat();
+      (InstructionSet::relative(), args)
+    }
+    _ => (InstructionSet::standard(), args.to_vec()),
+  };
+  match args.as_slice() {
+    [command, old, new] if command == "diff" => {
+      let diff = intcode::diff_memory(&load(old)?, &load(new)?);
+      println!("{}", diff);
+      Ok(if diff.is_empty() { 0 } else { 1 })
+    }
+    [command, program] if command == "disasm" => {
+      println!("{}", disasm::disassemble(&load(program)?, &instruction_set));
+      Ok(0)
+    }
+    [command, program] if command == "c" => {
+      print!("{}", transpile::transpile_to_c(&load(program)?));
+      Ok(0)
+    }
+    [command, program] if command == "optimize" => {
+      let optimized = optimize::optimize_with(&load(program)?, &instruction_set);
+      let words: Vec<String> = optimized
+        .sequence
+        .iter()
+        .map(|word| word.to_string())
+        .collect();
+      println!("{}", words.join(","));
+      eprintln!("{}", optimized.stats);
+      Ok(0)
+    }
+    [command, source] if command == "asm" => {
+      let text = fs::read_to_string(source).map_err(|err| format!("{}: {}", source, err))?;
+      let dir = Path::new(source)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+      let assembly = Assembler::new()
+        .with_loader(move |name| fs::read_to_string(dir.join(name)).ok())
+        .assemble(source, &text)
+        .map_err(|err| err.to_string())?;
+      let words: Vec<String> = assembly
+        .sequence
+        .iter()
+        .map(|word| word.to_string())
+        .collect();
+      println!("{}", words.join(","));
+      Ok(0)
+    }
+    [command, source] if command == "compile" => {
+      let text = fs::read_to_string(source).map_err(|err| format!("{}: {}", source, err))?;
+      let sequence = lang::compile(&text).map_err(|err| format!("{}:{}", source, err))?;
+      let words: Vec<String> = sequence.iter().map(|word| word.to_string()).collect();
+      println!("{}", words.join(","));
+      Ok(0)
+    }
+    _ => Err(USAGE.into()),
+  }
+}
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  match run(&args) {
+    Ok(status) => process::exit(status),
+    Err(message) => {
+      eprintln!("{}", message);
+      process::exit(2);
+    }
+  }
+}
@@ -10,20 +10,20 @@ pub fn brute_force_answer(
   verb_addr: usize,
   desired_output: usize,
 ) -> (usize, usize) {
-  let candidates: Vec<(usize, usize)> = (0..100)
-    .flat_map(|noun_candidate| (0..100).map(move |verb_candidate| (noun_candidate, verb_candidate)))
-    .collect();
+  let sites = vec![
+    intcode::search::SearchSite::new(noun_addr, 0..100),
+    intcode::search::SearchSite::new(verb_addr, 0..100),
+  ];
+  let desired_output: isize = desired_output.try_into().unwrap();
+  let result =
+    intcode::search::search(sequence, &sites, &[], |run| run.memory[0] == desired_output)
+      .expect("Invalid search sites")
+      .expect("No answer found");
 
-  candidates
-    .into_iter()
-    .find(move |&(noun_candidate, verb_candidate)| {
-      let mut candidate_sequence = sequence.clone();
-      candidate_sequence[noun_addr] = noun_candidate.try_into().unwrap();
-      candidate_sequence[verb_addr] = verb_candidate.try_into().unwrap();
-      let result = intcode::compat::compute_v02(&mut candidate_sequence);
-      result == desired_output
-    })
-    .expect("No answer found")
+  (
+    result.get(noun_addr).unwrap().try_into().unwrap(),
+    result.get(verb_addr).unwrap().try_into().unwrap(),
+  )
 }
 
 lazy_static! {
@@ -58,12 +58,24 @@ mod part_one {
     );
   }
 
+  #[test]
+  fn strict_test_cases() {
+    let mut sequence = intcode::parse("1,9,10,3,2,3,11,0,99,30,40,50");
+    assert_eq!(intcode::compat::compute_v02_strict(&mut sequence), Ok(3500));
+    let mut sequence = intcode::parse("1,1,1,4,99,5,6,0,99");
+    assert_eq!(intcode::compat::compute_v02_strict(&mut sequence), Ok(30));
+  }
+
   #[test]
   fn answer() {
-    let mut sequence = intcode::parse(&PUZZLE_INPUT);
-    sequence[1] = 12;
-    sequence[2] = 2;
-    assert_eq!(intcode::compat::compute_v02(&mut sequence), 5305097);
+    let mut sequence = intcode::parse_program(&PUZZLE_INPUT).unwrap();
+    let patches: intcode::PatchSet = "1=12,2=2".parse().unwrap();
+    patches.apply(&mut sequence).unwrap();
+    // Our solution shouldn't rely on anything introduced after Day 2
+    assert_eq!(
+      intcode::compat::compute_v02_strict(&mut sequence),
+      Ok(5305097)
+    );
   }
 }
 
@@ -74,7 +86,7 @@ mod part_two {
   #[test]
   fn part_two() {
     let start = time::Instant::now();
-    let sequence = intcode::parse(&PUZZLE_INPUT);
+    let sequence = intcode::parse_program(&PUZZLE_INPUT).unwrap();
     let (noun, verb) = brute_force_answer(&sequence, 1, 2, 19690720);
     let result = 100 * noun + verb;
     assert_eq!(result, 4925);
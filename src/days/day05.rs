@@ -8,7 +8,8 @@ lazy_static! {
 }
 
 pub fn get_diagnostic_code(input: isize) -> isize {
-  intcode::compat::parse_and_compute_v05(&PUZZLE_INPUT, Some(input)).unwrap()
+  let mut sequence = intcode::parse_program(&PUZZLE_INPUT).unwrap();
+  intcode::compat::compute_v05(&mut sequence, Some(input)).unwrap()
 }
 
 #[cfg(test)]
@@ -27,14 +28,14 @@ mod part_one {
   fn parameter_modes() {
     let mut code = intcode::parse("1002,4,3,4,33");
     intcode::compat::compute_v05(&mut code, None);
-    assert_eq!(code, vec![1002, 4, 3, 4, 99]);
+    crate::assert_memory_eq!(code, vec![1002, 4, 3, 4, 99]);
   }
 
   #[test]
   fn negative_integers() {
     let mut code = intcode::parse("1101,100,-1,4,0");
     intcode::compat::compute_v05(&mut code, None);
-    assert_eq!(code, vec![1101, 100, -1, 4, 99]);
+    crate::assert_memory_eq!(code, vec![1101, 100, -1, 4, 99]);
   }
 
   #[test]
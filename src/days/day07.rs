@@ -1,6 +1,7 @@
 // Day 7: Amplification Circuit
 
 use crate::logic::intcode;
+use crate::logic::intcode::batch::LaneStatus;
 
 use crate::prelude::*;
 use std::cell::Cell;
@@ -38,6 +39,35 @@ pub fn compute_thruster_signal(
   signal
 }
 
+/// Like `compute_thruster_signal` for every sequence of phase settings at once, running
+/// each amplifier's copies together in one batch.
+pub fn compute_thruster_signals_batched(
+  sequence: &intcode::IntcodeSequence,
+  phase_settings: &[PhaseSettingSequence],
+) -> Vec<isize> {
+  let mut signals = vec![0; phase_settings.len()];
+  for amplifier in 0..5 {
+    let mut batch = intcode::Batch::new(sequence, phase_settings.len());
+    for (lane, settings) in phase_settings.iter().enumerate() {
+      batch.push_input(lane, isize::from(settings[amplifier]));
+      batch.push_input(lane, signals[lane]);
+    }
+    batch.run();
+    for (lane, signal) in signals.iter_mut().enumerate() {
+      assert_eq!(
+        batch.status(lane),
+        &LaneStatus::Halted,
+        "Expected computer to halt"
+      );
+      *signal = *batch
+        .outputs(lane)
+        .first()
+        .expect("Expected computer to give output");
+    }
+  }
+  signals
+}
+
 fn get_all_phase_setting_combinations(
   options: &[u8],
 ) -> impl std::iter::Iterator<Item = PhaseSettingSequence> + '_ {
@@ -56,13 +86,25 @@ fn get_all_phase_setting_combinations(
   })
 }
 
+/// Splits `combinations` into one batch per thread, so batches are as wide as they can be
+/// while every thread still gets one. `benches/engines.rs` compares this with running
+/// every combination on its own computer.
+fn chunk_size(combinations: usize) -> usize {
+  combinations.div_ceil(rayon::current_num_threads()).max(1)
+}
+
 pub fn get_highest_phase_settings(
   sequence: &intcode::IntcodeSequence,
   phase_settings_options: &[u8],
 ) -> isize {
-  get_all_phase_setting_combinations(phase_settings_options)
-    .par_bridge()
-    .map(|phase_settings| compute_thruster_signal(&sequence, &phase_settings))
+  let combinations: Vec<_> = get_all_phase_setting_combinations(phase_settings_options).collect();
+  combinations
+    .par_chunks(chunk_size(combinations.len()))
+    .filter_map(|chunk| {
+      compute_thruster_signals_batched(sequence, chunk)
+        .into_iter()
+        .max()
+    })
     .max()
     .unwrap()
 }
@@ -108,13 +150,65 @@ pub fn compute_thruster_signal_feedback(
   }
 }
 
+/// Like `compute_thruster_signal_feedback` for every sequence of phase settings at once,
+/// with the amplifiers for all of them as lanes of one batch.
+pub fn compute_thruster_signals_feedback_batched(
+  sequence: &intcode::IntcodeSequence,
+  phase_settings: &[PhaseSettingSequence],
+) -> Vec<isize> {
+  let lane = |index: usize, amplifier: usize| index * 5 + amplifier;
+  let mut batch = intcode::Batch::new(sequence, phase_settings.len() * 5);
+  for (index, settings) in phase_settings.iter().enumerate() {
+    for (amplifier, phase_setting) in settings.iter().enumerate() {
+      batch.push_input(lane(index, amplifier), isize::from(*phase_setting));
+    }
+  }
+
+  batch.run();
+
+  let mut signals = vec![0; phase_settings.len()];
+  let mut running = vec![true; phase_settings.len()];
+  while running.contains(&true) {
+    for amplifier in 0..5 {
+      for index in 0..phase_settings.len() {
+        if !running[index] {
+          continue;
+        }
+        match batch.status(lane(index, amplifier)) {
+          LaneStatus::NeedsInput => batch.push_input(lane(index, amplifier), signals[index]),
+          LaneStatus::Halted => running[index] = false,
+          status => panic!(
+            "Unexpected computer state (expected to take input or halt): {:?}",
+            status
+          ),
+        }
+      }
+      batch.run();
+      for index in 0..phase_settings.len() {
+        if running[index] {
+          signals[index] = *batch
+            .take_outputs(lane(index, amplifier))
+            .first()
+            .expect("Expected computer to give output");
+        }
+      }
+    }
+  }
+  signals
+}
+
 pub fn get_highest_feedback_phase_settings(
   sequence: &intcode::IntcodeSequence,
   phase_settings_options: &[u8],
 ) -> isize {
-  get_all_phase_setting_combinations(phase_settings_options)
-    .par_bridge()
-    .map(|phase_settings| compute_thruster_signal_feedback(&sequence, &phase_settings))
+  let combinations: Vec<_> = get_all_phase_setting_combinations(phase_settings_options).collect();
+  combinations
+    .par_chunks(chunk_size(combinations.len()))
+    .filter_map(|chunk| {
+      compute_thruster_signals_feedback_batched(sequence, chunk)
+        .into_iter()
+        .max()
+    })
     .max()
     .unwrap()
 }
@@ -154,6 +248,26 @@ mod part_one {
     );
   }
 
+  #[test]
+  fn batched_matches() {
+    let sequence = intcode::parse(
+      "3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0",
+    );
+    let combinations: Vec<_> = get_all_phase_setting_combinations(&[0, 1, 2, 3, 4]).collect();
+    let expected: Vec<_> = combinations
+      .iter()
+      .map(|phase_settings| compute_thruster_signal(&sequence, phase_settings))
+      .collect();
+    assert_eq!(
+      compute_thruster_signals_batched(&sequence, &combinations),
+      expected
+    );
+    assert_eq!(
+      get_highest_phase_settings(&sequence, &[0, 1, 2, 3, 4]),
+      65210
+    );
+  }
+
   #[test]
   fn combinations() {
     let combinations = get_all_phase_setting_combinations(&(0..5).collect::<Vec<_>>()).count();
@@ -162,7 +276,7 @@ mod part_one {
 
   #[test]
   fn answer() {
-    let sequence = intcode::parse(&PUZZLE_INPUT);
+    let sequence = intcode::parse_program(&PUZZLE_INPUT).unwrap();
     let result = get_highest_phase_settings(&sequence, &(0..5).collect::<Vec<_>>());
     assert_eq!(result, 77500);
   }
@@ -193,9 +307,30 @@ mod part_two {
       18216
     );
   }
+
+  #[test]
+  fn batched_matches() {
+    let sequence = intcode::parse(
+      "3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,-5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10",
+    );
+    let combinations: Vec<_> = get_all_phase_setting_combinations(&[5, 6, 7, 8, 9]).collect();
+    let expected: Vec<_> = combinations
+      .iter()
+      .map(|phase_settings| compute_thruster_signal_feedback(&sequence, phase_settings))
+      .collect();
+    assert_eq!(
+      compute_thruster_signals_feedback_batched(&sequence, &combinations),
+      expected
+    );
+    assert_eq!(
+      get_highest_feedback_phase_settings(&sequence, &[5, 6, 7, 8, 9]),
+      18216
+    );
+  }
+
   #[test]
   fn answer() {
-    let sequence = intcode::parse(&PUZZLE_INPUT);
+    let sequence = intcode::parse_program(&PUZZLE_INPUT).unwrap();
     let result = get_highest_feedback_phase_settings(&sequence, &(5..10).collect::<Vec<_>>());
     assert_eq!(result, 22476942);
   }
@@ -1,54 +1,127 @@
-use std::convert::{TryFrom, TryInto};
+use std::convert::TryInto;
+use std::ops::Range;
+use std::sync::Arc;
 
+pub mod asm;
+pub mod batch;
+pub mod bigint;
+pub mod binary;
 pub mod compat;
+pub mod compiled;
+pub mod debugger;
+pub mod devices;
+pub mod diff;
+pub mod disasm;
+pub mod fuzz;
+pub mod instructions;
+pub mod lang;
+pub mod observer;
+pub mod optimize;
+pub mod outputs;
+pub mod parser;
+pub mod patch;
+pub mod profiles;
+pub mod protection;
+pub mod replay;
+pub mod search;
+pub mod stats;
+pub mod transpile;
+mod watchdog;
+pub mod word;
 
-pub type IntcodeSequence = Vec<isize>;
+pub use asm::{assemble, AsmError, Assembler, Assembly};
+pub use batch::Batch;
+pub use bigint::BigInt;
+pub use compiled::CompiledProgram;
+pub use debugger::{BreakReason, Debugger};
+pub use devices::Device;
+pub use diff::{diff_memory, MemoryDiff};
+pub use instructions::{InstructionContext, InstructionSet, OpcodeDef, OverflowPolicy};
+pub use lang::CompileError;
+pub use observer::ComputeObserver;
+pub use parser::{parse_program, ParseError};
+pub use patch::{Patch, PatchSet};
+pub use profiles::Profile;
+pub use protection::{MemoryProtection, Permission, Protection};
+pub use replay::{Replay, ReplayConfig};
+pub use stats::RunStats;
+pub use word::{parse_words, Word};
 
-trait IntcodeIndexable {
-  fn index(&self, i: isize) -> isize;
-  fn index_mut(&mut self, i: isize) -> &mut isize;
-  fn set(&mut self, i: isize, val: isize);
-}
-impl IntcodeIndexable for IntcodeSequence {
-  fn index(&self, i: isize) -> isize {
-    self[usize::try_from(i).unwrap()]
-  }
-  fn index_mut(&mut self, i: isize) -> &mut isize {
-    &mut self[usize::try_from(i).unwrap()]
-  }
-  fn set(&mut self, i: isize, val: isize) {
-    self[usize::try_from(i).unwrap()] = val;
-  }
-}
-trait Unsign {
-  fn expect_unsigned(self) -> usize;
-}
-impl Unsign for isize {
-  fn expect_unsigned(self) -> usize {
-    self.try_into().unwrap()
-  }
-}
+pub type IntcodeSequence = Vec<isize>;
+/// Memory made of any kind of `Word`. Most programs just use `IntcodeSequence`.
+pub type IntcodeMemory<W = isize> = Vec<W>;
 
-pub trait IntcodeComputerState {
-  fn get_internal_state(&self) -> &IntcodeComputerInternalState;
-  fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState;
-  fn borrow_memory(&self) -> &IntcodeSequence {
+pub trait IntcodeComputerState<W: Word = isize> {
+  fn get_internal_state(&self) -> &IntcodeComputerInternalState<W>;
+  fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState<W>;
+  fn borrow_memory(&self) -> &IntcodeMemory<W> {
     &self.get_internal_state().sequence
   }
-  fn borrow_memory_mut(&mut self) -> &mut IntcodeSequence {
+  fn borrow_memory_mut(&mut self) -> &mut IntcodeMemory<W> {
     &mut self.get_internal_state_mut().sequence
   }
   fn get_pointer(&self) -> usize {
     self.get_internal_state().pointer
   }
+  /// Where relative mode parameters count from. Starts at 0 and only moves with `arb`.
+  fn get_relative_base(&self) -> isize {
+    self.get_internal_state().relative_base
+  }
+  fn stats(&self) -> &RunStats {
+    &self.get_internal_state().stats
+  }
+  /// Everything recorded so far, if recording was turned on before starting.
+  fn recording(&self) -> Option<&Replay> {
+    self
+      .get_internal_state()
+      .recorder
+      .as_ref()
+      .map(|recorder| &recorder.replay)
+  }
+  fn debugger(&self) -> Option<&Debugger<W>> {
+    self.get_internal_state().debugger.as_deref()
+  }
+  fn debugger_mut(&mut self) -> Option<&mut Debugger<W>> {
+    self.get_internal_state_mut().debugger.as_deref_mut()
+  }
+  /// The attached observer, if there is one and it's a `T`.
+  fn observer<T: ComputeObserver<W>>(&self) -> Option<&T> {
+    self
+      .get_internal_state()
+      .observer
+      .as_ref()
+      .and_then(|observer| observer.downcast_ref())
+  }
+  fn observer_mut<T: ComputeObserver<W>>(&mut self) -> Option<&mut T> {
+    self
+      .get_internal_state_mut()
+      .observer
+      .as_mut()
+      .and_then(|observer| observer.downcast_mut())
+  }
+  /// The first attached device that's a `T`, if there is one.
+  fn device<T: Device<W>>(&self) -> Option<&T> {
+    self
+      .get_internal_state()
+      .devices
+      .as_ref()
+      .and_then(|devices| devices.downcast_ref())
+  }
+  fn device_mut<T: Device<W>>(&mut self) -> Option<&mut T> {
+    self
+      .get_internal_state_mut()
+      .devices
+      .as_mut()
+      .and_then(|devices| devices.downcast_mut())
+  }
 }
 macro_rules! impl_intcode_computer_state {
   (  $x:ident ) => {
-    impl IntcodeComputerState for $x {
-      fn get_internal_state(&self) -> &IntcodeComputerInternalState {
+    impl<W: Word> IntcodeComputerState<W> for $x<W> {
+      fn get_internal_state(&self) -> &IntcodeComputerInternalState<W> {
         &self.internal_state
       }
-      fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState {
+      fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState<W> {
         &mut self.internal_state
       }
     }
@@ -56,14 +129,82 @@ macro_rules! impl_intcode_computer_state {
 }
 
 #[derive(Debug)]
-pub struct IntcodeComputerInternalState {
-  sequence: IntcodeSequence,
+pub struct IntcodeComputerInternalState<W: Word = isize> {
+  sequence: IntcodeMemory<W>,
   pointer: usize,
+  relative_base: isize,
+  instruction_set: Arc<InstructionSet<W>>,
+  overflow_policy: OverflowPolicy,
+  stats: RunStats,
+  /// Only ever attached to `isize` computers, see `IntcodeComputerStart::with_recording`
+  recorder: Option<Box<replay::Recorder>>,
+  watchdog: Option<Box<watchdog::Watchdog<W>>>,
+  protection: Option<Box<MemoryProtection>>,
+  devices: Option<Box<devices::DeviceMap<W>>>,
+  observer: Option<observer::Attached<W>>,
+  debugger: Option<Box<Debugger<W>>>,
 }
-impl IntcodeComputerInternalState {
-  fn compute(mut self) -> IntcodeComputer {
+impl<W: Word> IntcodeComputerInternalState<W> {
+  fn compute(mut self) -> IntcodeComputer<W> {
     loop {
-      let result = compute_instruction(&mut self.sequence, self.pointer);
+      if let Some(debugger) = self.debugger.as_mut() {
+        let reason = debugger.check(
+          &self.sequence,
+          self.pointer,
+          self.relative_base,
+          &self.instruction_set,
+        );
+        if let Some(reason) = reason {
+          return IntcodeComputer::Break(IntcodeComputerBreakState {
+            internal_state: self,
+            reason,
+          });
+        }
+      }
+      if let Some(watchdog) = self.watchdog.as_mut() {
+        if let Err(fault) = watchdog.check(&self.sequence, self.pointer, self.relative_base) {
+          return self.fault(fault);
+        }
+      }
+      if let Some(protection) = self.protection.as_ref() {
+        let checked = protection.check(
+          &self.sequence,
+          self.pointer,
+          self.relative_base,
+          &self.instruction_set,
+        );
+        if let Err(fault) = checked {
+          return self.fault(fault);
+        }
+      }
+      let devices = self.devices.as_deref_mut();
+      let result = match self.observer.as_mut() {
+        None => self.instruction_set.compute_instruction_mapped(
+          &mut self.sequence,
+          self.pointer,
+          &mut self.relative_base,
+          self.overflow_policy,
+          None,
+          devices,
+        ),
+        Some(observer) => {
+          observer.0.before_instruction(self.pointer, &self.sequence);
+          self.instruction_set.compute_instruction_mapped(
+            &mut self.sequence,
+            self.pointer,
+            &mut self.relative_base,
+            self.overflow_policy,
+            Some(observer.0.as_mut()),
+            devices,
+          )
+        }
+      };
+      match result {
+        // Input isn't done until IntcodeComputerInputState::execute
+        ProgramState::WaitForInput | ProgramState::Fault(_) => (),
+        _ => self.executed_instruction(),
+      }
+      self.stats.peak_memory_size = self.stats.peak_memory_size.max(self.sequence.len());
       match result {
         ProgramState::Continue(new_position) => {
           self.pointer = new_position;
@@ -73,289 +214,659 @@ impl IntcodeComputerInternalState {
           output,
         } => {
           self.pointer = new_position;
+          self.stats.outputs_emitted += 1;
+          if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_output(recordable(&output));
+          }
+          if let Some(observer) = self.observer.as_mut() {
+            observer.0.on_output(output.clone());
+          }
+          if let Some(debugger) = self.debugger.as_mut() {
+            if debugger.take_output_step() {
+              return IntcodeComputer::Break(IntcodeComputerBreakState {
+                internal_state: self,
+                reason: BreakReason::Step {
+                  output: Some(output),
+                },
+              });
+            }
+          }
+          if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.reset();
+          }
           return IntcodeComputer::Output(IntcodeComputerOutputState {
             internal_state: self,
             output,
           });
         }
         ProgramState::WaitForInput => {
+          if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.reset();
+          }
           return IntcodeComputer::Input(IntcodeComputerInputState {
             internal_state: self,
           });
         }
         ProgramState::Halt => {
+          self.stats.halt_address = Some(self.pointer);
+          if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_halt();
+          }
+          if let Some(observer) = self.observer.as_mut() {
+            observer.0.on_halt(self.pointer);
+          }
           return IntcodeComputer::Halt(IntcodeComputerHaltState {
             internal_state: self,
-          })
+          });
+        }
+        ProgramState::Fault(fault) => {
+          return self.fault(fault);
+        }
+      }
+    }
+  }
+
+  fn executed_instruction(&mut self) {
+    self.stats.instructions_executed += 1;
+    if let Some(devices) = self.devices.as_mut() {
+      devices.tick();
+      // Devices can change without the program doing any I/O, so it isn't looping
+      if devices.take_accessed() {
+        if let Some(watchdog) = self.watchdog.as_mut() {
+          watchdog.reset();
         }
       }
     }
   }
+
+  fn fault(mut self, fault: Fault) -> IntcodeComputer<W> {
+    if let Some(recorder) = self.recorder.as_mut() {
+      recorder.record_fault(&fault);
+    }
+    if let Some(observer) = self.observer.as_mut() {
+      observer.0.on_fault(&fault);
+    }
+    IntcodeComputer::Fault(IntcodeComputerFaultState {
+      internal_state: self,
+      fault,
+    })
+  }
 }
-impl IntcodeComputerState for IntcodeComputerInternalState {
-  fn get_internal_state(&self) -> &IntcodeComputerInternalState {
+impl<W: Word> IntcodeComputerState<W> for IntcodeComputerInternalState<W> {
+  fn get_internal_state(&self) -> &IntcodeComputerInternalState<W> {
     self
   }
-  fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState {
+  fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState<W> {
     self
   }
 }
 
+/// The recorder only sees `isize` words, since only `isize` computers can record.
+fn recordable<W: Word>(word: &W) -> isize {
+  word
+    .to_isize()
+    .expect("Only isize computers can be recorded")
+}
+
 #[derive(Debug)]
-pub enum IntcodeComputer {
-  Input(IntcodeComputerInputState),
-  Output(IntcodeComputerOutputState),
-  Halt(IntcodeComputerHaltState),
+pub enum IntcodeComputer<W: Word = isize> {
+  Input(IntcodeComputerInputState<W>),
+  Output(IntcodeComputerOutputState<W>),
+  Halt(IntcodeComputerHaltState<W>),
+  Fault(IntcodeComputerFaultState<W>),
+  Break(IntcodeComputerBreakState<W>),
 }
 #[derive(Debug)]
-pub struct WrongTypeError(IntcodeComputer);
+pub struct WrongTypeError<W: Word = isize>(Box<IntcodeComputer<W>>);
 
 impl IntcodeComputer {
   pub fn new(sequence: IntcodeSequence) -> IntcodeComputerStart {
+    IntcodeComputer::from_words(sequence)
+  }
+
+  /// Starts a computer with `patches` applied over `sequence`.
+  pub fn new_patched(
+    mut sequence: IntcodeSequence,
+    patches: &PatchSet,
+  ) -> Result<IntcodeComputerStart, patch::PatchError> {
+    patches.apply(&mut sequence)?;
+    Ok(Self::new(sequence))
+  }
+
+  pub fn parse(str: &str) -> IntcodeComputerStart {
+    let sequence = parse(str);
+    Self::new(sequence)
+  }
+}
+
+impl<W: Word> IntcodeComputer<W> {
+  /// Like `new`, for memory made of another kind of word, e.g. `i128` or `BigInt`
+  /// for programs whose values don't fit in an `isize`.
+  pub fn from_words(sequence: IntcodeMemory<W>) -> IntcodeComputerStart<W> {
     IntcodeComputerStart {
       internal_state: IntcodeComputerInternalState {
         sequence,
         pointer: 0,
+        relative_base: 0,
+        instruction_set: W::standard_instruction_set(),
+        overflow_policy: OverflowPolicy::default(),
+        stats: RunStats::default(),
+        recorder: None,
+        watchdog: None,
+        protection: None,
+        devices: None,
+        observer: None,
+        debugger: None,
       },
     }
   }
 
-  pub fn parse(str: &str) -> IntcodeComputerStart {
-    let sequence = parse(str);
-    Self::new(sequence)
-  }
-
-  pub fn as_input(self) -> Result<IntcodeComputerInputState, WrongTypeError> {
+  pub fn as_input(self) -> Result<IntcodeComputerInputState<W>, WrongTypeError<W>> {
     if let IntcodeComputer::Input(state) = self {
       Ok(state)
     } else {
-      Err(WrongTypeError(self))
+      Err(WrongTypeError(Box::new(self)))
     }
   }
 
-  pub fn as_output(self) -> Result<IntcodeComputerOutputState, WrongTypeError> {
+  pub fn as_output(self) -> Result<IntcodeComputerOutputState<W>, WrongTypeError<W>> {
     if let IntcodeComputer::Output(state) = self {
       Ok(state)
     } else {
-      Err(WrongTypeError(self))
+      Err(WrongTypeError(Box::new(self)))
     }
   }
 
-  pub fn as_halt(self) -> Result<IntcodeComputerHaltState, WrongTypeError> {
+  pub fn as_halt(self) -> Result<IntcodeComputerHaltState<W>, WrongTypeError<W>> {
     if let IntcodeComputer::Halt(state) = self {
       Ok(state)
     } else {
-      Err(WrongTypeError(self))
+      Err(WrongTypeError(Box::new(self)))
+    }
+  }
+
+  pub fn as_fault(self) -> Result<IntcodeComputerFaultState<W>, WrongTypeError<W>> {
+    if let IntcodeComputer::Fault(state) = self {
+      Ok(state)
+    } else {
+      Err(WrongTypeError(Box::new(self)))
+    }
+  }
+
+  pub fn as_break(self) -> Result<IntcodeComputerBreakState<W>, WrongTypeError<W>> {
+    if let IntcodeComputer::Break(state) = self {
+      Ok(state)
+    } else {
+      Err(WrongTypeError(Box::new(self)))
     }
   }
 }
-impl IntcodeComputerState for IntcodeComputer {
-  fn get_internal_state(&self) -> &IntcodeComputerInternalState {
+impl<W: Word> IntcodeComputerState<W> for IntcodeComputer<W> {
+  fn get_internal_state(&self) -> &IntcodeComputerInternalState<W> {
     match self {
       IntcodeComputer::Input(state) => state.get_internal_state(),
       IntcodeComputer::Output(state) => state.get_internal_state(),
       IntcodeComputer::Halt(state) => state.get_internal_state(),
+      IntcodeComputer::Fault(state) => state.get_internal_state(),
+      IntcodeComputer::Break(state) => state.get_internal_state(),
     }
   }
-  fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState {
+  fn get_internal_state_mut(&mut self) -> &mut IntcodeComputerInternalState<W> {
     match self {
       IntcodeComputer::Input(state) => state.get_internal_state_mut(),
       IntcodeComputer::Output(state) => state.get_internal_state_mut(),
       IntcodeComputer::Halt(state) => state.get_internal_state_mut(),
+      IntcodeComputer::Fault(state) => state.get_internal_state_mut(),
+      IntcodeComputer::Break(state) => state.get_internal_state_mut(),
     }
   }
 }
 
 #[derive(Debug)]
-pub struct IntcodeComputerStart {
-  internal_state: IntcodeComputerInternalState,
+pub struct IntcodeComputerStart<W: Word = isize> {
+  internal_state: IntcodeComputerInternalState<W>,
 }
 impl_intcode_computer_state!(IntcodeComputerStart);
 
 impl IntcodeComputerStart {
-  pub fn start(self) -> IntcodeComputer {
+  /// Records every input (and optionally a checksum of every output) so the session
+  /// can be replayed later. See `IntcodeComputerState::recording`.
+  pub fn with_recording(mut self, output_checksums: bool) -> Self {
+    let recorder = replay::Recorder::new(&self.internal_state.sequence, output_checksums);
+    self.internal_state.recorder = Some(Box::new(recorder));
+    self
+  }
+}
+
+impl<W: Word> IntcodeComputerStart<W> {
+  pub fn with_instruction_set(mut self, instruction_set: InstructionSet<W>) -> Self {
+    self.internal_state.instruction_set = Arc::new(instruction_set);
+    self
+  }
+
+  /// Sets what `add` and `mul` do on overflow. Faulting is the default.
+  pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+    self.internal_state.overflow_policy = policy;
+    self
+  }
+
+  /// Restricts the computer to `profile`, faulting on any opcode or parameter mode outside it.
+  pub fn with_strict_profile(self, profile: Profile) -> Self {
+    self.with_instruction_set(profile.instruction_set_for_words())
+  }
+
+  /// Faults with `Fault::InfiniteLoop` if the program is certain to loop forever.
+  /// The (slower) check only kicks in after `quiet_steps` instructions without input or output.
+  pub fn with_watchdog(mut self, quiet_steps: u64) -> Self {
+    self.internal_state.watchdog = Some(Box::new(watchdog::Watchdog::new(quiet_steps)));
+    self
+  }
+
+  /// Faults with `Fault::ProtectionViolation` if the program uses any address in `range`
+  /// in a way `protection` doesn't allow. Regions can overlap, in which case an access
+  /// has to be allowed by all of them.
+  pub fn with_protection(mut self, range: Range<usize>, protection: Protection) -> Self {
+    self
+      .internal_state
+      .protection
+      .get_or_insert_with(Default::default)
+      .protect(range, protection);
+    self
+  }
+
+  /// Maps `device` over the addresses in `range`, which must be inside memory. See
+  /// `IntcodeComputerState::device` for getting it back.
+  pub fn with_device<T: Device<W>>(mut self, range: Range<usize>, device: T) -> Self {
+    assert!(
+      range.end <= self.internal_state.sequence.len(),
+      "Device at {:?} is outside memory",
+      range
+    );
+    self
+      .internal_state
+      .devices
+      .get_or_insert_with(|| Box::new(devices::DeviceMap::new()))
+      .attach(range, Box::new(device));
+    self
+  }
+
+  /// Stops in `IntcodeComputer::Break` whenever one of `debugger`'s breakpoints or
+  /// watchpoints is hit.
+  pub fn with_debugger(mut self, debugger: Debugger<W>) -> Self {
+    self.internal_state.debugger = Some(Box::new(debugger));
+    self
+  }
+
+  /// Calls `observer` as the program runs. See `IntcodeComputerState::observer` for
+  /// getting it back.
+  pub fn with_observer<T: ComputeObserver<W>>(mut self, observer: T) -> Self {
+    self.internal_state.observer = Some(observer::Attached(Box::new(observer)));
+    self
+  }
+
+  pub fn start(mut self) -> IntcodeComputer<W> {
+    // The setup is only known once the builder is done with it
+    if let Some(mut recorder) = self.internal_state.recorder.take() {
+      recorder.replay.config = replay::ReplayConfig::of(&self.internal_state);
+      self.internal_state.recorder = Some(recorder);
+    }
     self.internal_state.compute()
   }
 }
 
 #[derive(Debug)]
-pub struct IntcodeComputerInputState {
-  internal_state: IntcodeComputerInternalState,
+pub struct IntcodeComputerInputState<W: Word = isize> {
+  internal_state: IntcodeComputerInternalState<W>,
 }
 impl_intcode_computer_state!(IntcodeComputerInputState);
 
-impl IntcodeComputerInputState {
-  pub fn execute(mut self, input: isize) -> IntcodeComputer {
+impl<W: Word> IntcodeComputerInputState<W> {
+  pub fn execute(mut self, input: W) -> IntcodeComputer<W> {
+    let state = &mut self.internal_state;
+    let def = state
+      .instruction_set
+      .decode(&state.sequence, state.pointer)
+      .expect("Input instruction was already decoded");
+    let destination_param = *def
+      .writes
+      .first()
+      .expect("Input opcode must write somewhere");
     let instruction = parse_instruction(
-      &self.internal_state.sequence,
-      self.internal_state.pointer,
-      1,
+      &state.sequence,
+      state.pointer,
+      state.relative_base,
+      def.arity,
     );
-    let destination_addr = instruction.raw_parameters[0];
-    self.internal_state.sequence.set(destination_addr, input);
+    let destination_addr = instruction.address(usize::from(destination_param));
+    if let Some(recorder) = self.internal_state.recorder.as_mut() {
+      recorder.record_input(recordable(&input));
+    }
+    if let Some(observer) = self.internal_state.observer.as_mut() {
+      observer.0.on_input(input.clone());
+    }
+    let input = match self.internal_state.devices.as_mut() {
+      Some(devices) => devices.write(destination_addr, input),
+      None => Some(input),
+    };
+    if let Some(input) = input {
+      let memory = &mut self.internal_state.sequence;
+      let old = std::mem::replace(&mut memory[destination_addr], input);
+      if let Some(observer) = self.internal_state.observer.as_mut() {
+        observer
+          .0
+          .on_memory_write(destination_addr, old, memory[destination_addr].clone());
+      }
+    }
+    self.internal_state.executed_instruction();
+    self.internal_state.stats.inputs_consumed += 1;
     self.internal_state.pointer = instruction.next_pointer;
     self.internal_state.compute()
   }
 }
 
 #[derive(Debug)]
-pub struct IntcodeComputerOutputState {
-  internal_state: IntcodeComputerInternalState,
-  pub output: isize,
+pub struct IntcodeComputerOutputState<W: Word = isize> {
+  internal_state: IntcodeComputerInternalState<W>,
+  pub output: W,
 }
 impl_intcode_computer_state!(IntcodeComputerOutputState);
 
-impl IntcodeComputerOutputState {
-  pub fn execute(self) -> IntcodeComputer {
+impl<W: Word> IntcodeComputerOutputState<W> {
+  pub fn execute(self) -> IntcodeComputer<W> {
     self.internal_state.compute()
   }
 }
 
 #[derive(Debug)]
-pub struct IntcodeComputerHaltState {
-  internal_state: IntcodeComputerInternalState,
+pub struct IntcodeComputerHaltState<W: Word = isize> {
+  internal_state: IntcodeComputerInternalState<W>,
 }
 impl_intcode_computer_state!(IntcodeComputerHaltState);
 
+#[derive(Debug)]
+pub struct IntcodeComputerFaultState<W: Word = isize> {
+  internal_state: IntcodeComputerInternalState<W>,
+  pub fault: Fault,
+}
+impl_intcode_computer_state!(IntcodeComputerFaultState);
+
+impl<W: Word> IntcodeComputerFaultState<W> {
+  /// Carries on from an overflow under `OverflowPolicy::Promote`, with every word widened
+  /// to `V`, e.g. `i128` or `BigInt`. The pointer, relative base, stats and memory
+  /// protection carry over, and a watchdog starts watching again from scratch.
+  ///
+  /// Gives the state back for any other fault or policy, and when something attached to
+  /// the computer only works on `W`: custom opcodes, a recording, devices, an observer or
+  /// a debugger.
+  pub fn promote<V: Word>(self) -> Result<IntcodeComputer<V>, Box<Self>> {
+    let state = &self.internal_state;
+    let promotable = state.overflow_policy == OverflowPolicy::Promote
+      && matches!(self.fault, Fault::Overflow { .. })
+      && state.recorder.is_none()
+      && state.devices.is_none()
+      && state.observer.is_none()
+      && state.debugger.is_none();
+    let instruction_set = state.instruction_set.for_words();
+    let sequence: Option<IntcodeMemory<V>> = state.sequence.iter().map(Word::convert).collect();
+    let (instruction_set, sequence) = match (instruction_set, sequence) {
+      (Some(instruction_set), Some(sequence)) if promotable => (instruction_set, sequence),
+      _ => return Err(Box::new(self)),
+    };
+    let state = self.internal_state;
+    let promoted = IntcodeComputerInternalState {
+      sequence,
+      pointer: state.pointer,
+      relative_base: state.relative_base,
+      instruction_set: Arc::new(instruction_set),
+      overflow_policy: state.overflow_policy,
+      stats: state.stats,
+      recorder: None,
+      watchdog: state
+        .watchdog
+        .map(|watchdog| Box::new(watchdog.restarted())),
+      protection: state.protection,
+      devices: None,
+      observer: None,
+      debugger: None,
+    };
+    Ok(promoted.compute())
+  }
+}
+
+#[derive(Debug)]
+pub struct IntcodeComputerBreakState<W: Word = isize> {
+  internal_state: IntcodeComputerInternalState<W>,
+  pub reason: BreakReason<W>,
+}
+impl_intcode_computer_state!(IntcodeComputerBreakState);
+
+impl<W: Word> IntcodeComputerBreakState<W> {
+  fn attached_debugger(&mut self) -> &mut Debugger<W> {
+    self
+      .internal_state
+      .debugger
+      .as_mut()
+      .expect("Only computers with a debugger can break")
+  }
+
+  /// Carries on until the next break, I/O, halt or fault.
+  pub fn resume(mut self) -> IntcodeComputer<W> {
+    self.attached_debugger().resume();
+    self.internal_state.compute()
+  }
+
+  /// Executes one instruction, then breaks again. Input and output still stop the
+  /// computer as usual, and the step finishes once they're done.
+  pub fn step(mut self) -> IntcodeComputer<W> {
+    self.attached_debugger().step(false);
+    self.internal_state.compute()
+  }
+
+  /// Like `step`, but an output instruction breaks straight after it, with the value in
+  /// `BreakReason::Step`, instead of stopping in `IntcodeComputer::Output`.
+  pub fn step_over_output(mut self) -> IntcodeComputer<W> {
+    self.attached_debugger().step(true);
+    self.internal_state.compute()
+  }
+
+  pub fn registers(&self) -> debugger::Registers {
+    debugger::Registers {
+      pointer: self.internal_state.pointer,
+      relative_base: self.internal_state.relative_base,
+    }
+  }
+
+  /// The instruction that will run next, if there's a valid one at the pointer.
+  pub fn current_instruction(&self) -> Option<disasm::DecodedInstruction<W>> {
+    disasm::decode_at(
+      &self.internal_state.sequence,
+      self.internal_state.pointer,
+      &self.internal_state.instruction_set,
+    )
+  }
+}
+
+/// Something the program did that the computer refuses to execute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+  UnknownOpcode {
+    opcode: isize,
+    pointer: usize,
+  },
+  UnsupportedParameterMode {
+    mode: u8,
+    pointer: usize,
+  },
+  /// The program repeated an earlier state without doing any I/O in between.
+  /// `start` and `end` are the lowest and highest instruction addresses in the loop.
+  InfiniteLoop {
+    start: usize,
+    end: usize,
+  },
+  /// An `add` or `mul` result didn't fit in a word, under `OverflowPolicy::Fault` or
+  /// `OverflowPolicy::Promote`.
+  Overflow {
+    pointer: usize,
+  },
+  /// The instruction at the pointer is too big to be an opcode, which can only happen
+  /// with words wider than an `isize`.
+  OversizedInstruction {
+    pointer: usize,
+  },
+  /// The instruction at `pointer` would use `address` in a way its protection doesn't
+  /// allow. See `IntcodeComputerStart::with_protection`.
+  ProtectionViolation {
+    address: usize,
+    permission: Permission,
+    pointer: usize,
+  },
+}
+
+impl std::fmt::Display for Fault {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Fault::UnknownOpcode { opcode, pointer } => write!(
+        f,
+        "Unrecognized opcode {} at instruction pointer {}",
+        opcode, pointer
+      ),
+      Fault::UnsupportedParameterMode { mode, pointer } => write!(
+        f,
+        "Unrecognized parameter mode {} at instruction pointer {}",
+        mode, pointer
+      ),
+      Fault::InfiniteLoop { start, end } => write!(
+        f,
+        "Infinite loop between instruction pointers {} and {}",
+        start, end
+      ),
+      Fault::Overflow { pointer } => {
+        write!(f, "Arithmetic overflow at instruction pointer {}", pointer)
+      }
+      Fault::OversizedInstruction { pointer } => {
+        write!(
+          f,
+          "Oversized instruction at instruction pointer {}",
+          pointer
+        )
+      }
+      Fault::ProtectionViolation {
+        address,
+        permission,
+        pointer,
+      } => write!(
+        f,
+        "No {} permission for address {} at instruction pointer {}",
+        permission, address, pointer
+      ),
+    }
+  }
+}
+
 #[derive(Debug, PartialEq)]
-pub enum ProgramState {
+pub enum ProgramState<W = isize> {
   Continue(usize),
   WaitForInput,
-  OutputAndContinue { pointer: usize, output: isize },
+  OutputAndContinue { pointer: usize, output: W },
   Halt,
+  Fault(Fault),
 }
 
+/// Like `parse_program`, but panics if the program is invalid.
 pub fn parse(input: &str) -> IntcodeSequence {
-  input.split(",").map(|num| num.parse().unwrap()).collect()
+  parse_program(input).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Reads a program or memory snapshot in either the text or the binary format.
+pub fn load_program(bytes: &[u8]) -> Result<IntcodeSequence, String> {
+  if binary::is_binary(bytes) {
+    binary::decode(bytes).map_err(|err| format!("Invalid binary program: {}", err))
+  } else {
+    let text = std::str::from_utf8(bytes).map_err(|err| err.to_string())?;
+    parse_program(text).map_err(|err| err.to_string())
+  }
 }
 
 pub fn compute_instruction(
   sequence: &mut IntcodeSequence,
   instruction_pointer: usize,
 ) -> ProgramState {
-  let instruction = sequence[instruction_pointer];
-  let opcode = instruction % 100;
-  match opcode {
-    1 => {
-      // Add
-      let instruction = parse_instruction(&sequence, instruction_pointer, 3);
-      let a = instruction.parameters[0];
-      let b = instruction.parameters[1];
-      sequence.set(instruction.raw_parameters[2], a + b);
-      ProgramState::Continue(instruction.next_pointer)
-    }
-    2 => {
-      // Multiply
-      let instruction = parse_instruction(&sequence, instruction_pointer, 3);
-      let a = instruction.parameters[0];
-      let b = instruction.parameters[1];
-      sequence.set(instruction.raw_parameters[2], a * b);
-      ProgramState::Continue(instruction.next_pointer)
-    }
-    3 => {
-      // Input
-      ProgramState::WaitForInput
-    }
-    4 => {
-      // Output
-      let instruction = parse_instruction(&sequence, instruction_pointer, 1);
-      ProgramState::OutputAndContinue {
-        pointer: instruction.next_pointer,
-        output: instruction.parameters[0],
-      }
-    }
-    5 => {
-      // Jump If True
-      let instruction = parse_instruction(&sequence, instruction_pointer, 2);
-      if instruction.parameters[0] != 0 {
-        ProgramState::Continue(instruction.parameters[1].expect_unsigned())
-      } else {
-        ProgramState::Continue(instruction.next_pointer)
-      }
-    }
-    6 => {
-      // Jump If False
-      let instruction = parse_instruction(&sequence, instruction_pointer, 2);
-      if instruction.parameters[0] == 0 {
-        ProgramState::Continue(instruction.parameters[1].expect_unsigned())
-      } else {
-        ProgramState::Continue(instruction.next_pointer)
-      }
-    }
-    7 => {
-      // Less Than
-      let instruction = parse_instruction(&sequence, instruction_pointer, 3);
-      sequence.set(
-        instruction.raw_parameters[2],
-        if instruction.parameters[0] < instruction.parameters[1] {
-          1
-        } else {
-          0
-        },
-      );
-      ProgramState::Continue(instruction.next_pointer)
-    }
-    8 => {
-      // Equals
-      let instruction = parse_instruction(&sequence, instruction_pointer, 3);
-      sequence.set(
-        instruction.raw_parameters[2],
-        if instruction.parameters[0] == instruction.parameters[1] {
-          1
-        } else {
-          0
-        },
-      );
-      ProgramState::Continue(instruction.next_pointer)
-    }
-    99 => ProgramState::Halt,
-    _ => {
-      panic!(format!(
-        "Unrecognized opcode {} at instruction pointer {}",
-        opcode, instruction_pointer
-      ));
-    }
-  }
+  instructions::STANDARD_INSTRUCTION_SET.compute_instruction(sequence, instruction_pointer)
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct InstructionParameters {
-  raw_parameters: Vec<isize>,
+struct InstructionParameters<W = isize> {
+  raw_parameters: Vec<W>,
   parameter_modes: Vec<u8>,
-  parameters: Vec<isize>,
+  parameters: Vec<W>,
   next_pointer: usize,
+  relative_base: isize,
 }
 
-fn parse_instruction(
-  sequence: &IntcodeSequence,
-  pointer: usize,
-  num_params: u8,
-) -> InstructionParameters {
-  let raw_parameters: Vec<isize> = (1..num_params + 1)
-    .map(|i| sequence[pointer + usize::from(i)])
-    .collect();
+impl<W: Word> InstructionParameters<W> {
+  /// The address a position or relative mode parameter refers to.
+  fn address(&self, i: usize) -> usize {
+    parameter_address(
+      &self.raw_parameters[i],
+      self.parameter_modes[i],
+      self.relative_base,
+    )
+  }
+}
+
+/// The address a parameter refers to in position mode (0) or relative mode (2).
+fn parameter_address<W: Word>(raw_param: &W, mode: u8, relative_base: isize) -> usize {
+  match mode {
+    2 => W::from_isize(relative_base)
+      .checked_add(raw_param)
+      .unwrap_or_else(|| panic!("Address {}+{} overflowed", relative_base, raw_param))
+      .expect_address(),
+    _ => raw_param.expect_address(),
+  }
+}
 
-  let instruction: usize = sequence[pointer].try_into().unwrap();
-  let parameter_modes: Vec<u8> = (0..num_params)
+fn parameter_modes(instruction: isize, num_params: u8) -> Vec<u8> {
+  let instruction: usize = instruction.try_into().unwrap();
+  (0..num_params)
     .map(|i| {
       let place = 10usize.pow((i + 2).into());
       ((instruction / place) % 10).try_into().unwrap()
     })
+    .collect()
+}
+
+fn parse_instruction<W: Word>(
+  sequence: &IntcodeMemory<W>,
+  pointer: usize,
+  relative_base: isize,
+  num_params: u8,
+) -> InstructionParameters<W> {
+  let raw_parameters: Vec<W> = (1..num_params + 1)
+    .map(|i| sequence[pointer + usize::from(i)].clone())
     .collect();
 
+  let instruction = sequence[pointer]
+    .to_isize()
+    .expect("Instruction was already decoded");
+  let parameter_modes = parameter_modes(instruction, num_params);
+
   let parameters = (0..num_params)
     .map(|i| {
       let i: usize = i.into();
-      let raw_param = raw_parameters[i];
+      let raw_param = &raw_parameters[i];
       let param_mode = parameter_modes[i];
       match param_mode {
         0 => {
           // Position Mode
-          sequence.index(raw_param)
+          sequence[raw_param.expect_address()].clone()
         }
         1 => {
           // Immediate Mode
-          raw_param
+          raw_param.clone()
+        }
+        2 => {
+          // Relative Mode
+          sequence[parameter_address(raw_param, 2, relative_base)].clone()
         }
         _ => panic!(
           "Unrecognized parameter mode {} at instruction pointer {}",
@@ -370,6 +881,7 @@ fn parse_instruction(
     parameter_modes,
     parameters,
     next_pointer: pointer + 1 + usize::from(num_params),
+    relative_base,
   }
 }
 
@@ -380,13 +892,35 @@ mod part_one {
   fn test_parse_instruction() {
     let sequence = parse("1002,4,3,4,33");
     assert_eq!(
-      parse_instruction(&sequence, 0, 3),
+      parse_instruction(&sequence, 0, 0, 3),
       InstructionParameters {
         raw_parameters: vec![4, 3, 4],
         parameter_modes: vec![0, 1, 0],
         parameters: vec![33, 3, 33],
-        next_pointer: 4
+        next_pointer: 4,
+        relative_base: 0
       }
     );
   }
+
+  #[test]
+  fn test_parse_relative_instruction() {
+    let sequence = parse("22201,1,-2,-3,33");
+    let instruction = parse_instruction(&sequence, 0, 3, 3);
+    assert_eq!(instruction.parameters, vec![33, 1, 22201]);
+    assert_eq!(instruction.address(2), 0);
+  }
+}
+
+#[cfg(test)]
+mod loading {
+  use super::*;
+
+  #[test]
+  fn loads_either_format() {
+    let sequence = parse("1,0,0,0,99");
+    assert_eq!(load_program(b"1,0,0,0,99\n"), Ok(sequence.clone()));
+    assert_eq!(load_program(&binary::encode(&sequence)), Ok(sequence));
+    assert!(load_program(b"1,,2").is_err());
+  }
 }
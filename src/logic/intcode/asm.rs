@@ -0,0 +1,1165 @@
+// A text assembler for Intcode, with macros, include files and a small bundled library.
+//
+// Instructions are written the way `disasm` lists them, with `#` for immediate mode,
+// `[...]` for position mode and `rb[...]` for relative mode:
+//
+//       in [n]                ; comments run from `;` to the end of the line
+//   loop:
+//       mul [n], #2, [n]
+//       lt [n], #100, [small]
+//       jnz [small], #loop
+//       out [n]
+//       halt
+//   n:     data 0
+//   small: space 1
+//
+// `data` emits words and `space` emits that many zeros. Operands are sums and differences
+// of numbers, labels, character literals like `'a'` and parenthesised expressions.
+//
+// `.macro name param, ...` up to `.endm` defines a macro, invoked like an instruction.
+// Parameters are used as `%param` in the body, and labels starting with `@` are local to
+// each expansion. Macros can be used before they're defined.
+//
+// `.include "name"` includes a file once, from those added with `Assembler::with_file`,
+// then the bundled library, then the loader. The file's labels and macros are namespaced
+// by its name without any directories or extension, so `util.asm`'s `loop` is `util.loop`
+// everywhere else. Names inside a file look in its own namespace first. Included code goes
+// where the `.include` is, so libraries with subroutines are best included after the main
+// program's last instruction.
+//
+// The bundled library is in `asm/`: `stack`, `call`, `memory`, `math` and `print`. Programs
+// that use it need `InstructionSet::relative`.
+
+use super::{InstructionSet, IntcodeSequence};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::Path;
+
+const LIBRARY: &[(&str, &str)] = &[
+  ("stack", include_str!("asm/stack.asm")),
+  ("call", include_str!("asm/call.asm")),
+  ("memory", include_str!("asm/memory.asm")),
+  ("math", include_str!("asm/math.asm")),
+  ("print", include_str!("asm/print.asm")),
+];
+
+/// How deep macros can expand inside other macros, to catch macros that expand forever.
+const MAX_MACRO_DEPTH: usize = 64;
+
+/// Why a program failed to assemble, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+  pub file: String,
+  /// One-based
+  pub line: usize,
+  pub message: String,
+}
+
+impl fmt::Display for AsmError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}: {}", self.file, self.line, self.message)
+  }
+}
+
+impl std::error::Error for AsmError {}
+
+/// An assembled program, with the address of every label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+  pub sequence: IntcodeSequence,
+  /// Fully qualified, e.g. `print.number`
+  pub labels: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Location {
+  file: String,
+  line: usize,
+}
+
+impl Location {
+  fn error(&self, message: impl Into<String>) -> AsmError {
+    AsmError {
+      file: self.file.clone(),
+      line: self.line,
+      message: message.into(),
+    }
+  }
+}
+
+/// A name as written, along with the namespace of the file it was written in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Name {
+  text: String,
+  namespace: String,
+}
+
+impl Name {
+  fn qualified(&self) -> String {
+    qualify(&self.namespace, &self.text)
+  }
+
+  /// The names this could refer to, in the order to try them.
+  fn candidates(&self) -> Vec<String> {
+    if self.namespace.is_empty() {
+      vec![self.text.clone()]
+    } else {
+      vec![self.qualified(), self.text.clone()]
+    }
+  }
+}
+
+fn qualify(namespace: &str, name: &str) -> String {
+  if namespace.is_empty() {
+    name.to_string()
+  } else {
+    format!("{}.{}", namespace, name)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+  Name(Name),
+  /// Wider than a word, so that `-` can be read together with the magnitude of the most
+  /// negative word, which doesn't fit in one
+  Number(i128),
+  /// A macro parameter, `%name`
+  Param(String),
+  Str(String),
+  Punct(char),
+}
+
+fn is_name_start(c: char) -> bool {
+  c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '@'
+}
+
+fn is_name_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@'
+}
+
+fn tokenize(text: &str, namespace: &str, location: &Location) -> Result<Vec<Token>, AsmError> {
+  let mut tokens = vec![];
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      ';' => break,
+      c if c.is_whitespace() => (),
+      c if c.is_ascii_digit() => {
+        let mut digits = c.to_string();
+        while let Some(c) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+          digits.push(*c);
+          chars.next();
+        }
+        let number = digits
+          .parse()
+          .map_err(|_| location.error(format!("Invalid number {:?}", digits)))?;
+        tokens.push(Token::Number(number));
+      }
+      c if is_name_start(c) || c == '%' => {
+        let mut name = if c == '%' {
+          String::new()
+        } else {
+          c.to_string()
+        };
+        while let Some(c) = chars.peek().filter(|c| is_name_char(**c)) {
+          name.push(*c);
+          chars.next();
+        }
+        tokens.push(if c == '%' {
+          Token::Param(name)
+        } else {
+          Token::Name(Name {
+            text: name,
+            namespace: namespace.to_string(),
+          })
+        });
+      }
+      '\'' => {
+        let c = match chars.next() {
+          Some('\\') => match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(c @ '\\') | Some(c @ '\'') => c,
+            _ => return Err(location.error("Invalid escape in character literal")),
+          },
+          Some(c) => c,
+          None => return Err(location.error("Unterminated character literal")),
+        };
+        if chars.next() != Some('\'') {
+          return Err(location.error("Unterminated character literal"));
+        }
+        tokens.push(Token::Number(c as i128));
+      }
+      '"' => {
+        let text: String = chars.by_ref().take_while(|c| *c != '"').collect();
+        tokens.push(Token::Str(text));
+      }
+      '#' | '[' | ']' | '(' | ')' | '+' | '-' | ',' | ':' => tokens.push(Token::Punct(c)),
+      c => return Err(location.error(format!("Unexpected character {:?}", c))),
+    }
+  }
+  Ok(tokens)
+}
+
+/// One line of source, split into its parts.
+#[derive(Debug, Clone)]
+struct Line {
+  location: Location,
+  label: Option<Name>,
+  mnemonic: Option<Name>,
+  operands: Vec<Vec<Token>>,
+}
+
+fn parse_line(text: &str, namespace: &str, location: Location) -> Result<Line, AsmError> {
+  let mut tokens = tokenize(text, namespace, &location)?.into_iter().peekable();
+  let mut label = None;
+  let mut mnemonic = None;
+  if let Some(Token::Name(name)) = tokens.next_if(|token| matches!(token, Token::Name(_))) {
+    if tokens.next_if_eq(&Token::Punct(':')).is_some() {
+      label = Some(name);
+      if let Some(Token::Name(name)) = tokens.next_if(|token| matches!(token, Token::Name(_))) {
+        mnemonic = Some(name);
+      }
+    } else {
+      mnemonic = Some(name);
+    }
+  }
+  let rest: Vec<Token> = tokens.collect();
+  if mnemonic.is_none() && !rest.is_empty() {
+    return Err(location.error("Expected an instruction"));
+  }
+  let operands = if rest.is_empty() {
+    vec![]
+  } else {
+    rest
+      .split(|token| *token == Token::Punct(','))
+      .map(|operand| operand.to_vec())
+      .collect()
+  };
+  if operands.iter().any(|operand| operand.is_empty()) {
+    return Err(location.error("Empty operand"));
+  }
+  Ok(Line {
+    location,
+    label,
+    mnemonic,
+    operands,
+  })
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+  params: Vec<String>,
+  body: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+  Number(isize),
+  Label(Name),
+  Neg(Box<Expr>),
+  Add(Box<Expr>, Box<Expr>),
+  Sub(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+  fn evaluate(
+    &self,
+    labels: &BTreeMap<String, usize>,
+    location: &Location,
+  ) -> Result<isize, AsmError> {
+    let overflow = || location.error("Expression overflows");
+    match self {
+      Expr::Number(number) => Ok(*number),
+      Expr::Label(name) => name
+        .candidates()
+        .iter()
+        .find_map(|candidate| labels.get(candidate))
+        .map(|address| *address as isize)
+        .ok_or_else(|| location.error(format!("Undefined label {}", name.text))),
+      Expr::Neg(expr) => expr
+        .evaluate(labels, location)?
+        .checked_neg()
+        .ok_or_else(overflow),
+      Expr::Add(a, b) => a
+        .evaluate(labels, location)?
+        .checked_add(b.evaluate(labels, location)?)
+        .ok_or_else(overflow),
+      Expr::Sub(a, b) => a
+        .evaluate(labels, location)?
+        .checked_sub(b.evaluate(labels, location)?)
+        .ok_or_else(overflow),
+    }
+  }
+}
+
+/// Parses a whole expression from `tokens`.
+fn parse_expr(tokens: &[Token], location: &Location) -> Result<Expr, AsmError> {
+  let mut position = 0;
+  let expr = parse_sum(tokens, &mut position, location)?;
+  match tokens.get(position) {
+    None => Ok(expr),
+    Some(token) => Err(location.error(format!("Unexpected {}", describe(token)))),
+  }
+}
+
+fn parse_sum(
+  tokens: &[Token],
+  position: &mut usize,
+  location: &Location,
+) -> Result<Expr, AsmError> {
+  let mut expr = parse_term(tokens, position, location)?;
+  while let Some(Token::Punct(op @ '+')) | Some(Token::Punct(op @ '-')) = tokens.get(*position) {
+    *position += 1;
+    let rhs = Box::new(parse_term(tokens, position, location)?);
+    expr = if *op == '+' {
+      Expr::Add(Box::new(expr), rhs)
+    } else {
+      Expr::Sub(Box::new(expr), rhs)
+    };
+  }
+  Ok(expr)
+}
+
+fn parse_term(
+  tokens: &[Token],
+  position: &mut usize,
+  location: &Location,
+) -> Result<Expr, AsmError> {
+  let token = tokens
+    .get(*position)
+    .ok_or_else(|| location.error("Expected a value"))?;
+  *position += 1;
+  match token {
+    Token::Number(number) => number_expr(*number, location),
+    Token::Name(name) => Ok(Expr::Label(name.clone())),
+    Token::Punct('-') => match tokens.get(*position) {
+      Some(Token::Number(number)) => {
+        *position += 1;
+        number_expr(-number, location)
+      }
+      _ => Ok(Expr::Neg(Box::new(parse_term(tokens, position, location)?))),
+    },
+    Token::Punct('(') => {
+      let expr = parse_sum(tokens, position, location)?;
+      if tokens.get(*position) != Some(&Token::Punct(')')) {
+        return Err(location.error("Expected )"));
+      }
+      *position += 1;
+      Ok(expr)
+    }
+    token => Err(location.error(format!("Unexpected {}", describe(token)))),
+  }
+}
+
+fn number_expr(number: i128, location: &Location) -> Result<Expr, AsmError> {
+  isize::try_from(number)
+    .map(Expr::Number)
+    .map_err(|_| location.error(format!("{} doesn't fit in a word", number)))
+}
+
+fn describe(token: &Token) -> String {
+  match token {
+    Token::Name(name) => name.text.clone(),
+    Token::Number(number) => number.to_string(),
+    Token::Param(param) => format!("%{} outside a macro", param),
+    Token::Str(text) => format!("{:?}", text),
+    Token::Punct(c) => c.to_string(),
+  }
+}
+
+/// Parses an instruction operand into its parameter mode and value.
+fn parse_operand(tokens: &[Token], location: &Location) -> Result<(u8, Expr), AsmError> {
+  let bracketed = |tokens: &[Token]| match tokens.last() {
+    Some(Token::Punct(']')) => parse_expr(&tokens[..tokens.len() - 1], location),
+    _ => Err(location.error("Expected ]")),
+  };
+  match tokens {
+    [Token::Punct('#'), rest @ ..] => Ok((1, parse_expr(rest, location)?)),
+    [Token::Punct('['), rest @ ..] => Ok((0, bracketed(rest)?)),
+    [Token::Name(name), Token::Punct('['), rest @ ..] if name.text == "rb" => {
+      Ok((2, bracketed(rest)?))
+    }
+    _ => Err(location.error("Expected an operand: #value, [address] or rb[offset]")),
+  }
+}
+
+/// Something that takes up memory, after macros are expanded.
+#[derive(Debug)]
+enum Item {
+  Label(String, Location),
+  Instruction {
+    opcode: u8,
+    operands: Vec<(u8, Expr)>,
+    location: Location,
+  },
+  Data(Vec<Expr>, Location),
+}
+
+type Loader = dyn Fn(&str) -> Option<String>;
+
+/// Assembles programs, with extra files to include. See the top of this file for the
+/// syntax.
+pub struct Assembler {
+  files: HashMap<String, String>,
+  loader: Option<Box<Loader>>,
+  instruction_set: InstructionSet,
+}
+
+impl Default for Assembler {
+  fn default() -> Self {
+    Assembler::new()
+  }
+}
+
+impl Assembler {
+  pub fn new() -> Assembler {
+    Assembler {
+      files: HashMap::new(),
+      loader: None,
+      instruction_set: InstructionSet::relative(),
+    }
+  }
+
+  /// Makes `source` available to `.include` as `name`.
+  pub fn with_file(mut self, name: &str, source: &str) -> Self {
+    self.files.insert(name.to_string(), source.to_string());
+    self
+  }
+
+  /// Looks up included files that weren't added with `with_file` and aren't part of the
+  /// bundled library, e.g. from disk.
+  pub fn with_loader<F>(mut self, loader: F) -> Self
+  where
+    F: Fn(&str) -> Option<String> + 'static,
+  {
+    self.loader = Some(Box::new(loader));
+    self
+  }
+
+  /// Assembles `source`, naming it `file` in errors.
+  pub fn assemble(&self, file: &str, source: &str) -> Result<Assembly, AsmError> {
+    let mut reader = Reader {
+      assembler: self,
+      included: HashSet::new(),
+      macros: HashMap::new(),
+      lines: vec![],
+    };
+    reader.read(file, "", source)?;
+    let mut expander = Expander {
+      assembler: self,
+      macros: &reader.macros,
+      expansions: 0,
+      items: vec![],
+    };
+    for line in reader.lines.iter() {
+      expander.expand(line, 0)?;
+    }
+    self.lay_out(expander.items)
+  }
+
+  fn load(&self, name: &str) -> Option<String> {
+    self
+      .files
+      .get(name)
+      .cloned()
+      .or_else(|| {
+        LIBRARY
+          .iter()
+          .find(|(library, _)| *library == name)
+          .map(|(_, source)| source.to_string())
+      })
+      .or_else(|| self.loader.as_ref().and_then(|loader| loader(name)))
+  }
+
+  fn is_instruction(&self, name: &str) -> bool {
+    self.instruction_set.iter().any(|def| def.name == name)
+  }
+
+  /// Gives every label an address, then emits every word.
+  fn lay_out(&self, items: Vec<Item>) -> Result<Assembly, AsmError> {
+    let mut labels = BTreeMap::new();
+    let mut address = 0;
+    for item in items.iter() {
+      match item {
+        Item::Label(name, location) => {
+          if labels.insert(name.clone(), address).is_some() {
+            return Err(location.error(format!("Label {} is already defined", name)));
+          }
+        }
+        Item::Instruction { operands, .. } => address += 1 + operands.len(),
+        Item::Data(values, _) => address += values.len(),
+      }
+    }
+
+    let mut sequence = vec![];
+    for item in items.iter() {
+      match item {
+        Item::Label(..) => (),
+        Item::Instruction {
+          opcode,
+          operands,
+          location,
+        } => {
+          let modes = operands
+            .iter()
+            .enumerate()
+            .map(|(i, (mode, _))| isize::from(*mode) * 10isize.pow(i as u32 + 2))
+            .sum::<isize>();
+          sequence.push(modes + isize::from(*opcode));
+          for (_, value) in operands.iter() {
+            sequence.push(value.evaluate(&labels, location)?);
+          }
+        }
+        Item::Data(values, location) => {
+          for value in values.iter() {
+            sequence.push(value.evaluate(&labels, location)?);
+          }
+        }
+      }
+    }
+    Ok(Assembly { sequence, labels })
+  }
+}
+
+/// Assembles `source` with only the bundled library to include.
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+  Assembler::new().assemble("<input>", source)
+}
+
+/// Reads files into lines, following includes and collecting macros.
+struct Reader<'a> {
+  assembler: &'a Assembler,
+  included: HashSet<String>,
+  macros: HashMap<String, Macro>,
+  lines: Vec<Line>,
+}
+
+impl Reader<'_> {
+  fn read(&mut self, file: &str, namespace: &str, source: &str) -> Result<(), AsmError> {
+    let mut open_macro: Option<(String, Location, Macro)> = None;
+    for (index, text) in source.lines().enumerate() {
+      let location = Location {
+        file: file.to_string(),
+        line: index + 1,
+      };
+      let line = parse_line(text, namespace, location.clone())?;
+      let directive = line.mnemonic.as_ref().map(|name| name.text.as_str());
+
+      if open_macro.is_some() {
+        match directive {
+          Some(".endm") => {
+            let (name, _, definition) = open_macro.take().unwrap();
+            self.macros.insert(name, definition);
+          }
+          Some(".macro") => {
+            let name = &open_macro.unwrap().0;
+            return Err(location.error(format!("Macro {} isn't closed with .endm", name)));
+          }
+          _ => open_macro.as_mut().unwrap().2.body.push(line),
+        }
+        continue;
+      }
+
+      match directive {
+        Some(".macro") => open_macro = Some(self.start_macro(&line, namespace)?),
+        Some(".endm") => return Err(location.error(".endm without .macro")),
+        Some(".include") => {
+          let name = match line.operands.as_slice() {
+            [operand] => match operand.as_slice() {
+              [Token::Str(name)] => name.clone(),
+              _ => return Err(location.error(".include needs a quoted file name")),
+            },
+            _ => return Err(location.error(".include needs a quoted file name")),
+          };
+          if line.label.is_some() {
+            self.lines.push(Line {
+              mnemonic: None,
+              operands: vec![],
+              ..line
+            });
+          }
+          self.include(&name, &location)?;
+        }
+        Some(directive) if directive.starts_with('.') => {
+          return Err(location.error(format!("Unknown directive {}", directive)))
+        }
+        _ => {
+          if line.label.is_some() || line.mnemonic.is_some() {
+            self.lines.push(line);
+          }
+        }
+      }
+    }
+    match open_macro {
+      Some((name, location, _)) => {
+        Err(location.error(format!("Macro {} isn't closed with .endm", name)))
+      }
+      None => Ok(()),
+    }
+  }
+
+  fn start_macro(
+    &self,
+    line: &Line,
+    namespace: &str,
+  ) -> Result<(String, Location, Macro), AsmError> {
+    let location = &line.location;
+    let mut operands = line.operands.iter();
+    let expected = "Expected .macro name followed by its parameters";
+    // The name and the first parameter aren't separated by a comma
+    let (name, first) = match operands.next().map(|operand| operand.as_slice()) {
+      Some([Token::Name(name)]) => (name, None),
+      Some([Token::Name(name), Token::Name(param)]) => (name, Some(param)),
+      _ => return Err(location.error(expected)),
+    };
+    let mut params: Vec<String> = first.iter().map(|param| param.text.clone()).collect();
+    for operand in operands {
+      match operand.as_slice() {
+        [Token::Name(param)] => params.push(param.text.clone()),
+        _ => return Err(location.error(expected)),
+      }
+    }
+    if self.assembler.is_instruction(&name.text) || ["data", "space"].contains(&&*name.text) {
+      return Err(location.error(format!("Macro {} would hide an instruction", name.text)));
+    }
+    let qualified = qualify(namespace, &name.text);
+    if self.macros.contains_key(&qualified) {
+      return Err(location.error(format!("Macro {} is already defined", qualified)));
+    }
+    Ok((
+      qualified,
+      location.clone(),
+      Macro {
+        params,
+        body: vec![],
+      },
+    ))
+  }
+
+  fn include(&mut self, name: &str, location: &Location) -> Result<(), AsmError> {
+    if !self.included.insert(name.to_string()) {
+      return Ok(());
+    }
+    let source = self
+      .assembler
+      .load(name)
+      .ok_or_else(|| location.error(format!("Can't find included file {:?}", name)))?;
+    let namespace = Path::new(name)
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .unwrap_or(name)
+      .to_string();
+    self.read(name, &namespace, &source)
+  }
+}
+
+/// Expands macros into items.
+struct Expander<'a> {
+  assembler: &'a Assembler,
+  macros: &'a HashMap<String, Macro>,
+  /// How many macros have been expanded, to name their local labels
+  expansions: usize,
+  items: Vec<Item>,
+}
+
+impl Expander<'_> {
+  fn expand(&mut self, line: &Line, depth: usize) -> Result<(), AsmError> {
+    let location = &line.location;
+    if let Some(label) = line.label.as_ref() {
+      self
+        .items
+        .push(Item::Label(label.qualified(), location.clone()));
+    }
+    let mnemonic = match line.mnemonic.as_ref() {
+      Some(mnemonic) => mnemonic,
+      None => return Ok(()),
+    };
+    match mnemonic.text.as_str() {
+      "data" => {
+        let values = line
+          .operands
+          .iter()
+          .map(|operand| parse_expr(operand, location))
+          .collect::<Result<Vec<_>, _>>()?;
+        self.items.push(Item::Data(values, location.clone()));
+        return Ok(());
+      }
+      "space" => {
+        let count = match line.operands.as_slice() {
+          [operand] => parse_expr(operand, location)?.evaluate(&BTreeMap::new(), location)?,
+          _ => return Err(location.error("space needs a single count")),
+        };
+        if count < 0 {
+          return Err(location.error("space needs a count that isn't negative"));
+        }
+        let zeros = vec![Expr::Number(0); count as usize];
+        self.items.push(Item::Data(zeros, location.clone()));
+        return Ok(());
+      }
+      _ => (),
+    }
+
+    if let Some(def) = self
+      .assembler
+      .instruction_set
+      .iter()
+      .find(|def| def.name == mnemonic.text)
+    {
+      if line.operands.len() != usize::from(def.arity) {
+        return Err(location.error(format!(
+          "{} takes {} operands, not {}",
+          def.name,
+          def.arity,
+          line.operands.len()
+        )));
+      }
+      let operands = line
+        .operands
+        .iter()
+        .map(|operand| parse_operand(operand, location))
+        .collect::<Result<Vec<_>, _>>()?;
+      if let Some(i) =
+        (0..def.arity).find(|i| def.writes_to(*i) && operands[usize::from(*i)].0 == 1)
+      {
+        return Err(location.error(format!(
+          "Operand {} of {} is written to, so it can't be immediate",
+          i + 1,
+          def.name
+        )));
+      }
+      self.items.push(Item::Instruction {
+        opcode: def.opcode,
+        operands,
+        location: location.clone(),
+      });
+      return Ok(());
+    }
+
+    let (name, definition) = mnemonic
+      .candidates()
+      .into_iter()
+      .find_map(|name| self.macros.get(&name).map(|definition| (name, definition)))
+      .ok_or_else(|| location.error(format!("Unknown instruction {}", mnemonic.text)))?;
+    if line.operands.len() != definition.params.len() {
+      return Err(location.error(format!(
+        "Macro {} takes {} operands, not {}",
+        name,
+        definition.params.len(),
+        line.operands.len()
+      )));
+    }
+    if depth >= MAX_MACRO_DEPTH {
+      return Err(location.error(format!("Macro {} expands too deeply", name)));
+    }
+    self.expansions += 1;
+    let suffix = format!(".{}", self.expansions);
+    let arguments: HashMap<&str, &Vec<Token>> = definition
+      .params
+      .iter()
+      .map(|param| param.as_str())
+      .zip(line.operands.iter())
+      .collect();
+    for body_line in definition.body.iter() {
+      let substitute = |tokens: &[Token]| -> Result<Vec<Token>, AsmError> {
+        let mut substituted = vec![];
+        for token in tokens {
+          match token {
+            Token::Param(param) => match arguments.get(param.as_str()) {
+              Some(argument) => substituted.extend(argument.iter().cloned()),
+              None => {
+                return Err(location.error(format!("Macro {} has no parameter {}", name, param)))
+              }
+            },
+            Token::Name(ident) => substituted.push(Token::Name(localize(ident, &suffix))),
+            token => substituted.push(token.clone()),
+          }
+        }
+        Ok(substituted)
+      };
+      let expanded = Line {
+        // Errors point at where the macro was used
+        location: location.clone(),
+        label: body_line
+          .label
+          .as_ref()
+          .map(|label| localize(label, &suffix)),
+        mnemonic: body_line.mnemonic.clone(),
+        operands: body_line
+          .operands
+          .iter()
+          .map(|operand| substitute(operand))
+          .collect::<Result<_, _>>()?,
+      };
+      self.expand(&expanded, depth + 1)?;
+    }
+    Ok(())
+  }
+}
+
+/// Gives a macro's local labels, which start with `@`, a name unique to one expansion.
+fn localize(name: &Name, suffix: &str) -> Name {
+  if name.text.starts_with('@') && !name.text.contains('.') {
+    Name {
+      text: name.text.clone() + suffix,
+      namespace: name.namespace.clone(),
+    }
+  } else {
+    name.clone()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::disasm::disassemble;
+  use super::super::{parse, IntcodeComputer};
+  use super::*;
+
+  fn run(sequence: IntcodeSequence, inputs: &[isize]) -> Vec<isize> {
+    let mut inputs = inputs.iter();
+    let mut outputs = vec![];
+    let mut computer = IntcodeComputer::new(sequence)
+      .with_instruction_set(InstructionSet::relative())
+      .start();
+    loop {
+      computer = match computer {
+        IntcodeComputer::Input(state) => state.execute(*inputs.next().unwrap()),
+        IntcodeComputer::Output(state) => {
+          outputs.push(state.output);
+          state.execute()
+        }
+        IntcodeComputer::Halt(_) => return outputs,
+        computer => panic!("Unexpected {:?}", computer),
+      }
+    }
+  }
+
+  fn error(source: &str) -> String {
+    assemble(source).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn assembles_instructions() {
+    let assembly = assemble(
+      "
+        in [n]              ; doubles the input until it's at least 100
+      loop:
+        mul [n], #2, [n]
+        lt [n], #100, [small]
+        jnz [small], #loop
+        out rb[n]
+        halt
+      n:     data 0
+      small: space 1
+      ",
+    )
+    .unwrap();
+    assert_eq!(
+      assembly.sequence,
+      parse("3,16,1002,16,2,16,1007,16,100,17,1005,17,2,204,16,99,0,0")
+    );
+    assert_eq!(assembly.labels["loop"], 2);
+    assert_eq!(run(assembly.sequence, &[7]), vec![112]);
+  }
+
+  #[test]
+  fn expressions() {
+    let assembly = assemble("a: data 'a', -(2 - 5), end - a + 1, '\\n', -'\\''\nend:").unwrap();
+    assert_eq!(assembly.sequence, vec![97, 3, 6, 10, -39]);
+    let assembly = assemble("data -9223372036854775808, -9223372036854775807 - 1").unwrap();
+    assert_eq!(assembly.sequence, vec![isize::MIN, isize::MIN]);
+    let assembly = assemble("out #-9223372036854775808\nhalt").unwrap();
+    assert_eq!(run(assembly.sequence, &[]), vec![isize::MIN]);
+  }
+
+  #[test]
+  fn reads_disassembly() {
+    let sequence = parse("109,7,21101,5,-3,0,99,0,0");
+    let listing = disassemble(&sequence, &InstructionSet::relative());
+    let source: String = listing
+      .lines()
+      .map(|line| line.split_once(": ").unwrap().1)
+      .map(|line| format!("{}\n", line))
+      .collect();
+    assert_eq!(assemble(&source).unwrap().sequence, sequence);
+  }
+
+  #[test]
+  fn expands_macros() {
+    let assembly = assemble(
+      "
+        in [n]
+        countdown [n]
+        halt
+      n: data 0
+
+      ; Outputs `counter` down to 1
+      .macro countdown counter
+      @loop:
+        jz %counter, #@done
+        out %counter
+        add %counter, #-1, %counter
+        jz #0, #@loop
+      @done:
+      .endm
+      ",
+    )
+    .unwrap();
+    assert_eq!(run(assembly.sequence, &[3]), vec![3, 2, 1]);
+
+    // Local labels are different in every expansion
+    let assembly =
+      assemble(".macro skip\n  jz #0, #@next\n@next:\n.endm\nskip\nskip\nhalt").unwrap();
+    assert_eq!(assembly.sequence, parse("1106,0,3,1106,0,6,99"));
+  }
+
+  #[test]
+  fn namespaces_included_files() {
+    let assembler = Assembler::new().with_file(
+      "lib/util.asm",
+      "
+      ; Outputs `value` plus the offset below
+      .macro show value
+        add %value, [offset], [scratch]
+        out [scratch]
+      .endm
+      offset:  data 100
+      scratch: data 0
+      ",
+    );
+    let assembly = assembler
+      .assemble(
+        "main.asm",
+        "
+          util.show [offset]
+          util.show #2
+          halt
+        offset: data 5
+        .include \"lib/util.asm\"
+        .include \"lib/util.asm\"
+        ",
+      )
+      .unwrap();
+    assert_eq!(assembly.labels["util.offset"], 14);
+    assert_eq!(assembly.labels["offset"], 13);
+    assert_eq!(assembly.sequence.len(), 16);
+    assert_eq!(run(assembly.sequence, &[]), vec![105, 102]);
+  }
+
+  #[test]
+  fn loads_includes() {
+    let assembler = Assembler::new().with_loader(|name| {
+      if name == "answer.asm" {
+        Some("value: data 42".to_string())
+      } else {
+        None
+      }
+    });
+    let assembly = assembler
+      .assemble(
+        "main.asm",
+        "out [answer.value]\nhalt\n.include \"answer.asm\"",
+      )
+      .unwrap();
+    assert_eq!(run(assembly.sequence, &[]), vec![42]);
+  }
+
+  /// Outputs from `program`, with the stack set up at the end of memory.
+  fn run_with_stack(program: &str, inputs: &[isize]) -> Vec<isize> {
+    let source = format!(
+      "stack.init stack_space\n{}\n.include \"stack\"\n.include \"call\"\n\
+       .include \"memory\"\n.include \"math\"\n.include \"print\"\nstack_space: space 32",
+      program
+    );
+    let assembly = assemble(&source).unwrap_or_else(|err| panic!("{}", err));
+    run(assembly.sequence, inputs)
+  }
+
+  #[test]
+  fn stack() {
+    let outputs = run_with_stack(
+      "
+        in [x]
+        stack.push [x]
+        stack.push #2
+        stack.push rb[-1]
+        stack.pop [x]
+        out [x]
+        stack.drop 1
+        stack.pop [x]
+        out [x]
+        halt
+      x: data 0
+      ",
+      &[9],
+    );
+    assert_eq!(outputs, vec![2, 9]);
+  }
+
+  #[test]
+  fn calls_and_returns() {
+    let outputs = run_with_stack(
+      "
+        in [x]
+        stack.push [x]
+        call.call triple_plus_one
+        stack.pop [x]
+        out [x]
+        halt
+
+      ; Nested calls, each leaving its result over its argument
+      triple_plus_one:
+        stack.push rb[-2]
+        call.call triple
+        stack.pop rb[-2]
+        add rb[-2], #1, rb[-2]
+        call.ret
+      triple:
+        mul rb[-2], #3, rb[-2]
+        call.ret
+      x: data 0
+      ",
+      &[5],
+    );
+    assert_eq!(outputs, vec![16]);
+  }
+
+  #[test]
+  fn copies_memory() {
+    let outputs = run_with_stack(
+      "
+        stack.push #from
+        stack.push #to
+        stack.push #3
+        call.call memory.copy
+        stack.drop 3
+        out [to]
+        out [to + 1]
+        out [to + 2]
+        out [to + 3]
+        halt
+      from: data 7, 8, 9
+      to:   data 0, 0, 0, -1
+      ",
+      &[],
+    );
+    assert_eq!(outputs, vec![7, 8, 9, -1]);
+  }
+
+  #[test]
+  fn multiplies_and_accumulates() {
+    let outputs = run_with_stack(
+      "
+        math.mac [acc], #3, #4
+        math.mac [acc], [acc], #2
+        out [acc]
+        stack.push #a
+        stack.push #b
+        stack.push #3
+        call.call math.dot
+        stack.drop 2
+        stack.pop [acc]
+        out [acc]
+        halt
+      acc: data 1
+      a: data 1, 2, 3
+      b: data 4, -5, 6
+      ",
+      &[],
+    );
+    assert_eq!(outputs, vec![39, 12]);
+  }
+
+  #[test]
+  fn prints_numbers() {
+    let print = |value: isize| {
+      let outputs = run_with_stack(
+        "
+          in [x]
+          stack.push [x]
+          call.call print.number
+          stack.drop 1
+          print.newline
+          halt
+        x: data 0
+        ",
+        &[value],
+      );
+      outputs
+        .into_iter()
+        .map(|c| c as u8 as char)
+        .collect::<String>()
+    };
+    for value in [0, 7, -42, 1000, 1234567890, isize::MAX, isize::MIN + 1] {
+      assert_eq!(print(value), format!("{}\n", value));
+    }
+  }
+
+  #[test]
+  fn errors() {
+    assert_eq!(
+      error("halt\nout [nowhere]"),
+      "<input>:2: Undefined label nowhere"
+    );
+    assert_eq!(
+      error("frobnicate #1"),
+      "<input>:1: Unknown instruction frobnicate"
+    );
+    assert_eq!(
+      error("add #1, #2"),
+      "<input>:1: add takes 3 operands, not 2"
+    );
+    assert_eq!(
+      error("add #1, #2, #3"),
+      "<input>:1: Operand 3 of add is written to, so it can't be immediate"
+    );
+    assert_eq!(
+      error("out 5"),
+      "<input>:1: Expected an operand: #value, [address] or rb[offset]"
+    );
+    assert_eq!(error("out [5"), "<input>:1: Expected ]");
+    assert_eq!(
+      error("a: halt\na: halt"),
+      "<input>:2: Label a is already defined"
+    );
+    assert_eq!(
+      error(".include \"missing\""),
+      "<input>:1: Can't find included file \"missing\""
+    );
+    assert_eq!(
+      error("\n.macro m\nhalt"),
+      "<input>:2: Macro m isn't closed with .endm"
+    );
+    assert_eq!(
+      error(".macro m a\n.endm\nm"),
+      "<input>:3: Macro m takes 1 operands, not 0"
+    );
+    assert_eq!(
+      error(".macro m\nm\n.endm\nm"),
+      "<input>:4: Macro m expands too deeply"
+    );
+    assert_eq!(
+      error(".macro out\n.endm"),
+      "<input>:1: Macro out would hide an instruction"
+    );
+    assert_eq!(error(".org 5"), "<input>:1: Unknown directive .org");
+    assert_eq!(error("data $"), "<input>:1: Unexpected character '$'");
+    assert_eq!(
+      error(&format!("data {} + 1", isize::MAX)),
+      "<input>:1: Expression overflows"
+    );
+    assert_eq!(
+      error(&format!("data {}", isize::MAX as i128 + 1)),
+      format!(
+        "<input>:1: {} doesn't fit in a word",
+        isize::MAX as i128 + 1
+      )
+    );
+    assert_eq!(
+      error(&format!("data -({})", -(isize::MIN as i128))),
+      format!("<input>:1: {} doesn't fit in a word", -(isize::MIN as i128))
+    );
+  }
+}
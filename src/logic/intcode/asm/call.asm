@@ -0,0 +1,21 @@
+; Subroutine calls, with return addresses on the stack (see stack.asm).
+;
+; Arguments are pushed before `call`. Inside the subroutine, the return address is at
+; rb[-1], the last argument pushed is at rb[-2], the one before it at rb[-3], and so on.
+; Subroutines that give back a result write it over their first argument, so the caller
+; drops the rest and pops the result. The caller also cleans up any other arguments.
+.include "stack"
+
+; Calls the subroutine at `target`.
+.macro call target
+  add #@return, #0, rb[0]
+  arb #1
+  jz #0, #%target
+@return:
+.endm
+
+; Returns from a subroutine, which has to have popped whatever it pushed.
+.macro ret
+  arb #-1
+  jz #0, rb[0]
+.endm
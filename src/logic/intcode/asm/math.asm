@@ -0,0 +1,37 @@
+; Arithmetic beyond what the instruction set has.
+.include "call"
+
+; Adds `a` times `b` to `acc`.
+.macro mac acc, a, b
+  mul %a, %b, [product]
+  add %acc, [product], %acc
+.endm
+
+; The sum of `a[i]` times `b[i]` for every `i` below `count`, where `a` and `b` are
+; addresses. Push `a`, `b` and `count`, call, drop 2, then pop the result.
+dot:
+  add rb[-4], #0, [a]
+  add rb[-3], #0, [b]
+  add rb[-2], #0, [count]
+  add #0, #0, [sum]
+next:
+  jz [count], #done
+  ; Points the multiply at the current elements
+  add [a], #0, [multiply + 1]
+  add [b], #0, [multiply + 2]
+multiply:
+  mul [0], [0], [product]
+  add [sum], [product], [sum]
+  add [a], #1, [a]
+  add [b], #1, [b]
+  add [count], #-1, [count]
+  jz #0, #next
+done:
+  add [sum], #0, rb[-4]
+  call.ret
+
+product: data 0
+a:       data 0
+b:       data 0
+count:   data 0
+sum:     data 0
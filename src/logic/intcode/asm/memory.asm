@@ -0,0 +1,27 @@
+; Copying memory around.
+.include "call"
+
+; Copies `count` words from `source` to `destination`. Push `source`, `destination` and
+; `count`, call, then drop all three. When the ranges overlap, `destination` has to come
+; before `source`.
+copy:
+  add rb[-4], #0, [source]
+  add rb[-3], #0, [destination]
+  add rb[-2], #0, [count]
+next:
+  jz [count], #done
+  ; Points the move at the current words
+  add [source], #0, [move + 1]
+  add [destination], #0, [move + 3]
+move:
+  add [0], #0, [0]
+  add [source], #1, [source]
+  add [destination], #1, [destination]
+  add [count], #-1, [count]
+  jz #0, #next
+done:
+  call.ret
+
+source:      data 0
+destination: data 0
+count:       data 0
@@ -0,0 +1,60 @@
+; Printing as ASCII, one character per output.
+.include "call"
+
+; Outputs a newline.
+.macro newline
+  out #'\n'
+.endm
+
+; Outputs `value` in decimal, with a leading '-' when it's negative. Push `value`, call,
+; then drop it. The most negative word has no positive counterpart, so it overflows.
+number:
+  add rb[-2], #0, [value]
+  lt [value], #0, [flag]
+  jz [flag], #positive
+  out #'-'
+  mul [value], #-1, [value]
+positive:
+  add #powers, #0, [power]
+  add #0, #0, [started]
+next_place:
+  ; Loads the current power of ten into `place`
+  add [power], #0, [load + 1]
+load:
+  add [0], #0, [place]
+  mul [place], #-1, [minus_place]
+  add #0, #0, [digit]
+count:
+  ; Without division, the digit is how many times the place value can be taken away
+  lt [value], [place], [flag]
+  jnz [flag], #emit
+  add [value], [minus_place], [value]
+  add [digit], #1, [digit]
+  jz #0, #count
+emit:
+  ; Leading zeros are skipped, but the ones place is always shown
+  eq [place], #1, [last]
+  add [started], [digit], [started]
+  add [started], [last], [flag]
+  jz [flag], #skip
+  add [digit], #'0', [digit]
+  out [digit]
+skip:
+  jnz [last], #done
+  add [power], #1, [power]
+  jz #0, #next_place
+done:
+  call.ret
+
+powers:
+  data 1000000000000000000, 100000000000000000, 10000000000000000, 1000000000000000
+  data 100000000000000, 10000000000000, 1000000000000, 100000000000, 10000000000
+  data 1000000000, 100000000, 10000000, 1000000, 100000, 10000, 1000, 100, 10, 1
+value:       data 0
+power:       data 0
+place:       data 0
+minus_place: data 0
+digit:       data 0
+started:     data 0
+last:        data 0
+flag:        data 0
@@ -0,0 +1,25 @@
+; A stack in memory, growing upwards. The relative base points at the first free word, so
+; rb[-1] is the top of the stack.
+
+; Starts the stack at the address `base`, written without `#`. Use it once, before
+; anything else moves the relative base.
+.macro init base
+  arb #%base
+.endm
+
+; Pushes `value`, any operand.
+.macro push value
+  add %value, #0, rb[0]
+  arb #1
+.endm
+
+; Pops the top of the stack into `dest`. A relative `dest` counts from the new top.
+.macro pop dest
+  arb #-1
+  add rb[0], #0, %dest
+.endm
+
+; Drops the top `count` words.
+.macro drop count
+  arb #-(%count)
+.endm
@@ -0,0 +1,590 @@
+// Runs many copies of one program side by side, for searches that try the same program
+// over lots of inputs or patches.
+//
+// Memory is stored structure-of-arrays: each address holds one word per lane, next to
+// each other. Lanes that are at the same instruction run as a group, decoding it once
+// and then executing it for every lane in a tight loop. When a jump sends lanes to
+// different places, or a lane overwrote the instruction, the group splits apart, and
+// groups join back up whenever one catches up with another. Lanes are only compared on
+// the instruction word at addresses something has written to, so code that's never
+// modified costs nothing to keep in agreement.
+//
+// Like the compiled engine, only the standard instruction set is supported, overflowing
+// arithmetic faults as under `OverflowPolicy::Fault`, and bad addresses panic.
+
+use super::instructions::STANDARD_INSTRUCTION_SET;
+use super::patch::PatchError;
+use super::{parameter_modes, Fault, IntcodeSequence, PatchSet};
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::TryFrom;
+
+/// Where a lane is up to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LaneStatus {
+  /// Can carry on when `Batch::run` is next called
+  Ready,
+  /// Stopped at an input instruction with nothing in its queue
+  NeedsInput,
+  Halted,
+  Faulted(Fault),
+}
+
+/// How much work the lanes shared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+  /// Instructions decoded, once for every lane in the group running them
+  pub group_steps: u64,
+  /// Instructions executed, counted separately for every lane
+  pub lane_steps: u64,
+  /// Times a group split apart because its lanes diverged
+  pub splits: u64,
+}
+
+/// An instruction decoded from its opcode word.
+#[derive(Clone, Copy)]
+struct Decoded {
+  opcode: u8,
+  modes: [u8; 3],
+  len: usize,
+}
+
+/// `lanes` copies of one program, each with its own memory, inputs and outputs.
+pub struct Batch {
+  lanes: usize,
+  len: usize,
+  /// `memory[address * lanes + lane]`
+  memory: Vec<isize>,
+  pointers: Vec<usize>,
+  statuses: Vec<LaneStatus>,
+  inputs: Vec<VecDeque<isize>>,
+  outputs: Vec<Vec<isize>>,
+  /// Addresses any lane has written to, where lanes may no longer agree on the word
+  written: Vec<bool>,
+  /// The last word decoded at each address, and what it decoded to
+  decoded: Vec<Option<(isize, Result<Decoded, Fault>)>>,
+  stats: BatchStats,
+}
+
+fn to_address(value: isize) -> usize {
+  usize::try_from(value).unwrap()
+}
+
+/// Reads the parameter at `address` for `lane`, out of memory with `stride` lanes.
+fn read_param(memory: &[isize], stride: usize, lane: usize, address: usize, mode: u8) -> isize {
+  let value = memory[address * stride + lane];
+  if mode == 1 {
+    value
+  } else {
+    memory[to_address(value) * stride + lane]
+  }
+}
+
+fn decode(word: isize, pointer: usize) -> Result<Decoded, Fault> {
+  let opcode = word % 100;
+  let def = STANDARD_INSTRUCTION_SET
+    .get(opcode)
+    .ok_or(Fault::UnknownOpcode { opcode, pointer })?;
+  let mut modes = [0; 3];
+  for (i, mode) in parameter_modes(word, def.arity).into_iter().enumerate() {
+    if !STANDARD_INSTRUCTION_SET.parameter_modes().contains(&mode) {
+      return Err(Fault::UnsupportedParameterMode { mode, pointer });
+    }
+    modes[i] = mode;
+  }
+  Ok(Decoded {
+    opcode: def.opcode,
+    modes,
+    len: 1 + usize::from(def.arity),
+  })
+}
+
+impl Batch {
+  pub fn new(sequence: &IntcodeSequence, lanes: usize) -> Batch {
+    let memory = sequence
+      .iter()
+      .flat_map(|word| std::iter::repeat_n(*word, lanes))
+      .collect();
+    Batch {
+      lanes,
+      len: sequence.len(),
+      memory,
+      pointers: vec![0; lanes],
+      statuses: vec![LaneStatus::Ready; lanes],
+      inputs: vec![VecDeque::new(); lanes],
+      outputs: vec![vec![]; lanes],
+      written: vec![false; sequence.len()],
+      decoded: vec![None; sequence.len()],
+      stats: BatchStats::default(),
+    }
+  }
+
+  pub fn lanes(&self) -> usize {
+    self.lanes
+  }
+
+  pub fn stats(&self) -> BatchStats {
+    self.stats
+  }
+
+  pub fn read(&self, lane: usize, address: usize) -> isize {
+    assert!(address < self.len, "Address {} is out of bounds", address);
+    self.memory[address * self.lanes + lane]
+  }
+
+  pub fn write(&mut self, lane: usize, address: usize, value: isize) {
+    assert!(address < self.len, "Address {} is out of bounds", address);
+    self.memory[address * self.lanes + lane] = value;
+    self.written[address] = true;
+  }
+
+  /// Applies `patches` to one lane's memory.
+  pub fn patch(&mut self, lane: usize, patches: &PatchSet) -> Result<(), PatchError> {
+    if let Some(patch) = patches.iter().find(|patch| patch.address >= self.len) {
+      return Err(PatchError::OutOfBounds {
+        address: patch.address,
+        len: self.len,
+      });
+    }
+    for patch in patches.iter() {
+      self.write(lane, patch.address, patch.value);
+    }
+    Ok(())
+  }
+
+  /// A copy of one lane's memory.
+  pub fn memory(&self, lane: usize) -> IntcodeSequence {
+    (0..self.len)
+      .map(|address| self.read(lane, address))
+      .collect()
+  }
+
+  pub fn pointer(&self, lane: usize) -> usize {
+    self.pointers[lane]
+  }
+
+  pub fn status(&self, lane: usize) -> &LaneStatus {
+    &self.statuses[lane]
+  }
+
+  /// Queues an input for one lane. A lane waiting for input can carry on once it has some.
+  pub fn push_input(&mut self, lane: usize, input: isize) {
+    self.inputs[lane].push_back(input);
+    if self.statuses[lane] == LaneStatus::NeedsInput {
+      self.statuses[lane] = LaneStatus::Ready;
+    }
+  }
+
+  pub fn outputs(&self, lane: usize) -> &[isize] {
+    &self.outputs[lane]
+  }
+
+  pub fn take_outputs(&mut self, lane: usize) -> Vec<isize> {
+    std::mem::take(&mut self.outputs[lane])
+  }
+
+  /// Runs every ready lane until it halts, faults or runs out of input.
+  pub fn run(&mut self) {
+    let mut pending = Pending::new();
+    for lane in 0..self.lanes {
+      if self.statuses[lane] == LaneStatus::Ready {
+        pending.entry(self.pointers[lane]).or_default().push(lane);
+      }
+    }
+    // Lowest address first, so lanes that fell behind get the chance to catch up
+    while let Some((pointer, lanes)) = pending.pop_first() {
+      self.run_group(pointer, lanes, &mut pending);
+    }
+  }
+
+  fn word(&self, lane: usize, address: usize) -> isize {
+    self.memory[address * self.lanes + lane]
+  }
+
+  fn param(&self, lane: usize, address: usize, mode: u8) -> isize {
+    read_param(&self.memory, self.lanes, lane, address, mode)
+  }
+
+  /// Writes to the address in the parameter at `address`.
+  fn write_param(&mut self, lane: usize, address: usize, value: isize) {
+    let target = to_address(self.word(lane, address));
+    self.memory[target * self.lanes + lane] = value;
+    self.written[target] = true;
+  }
+
+  fn decode_at(&mut self, pointer: usize, word: isize) -> Result<Decoded, Fault> {
+    match &self.decoded[pointer] {
+      Some((cached, decoded)) if *cached == word => decoded.clone(),
+      _ => {
+        let decoded = decode(word, pointer);
+        self.decoded[pointer] = Some((word, decoded.clone()));
+        decoded
+      }
+    }
+  }
+
+  /// Stops `lane` at the instruction at `pointer`.
+  fn stop(&mut self, lane: usize, pointer: usize, status: LaneStatus) {
+    self.pointers[lane] = pointer;
+    self.statuses[lane] = status;
+  }
+
+  /// Drops the lanes that stopped partway through an instruction.
+  fn keep_ready(&self, lanes: &mut Vec<usize>) {
+    lanes.retain(|lane| self.statuses[*lane] == LaneStatus::Ready);
+  }
+
+  /// Runs lanes from `pointer` until they have all stopped, they split apart, or they
+  /// catch up with other lanes in `pending`.
+  fn run_group(&mut self, mut pointer: usize, mut lanes: Vec<usize>, pending: &mut Pending) {
+    while !lanes.is_empty() {
+      if let Some(waiting) = pending.get_mut(&pointer) {
+        waiting.append(&mut lanes);
+        return;
+      }
+      let word = self.word(lanes[0], pointer);
+      let agree = !self.written[pointer]
+        || lanes[1..]
+          .iter()
+          .all(|lane| self.word(*lane, pointer) == word);
+      if agree {
+        match self.step(pointer, word, &mut lanes) {
+          Next::All(next) => pointer = next,
+          Next::Each(targets) => {
+            self.stats.splits += 1;
+            queue_each(pending, targets);
+            return;
+          }
+          Next::Stopped => return,
+        }
+      } else {
+        // Some lanes overwrote this instruction, so they no longer agree on what it is
+        let mut by_word: BTreeMap<isize, Vec<usize>> = BTreeMap::new();
+        for lane in lanes {
+          by_word
+            .entry(self.word(lane, pointer))
+            .or_default()
+            .push(lane);
+        }
+        self.stats.splits += 1;
+        for (word, mut lanes) in by_word {
+          match self.step(pointer, word, &mut lanes) {
+            Next::All(next) => pending.entry(next).or_default().append(&mut lanes),
+            Next::Each(targets) => queue_each(pending, targets),
+            Next::Stopped => (),
+          }
+        }
+        return;
+      }
+    }
+  }
+
+  /// Runs an instruction that writes `operation` of its first two parameters to its
+  /// third, faulting lanes where it gives `None`.
+  fn arithmetic<F>(
+    &mut self,
+    pointer: usize,
+    decoded: &Decoded,
+    lanes: &mut Vec<usize>,
+    operation: F,
+  ) -> Next
+  where
+    F: Fn(isize, isize) -> Option<isize>,
+  {
+    let [m0, m1, _] = decoded.modes;
+    let (stride, memory, written) = (self.lanes, &mut self.memory, &mut self.written);
+    let mut faulted = vec![];
+    for lane in lanes.iter().cloned() {
+      let a = read_param(memory, stride, lane, pointer + 1, m0);
+      let b = read_param(memory, stride, lane, pointer + 2, m1);
+      match operation(a, b) {
+        Some(value) => {
+          let target = to_address(memory[(pointer + 3) * stride + lane]);
+          memory[target * stride + lane] = value;
+          written[target] = true;
+        }
+        None => faulted.push(lane),
+      }
+    }
+    if !faulted.is_empty() {
+      for lane in faulted {
+        self.stop(
+          lane,
+          pointer,
+          LaneStatus::Faulted(Fault::Overflow { pointer }),
+        );
+      }
+      self.keep_ready(lanes);
+    }
+    Next::All(pointer + decoded.len)
+  }
+
+  /// Executes the instruction `word` at `pointer` for every lane in `lanes`, dropping any
+  /// that stop.
+  fn step(&mut self, pointer: usize, word: isize, lanes: &mut Vec<usize>) -> Next {
+    let decoded = match self.decode_at(pointer, word) {
+      Ok(decoded) => decoded,
+      Err(fault) => {
+        for lane in lanes.iter() {
+          self.stop(*lane, pointer, LaneStatus::Faulted(fault.clone()));
+        }
+        return Next::Stopped;
+      }
+    };
+    self.stats.group_steps += 1;
+    self.stats.lane_steps += lanes.len() as u64;
+    let [m0, m1, _] = decoded.modes;
+    let next = pointer + decoded.len;
+    match decoded.opcode {
+      1 => self.arithmetic(pointer, &decoded, lanes, isize::checked_add),
+      2 => self.arithmetic(pointer, &decoded, lanes, isize::checked_mul),
+      7 => self.arithmetic(pointer, &decoded, lanes, |a, b| Some((a < b) as isize)),
+      8 => self.arithmetic(pointer, &decoded, lanes, |a, b| Some((a == b) as isize)),
+      3 => {
+        let mut waiting = false;
+        for lane in lanes.iter().cloned() {
+          match self.inputs[lane].pop_front() {
+            Some(input) => self.write_param(lane, pointer + 1, input),
+            None => {
+              self.stop(lane, pointer, LaneStatus::NeedsInput);
+              waiting = true;
+            }
+          }
+        }
+        if waiting {
+          self.keep_ready(lanes);
+        }
+        Next::All(next)
+      }
+      4 => {
+        for lane in lanes.iter().cloned() {
+          let output = self.param(lane, pointer + 1, m0);
+          self.outputs[lane].push(output);
+        }
+        Next::All(next)
+      }
+      5 | 6 => {
+        let jump_if = decoded.opcode == 5;
+        let (stride, memory) = (self.lanes, &self.memory);
+        let target = |lane: usize| {
+          if (read_param(memory, stride, lane, pointer + 1, m0) != 0) == jump_if {
+            to_address(read_param(memory, stride, lane, pointer + 2, m1))
+          } else {
+            next
+          }
+        };
+        let first = target(lanes[0]);
+        if lanes.iter().all(|lane| target(*lane) == first) {
+          Next::All(first)
+        } else {
+          Next::Each(lanes.iter().map(|lane| (*lane, target(*lane))).collect())
+        }
+      }
+      _ => {
+        for lane in lanes.iter() {
+          self.stop(*lane, pointer, LaneStatus::Halted);
+        }
+        Next::Stopped
+      }
+    }
+  }
+}
+
+/// Lanes waiting to run, by the instruction they're at.
+type Pending = BTreeMap<usize, Vec<usize>>;
+
+/// Where lanes go after an instruction.
+enum Next {
+  /// Every lane that's still running goes to the same place
+  All(usize),
+  /// Each lane, with where it goes
+  Each(Vec<(usize, usize)>),
+  Stopped,
+}
+
+fn queue_each(pending: &mut Pending, targets: Vec<(usize, usize)>) {
+  for (lane, target) in targets {
+    pending.entry(target).or_default().push(lane);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::fuzz::StateMachineEngine;
+  use super::super::fuzz::{run_catching, HaltKind, Outputs, ProgramGenerator, Rng};
+  use super::super::parse;
+  use super::*;
+
+  fn run_lanes(program: &str, inputs: &[&[isize]]) -> Batch {
+    let mut batch = Batch::new(&parse(program), inputs.len());
+    for (lane, lane_inputs) in inputs.iter().enumerate() {
+      for input in lane_inputs.iter() {
+        batch.push_input(lane, *input);
+      }
+    }
+    batch.run();
+    batch
+  }
+
+  #[test]
+  fn runs_lanes_in_lockstep() {
+    // Outputs input * 3 + 1
+    let batch = run_lanes(
+      "3,13,1002,13,3,13,101,1,13,13,4,13,99,0",
+      &[&[1], &[2], &[3]],
+    );
+    for lane in 0..3 {
+      assert_eq!(batch.status(lane), &LaneStatus::Halted);
+      assert_eq!(batch.pointer(lane), 12);
+    }
+    assert_eq!(batch.outputs(0), &[4]);
+    assert_eq!(batch.outputs(2), &[10]);
+    assert_eq!(batch.memory(1)[13], 7);
+    assert_eq!(
+      batch.stats(),
+      BatchStats {
+        group_steps: 5,
+        lane_steps: 15,
+        splits: 0
+      }
+    );
+  }
+
+  #[test]
+  fn splits_and_rejoins() {
+    // Outputs 1 if the input isn't zero, then 2 either way
+    let batch = run_lanes("3,10,1006,10,7,104,1,104,2,99,0", &[&[0], &[5], &[0]]);
+    assert_eq!(batch.outputs(0), &[2]);
+    assert_eq!(batch.outputs(1), &[1, 2]);
+    assert_eq!(batch.outputs(2), &[2]);
+    assert_eq!(
+      batch.stats(),
+      BatchStats {
+        group_steps: 5,
+        lane_steps: 13,
+        splits: 1
+      }
+    );
+  }
+
+  #[test]
+  fn splits_when_code_is_overwritten() {
+    // Writes the input over the opcode at 2, whose parameter is 5
+    let batch = run_lanes("3,2,0,5,99,7", &[&[99], &[104], &[4], &[42]]);
+    assert_eq!(batch.status(0), &LaneStatus::Halted);
+    assert_eq!(batch.pointer(0), 2);
+    assert_eq!(batch.outputs(1), &[5]);
+    assert_eq!(batch.outputs(2), &[7]);
+    assert_eq!(batch.pointer(2), 4);
+    assert_eq!(
+      batch.status(3),
+      &LaneStatus::Faulted(Fault::UnknownOpcode {
+        opcode: 42,
+        pointer: 2
+      })
+    );
+    assert_eq!(batch.stats().splits, 1);
+  }
+
+  #[test]
+  fn faults_single_lanes() {
+    // Triples its input
+    let batch = run_lanes("3,7,1002,7,3,7,99,0", &[&[1], &[isize::MAX], &[]]);
+    assert_eq!(batch.status(0), &LaneStatus::Halted);
+    assert_eq!(batch.read(0, 7), 3);
+    assert_eq!(
+      batch.status(1),
+      &LaneStatus::Faulted(Fault::Overflow { pointer: 2 })
+    );
+    assert_eq!(batch.status(2), &LaneStatus::NeedsInput);
+    assert_eq!(batch.pointer(2), 0);
+  }
+
+  #[test]
+  fn resumes_with_more_input() {
+    // Echoes inputs forever
+    let mut batch = Batch::new(&parse("3,7,4,7,1105,1,0,0"), 2);
+    for round in 0..3 {
+      for lane in 0..2 {
+        batch.push_input(lane, round * 10 + lane as isize);
+      }
+      batch.run();
+      for lane in 0..2 {
+        assert_eq!(batch.status(lane), &LaneStatus::NeedsInput);
+        assert_eq!(batch.take_outputs(lane), vec![round * 10 + lane as isize]);
+      }
+    }
+    assert_eq!(batch.stats().splits, 0);
+  }
+
+  #[test]
+  fn patches_lanes() {
+    // Multiplies addresses 5 and 6 into address 0
+    let mut batch = Batch::new(&parse("2,5,6,0,99,0,0"), 2);
+    batch.patch(0, &"5=6,6=7".parse().unwrap()).unwrap();
+    batch.patch(1, &"5=3,6=3".parse().unwrap()).unwrap();
+    assert_eq!(
+      batch.patch(1, &"7=1".parse().unwrap()),
+      Err(PatchError::OutOfBounds { address: 7, len: 7 })
+    );
+    batch.run();
+    assert_eq!(batch.memory(0), parse("42,5,6,0,99,6,7"));
+    assert_eq!(batch.read(1, 0), 9);
+  }
+
+  #[test]
+  fn matches_state_machine() {
+    let generator = ProgramGenerator::default();
+    let mut compared = 0;
+    for seed in 0..300 {
+      let case = generator.generate(&mut Rng::new(seed));
+      // The generated inputs, then other inputs to send the lanes different ways
+      let mut rng = Rng::new(seed);
+      let mut lane_inputs = vec![case.inputs.clone()];
+      for _ in 0..7 {
+        lane_inputs.push(case.inputs.iter().map(|_| rng.between(-3, 4)).collect());
+      }
+      let expected: Vec<_> = lane_inputs
+        .iter()
+        .map(|inputs| run_catching(&StateMachineEngine, &case.sequence, inputs))
+        .collect();
+      // Bad addresses panic the whole batch
+      if expected
+        .iter()
+        .any(|outcome| matches!(outcome.halt, HaltKind::Panicked(_)))
+      {
+        continue;
+      }
+
+      let mut batch = Batch::new(&case.sequence, lane_inputs.len());
+      for (lane, inputs) in lane_inputs.iter().enumerate() {
+        for input in inputs.iter() {
+          batch.push_input(lane, *input);
+        }
+      }
+      batch.run();
+      for (lane, expected) in expected.into_iter().enumerate() {
+        let halt = match batch.status(lane).clone() {
+          LaneStatus::Halted => HaltKind::Halted,
+          LaneStatus::NeedsInput => HaltKind::NeedsInput,
+          LaneStatus::Faulted(fault) => HaltKind::Faulted(fault),
+          LaneStatus::Ready => panic!("Seed {} lane {} didn't finish", seed, lane),
+        };
+        let context = format!("Seed {} with inputs {:?}", seed, lane_inputs[lane]);
+        assert_eq!(halt, expected.halt, "{}", context);
+        assert_eq!(
+          Outputs::All(batch.outputs(lane).to_vec()),
+          expected.outputs,
+          "{}",
+          context
+        );
+        assert_eq!(Some(batch.memory(lane)), expected.memory, "{}", context);
+      }
+      compared += 1;
+    }
+    assert!(
+      compared > 200,
+      "Only {} cases ran without panicking",
+      compared
+    );
+  }
+}
@@ -0,0 +1,332 @@
+// Arbitrary-precision integers, with just the operations Intcode needs.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+/// Decimal digits converted at a time, the most that fit in a `u32` limb
+const DECIMAL_CHUNK: usize = 9;
+const DECIMAL_BASE: u32 = 1_000_000_000;
+
+/// A signed integer of any size, stored as its sign and magnitude in base 2^32.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+  negative: bool,
+  /// Least significant limb first, with no high zero limbs, so zero is empty
+  magnitude: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "invalid integer")
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl BigInt {
+  fn new(negative: bool, mut magnitude: Vec<u32>) -> BigInt {
+    while magnitude.last() == Some(&0) {
+      magnitude.pop();
+    }
+    BigInt {
+      negative: negative && !magnitude.is_empty(),
+      magnitude,
+    }
+  }
+
+  pub fn zero() -> BigInt {
+    BigInt::default()
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.magnitude.is_empty()
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.negative
+  }
+
+  fn from_magnitude(negative: bool, value: u128) -> BigInt {
+    let limbs = (0..4).map(|i| (value >> (32 * i)) as u32).collect();
+    BigInt::new(negative, limbs)
+  }
+
+  /// The magnitude, if it fits in a `u128`.
+  fn small_magnitude(&self) -> Option<u128> {
+    if self.magnitude.len() > 4 {
+      return None;
+    }
+    Some(
+      self
+        .magnitude
+        .iter()
+        .rev()
+        .fold(0, |value, limb| (value << 32) | u128::from(*limb)),
+    )
+  }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+  a.len()
+    .cmp(&b.len())
+    .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+  let mut carry = 0;
+  for i in 0..a.len().max(b.len()) {
+    let total = u64::from(*a.get(i).unwrap_or(&0)) + u64::from(*b.get(i).unwrap_or(&0)) + carry;
+    sum.push(total as u32);
+    carry = total >> 32;
+  }
+  sum.push(carry as u32);
+  sum
+}
+
+/// `a - b`, where `a` is at least as big as `b`.
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut difference = Vec::with_capacity(a.len());
+  let mut borrow = 0;
+  for (i, limb) in a.iter().enumerate() {
+    let subtrahend = i64::from(*b.get(i).unwrap_or(&0)) + borrow;
+    let mut value = i64::from(*limb) - subtrahend;
+    borrow = 0;
+    if value < 0 {
+      value += 1 << 32;
+      borrow = 1;
+    }
+    difference.push(value as u32);
+  }
+  difference
+}
+
+fn multiply_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut product = vec![0u32; a.len() + b.len()];
+  for (i, x) in a.iter().enumerate() {
+    let mut carry = 0u64;
+    for (j, y) in b.iter().enumerate() {
+      let total = u64::from(*x) * u64::from(*y) + u64::from(product[i + j]) + carry;
+      product[i + j] = total as u32;
+      carry = total >> 32;
+    }
+    product[i + b.len()] = carry as u32;
+  }
+  product
+}
+
+/// Divides `magnitude` by `divisor` in place, returning the remainder.
+fn divide_small(magnitude: &mut [u32], divisor: u32) -> u32 {
+  let mut remainder = 0u64;
+  for limb in magnitude.iter_mut().rev() {
+    let value = (remainder << 32) | u64::from(*limb);
+    *limb = (value / u64::from(divisor)) as u32;
+    remainder = value % u64::from(divisor);
+  }
+  remainder as u32
+}
+
+/// Sets `magnitude` to `magnitude * factor + addend`.
+fn multiply_add_small(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+  let mut carry = u64::from(addend);
+  for limb in magnitude.iter_mut() {
+    let value = u64::from(*limb) * u64::from(factor) + carry;
+    *limb = value as u32;
+    carry = value >> 32;
+  }
+  if carry > 0 {
+    magnitude.push(carry as u32);
+  }
+}
+
+impl From<isize> for BigInt {
+  fn from(value: isize) -> BigInt {
+    BigInt::from(value as i128)
+  }
+}
+
+impl From<i128> for BigInt {
+  fn from(value: i128) -> BigInt {
+    BigInt::from_magnitude(value < 0, value.unsigned_abs())
+  }
+}
+
+impl TryFrom<&BigInt> for isize {
+  type Error = OutOfRange;
+
+  fn try_from(value: &BigInt) -> Result<isize, OutOfRange> {
+    let magnitude = value.small_magnitude().ok_or(OutOfRange)?;
+    let magnitude = i128::try_from(magnitude).map_err(|_| OutOfRange)?;
+    let signed = if value.negative {
+      -magnitude
+    } else {
+      magnitude
+    };
+    isize::try_from(signed).map_err(|_| OutOfRange)
+  }
+}
+
+impl Ord for BigInt {
+  fn cmp(&self, other: &BigInt) -> Ordering {
+    match (self.negative, other.negative) {
+      (false, true) => Ordering::Greater,
+      (true, false) => Ordering::Less,
+      (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+      (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+    }
+  }
+}
+
+impl PartialOrd for BigInt {
+  fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Add for &BigInt {
+  type Output = BigInt;
+
+  fn add(self, other: &BigInt) -> BigInt {
+    if self.negative == other.negative {
+      return BigInt::new(
+        self.negative,
+        add_magnitudes(&self.magnitude, &other.magnitude),
+      );
+    }
+    match compare_magnitudes(&self.magnitude, &other.magnitude) {
+      Ordering::Less => BigInt::new(
+        other.negative,
+        subtract_magnitudes(&other.magnitude, &self.magnitude),
+      ),
+      _ => BigInt::new(
+        self.negative,
+        subtract_magnitudes(&self.magnitude, &other.magnitude),
+      ),
+    }
+  }
+}
+
+impl Mul for &BigInt {
+  type Output = BigInt;
+
+  fn mul(self, other: &BigInt) -> BigInt {
+    BigInt::new(
+      self.negative != other.negative,
+      multiply_magnitudes(&self.magnitude, &other.magnitude),
+    )
+  }
+}
+
+impl fmt::Display for BigInt {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_zero() {
+      return write!(f, "0");
+    }
+    let mut magnitude = self.magnitude.clone();
+    let mut chunks = vec![];
+    while magnitude.iter().any(|limb| *limb != 0) {
+      chunks.push(divide_small(&mut magnitude, DECIMAL_BASE));
+    }
+    if self.negative {
+      write!(f, "-")?;
+    }
+    let mut chunks = chunks.iter().rev();
+    write!(f, "{}", chunks.next().unwrap())?;
+    for chunk in chunks {
+      write!(f, "{:09}", chunk)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for BigInt {
+  type Err = ParseBigIntError;
+
+  fn from_str(input: &str) -> Result<BigInt, ParseBigIntError> {
+    let (negative, digits) = match input.strip_prefix('-') {
+      Some(digits) => (true, digits),
+      None => (false, input.strip_prefix('+').unwrap_or(input)),
+    };
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+      return Err(ParseBigIntError);
+    }
+    let mut magnitude = vec![];
+    // Leading digits first, so the rest split into whole chunks
+    let first = match digits.len() % DECIMAL_CHUNK {
+      0 => DECIMAL_CHUNK,
+      partial => partial,
+    };
+    let mut start = 0;
+    let mut end = first;
+    while start < digits.len() {
+      let chunk: u32 = digits[start..end].parse().unwrap();
+      multiply_add_small(&mut magnitude, 10u32.pow((end - start) as u32), chunk);
+      start = end;
+      end += DECIMAL_CHUNK;
+    }
+    Ok(BigInt::new(negative, magnitude))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::fuzz::Rng;
+  use super::*;
+
+  fn big(value: &str) -> BigInt {
+    value.parse().unwrap()
+  }
+
+  #[test]
+  fn matches_i128() {
+    let mut rng = Rng::new(1);
+    for _ in 0..2000 {
+      let a = rng.next_u64() as i64 as i128 >> rng.below(64);
+      let b = rng.next_u64() as i64 as i128 >> rng.below(64);
+      let (x, y) = (BigInt::from(a), BigInt::from(b));
+      assert_eq!((&x + &y).to_string(), (a + b).to_string(), "{} + {}", a, b);
+      assert_eq!((&x * &y).to_string(), (a * b).to_string(), "{} * {}", a, b);
+      assert_eq!(x.cmp(&y), a.cmp(&b), "{} <=> {}", a, b);
+      assert_eq!(big(&a.to_string()), x);
+    }
+  }
+
+  #[test]
+  fn beyond_i128() {
+    let two_to_the_100 = big("1267650600228229401496703205376");
+    let product = &two_to_the_100 * &two_to_the_100;
+    assert_eq!(
+      product.to_string(),
+      "1606938044258990275541962092341162602522202993782792835301376"
+    );
+    let negated = &product * &BigInt::from(-1isize);
+    assert_eq!(&product + &negated, BigInt::zero());
+    assert!(negated < BigInt::zero());
+    assert_eq!(
+      (&negated + &BigInt::from(1isize)).to_string(),
+      "-1606938044258990275541962092341162602522202993782792835301375"
+    );
+  }
+
+  #[test]
+  fn parsing_and_conversion() {
+    assert_eq!(big("-0"), BigInt::zero());
+    assert_eq!(big("+000123").to_string(), "123");
+    assert_eq!(big("1000000000").to_string(), "1000000000");
+    assert_eq!("".parse::<BigInt>(), Err(ParseBigIntError));
+    assert_eq!("-".parse::<BigInt>(), Err(ParseBigIntError));
+    assert_eq!("1e5".parse::<BigInt>(), Err(ParseBigIntError));
+
+    assert_eq!(isize::try_from(&BigInt::from(isize::MIN)), Ok(isize::MIN));
+    assert_eq!(isize::try_from(&BigInt::from(isize::MAX)), Ok(isize::MAX));
+    let too_big = &BigInt::from(isize::MAX) + &BigInt::from(1isize);
+    assert_eq!(isize::try_from(&too_big), Err(OutOfRange));
+  }
+}
@@ -0,0 +1,249 @@
+// Compact binary encoding for Intcode programs and memory snapshots.
+//
+// Layout:
+//   magic     4 bytes  "INTC"
+//   version   1 byte
+//   length    varint   number of words
+//   checksum  4 bytes  FNV-1a of the payload, little-endian
+//   payload   one zigzag varint per word
+
+use super::IntcodeSequence;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+pub const MAGIC: [u8; 4] = *b"INTC";
+pub const VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+  BadMagic,
+  UnsupportedVersion(u8),
+  Truncated,
+  Overflow { offset: usize },
+  ChecksumMismatch { expected: u32, actual: u32 },
+  TrailingBytes { offset: usize },
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      DecodeError::BadMagic => write!(f, "Missing the \"INTC\" magic number"),
+      DecodeError::UnsupportedVersion(version) => {
+        write!(f, "Unsupported version {}, expected {}", version, VERSION)
+      }
+      DecodeError::Truncated => write!(f, "Ends partway through"),
+      DecodeError::Overflow { offset } => {
+        write!(f, "Word at byte offset {} is too big", offset)
+      }
+      DecodeError::ChecksumMismatch { expected, actual } => write!(
+        f,
+        "Checksum is {:08x}, but the header says {:08x}",
+        actual, expected
+      ),
+      DecodeError::TrailingBytes { offset } => {
+        write!(
+          f,
+          "Unexpected bytes after the last word at byte offset {}",
+          offset
+        )
+      }
+    }
+  }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Whether `bytes` start with the binary format's magic number.
+pub fn is_binary(bytes: &[u8]) -> bool {
+  bytes.starts_with(&MAGIC)
+}
+
+pub fn encode(sequence: &IntcodeSequence) -> Vec<u8> {
+  let mut payload = Vec::with_capacity(sequence.len() * 2);
+  for word in sequence.iter() {
+    write_varint(&mut payload, zigzag_encode(*word as i64));
+  }
+
+  let mut bytes = Vec::with_capacity(payload.len() + 16);
+  bytes.extend_from_slice(&MAGIC);
+  bytes.push(VERSION);
+  write_varint(&mut bytes, sequence.len() as u64);
+  bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+  bytes.extend_from_slice(&payload);
+  bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<IntcodeSequence, DecodeError> {
+  if !is_binary(bytes) {
+    return Err(DecodeError::BadMagic);
+  }
+  let mut offset = MAGIC.len();
+  let version = *bytes.get(offset).ok_or(DecodeError::Truncated)?;
+  if version != VERSION {
+    return Err(DecodeError::UnsupportedVersion(version));
+  }
+  offset += 1;
+
+  let len = read_varint(bytes, &mut offset)?;
+  let expected_checksum = bytes
+    .get(offset..offset + 4)
+    .ok_or(DecodeError::Truncated)?;
+  let expected_checksum = u32::from_le_bytes(expected_checksum.try_into().unwrap());
+  offset += 4;
+
+  let payload_start = offset;
+  // Every word takes at least one byte, so this also guards the allocation below
+  if len > (bytes.len() - payload_start) as u64 {
+    return Err(DecodeError::Truncated);
+  }
+  let mut sequence = Vec::with_capacity(len as usize);
+  for _ in 0..len {
+    let word_offset = offset;
+    let word = zigzag_decode(read_varint(bytes, &mut offset)?);
+    let word = isize::try_from(word).map_err(|_| DecodeError::Overflow {
+      offset: word_offset,
+    })?;
+    sequence.push(word);
+  }
+  if offset != bytes.len() {
+    return Err(DecodeError::TrailingBytes { offset });
+  }
+
+  let actual_checksum = checksum(&bytes[payload_start..]);
+  if actual_checksum != expected_checksum {
+    return Err(DecodeError::ChecksumMismatch {
+      expected: expected_checksum,
+      actual: actual_checksum,
+    });
+  }
+  Ok(sequence)
+}
+
+fn zigzag_encode(value: i64) -> u64 {
+  ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+  ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    bytes.push((value as u8) | 0x80);
+    value >>= 7;
+  }
+  bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Result<u64, DecodeError> {
+  let start = *offset;
+  let mut value: u64 = 0;
+  let mut shift = 0;
+  loop {
+    let byte = *bytes.get(*offset).ok_or(DecodeError::Truncated)?;
+    *offset += 1;
+    if (shift == 63 && byte > 1) || shift > 63 {
+      return Err(DecodeError::Overflow { offset: start });
+    }
+    value |= u64::from(byte & 0x7f) << shift;
+    if byte & 0x80 == 0 {
+      return Ok(value);
+    }
+    shift += 7;
+  }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+  bytes.iter().fold(0x811c_9dc5, |hash: u32, byte| {
+    (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let sequence = super::super::parse("1101,100,-1,4,0,99,-9999999,123456789");
+    let bytes = encode(&sequence);
+    assert!(is_binary(&bytes));
+    assert_eq!(decode(&bytes), Ok(sequence));
+    assert_eq!(decode(&encode(&vec![])), Ok(vec![]));
+    let extremes = vec![isize::MIN, isize::MAX, 0, -1, 1];
+    assert_eq!(decode(&encode(&extremes)), Ok(extremes));
+  }
+
+  #[test]
+  fn compact() {
+    let sequence = super::super::parse("1,0,0,3,1,1,2,3,1,3,4,3,1,5,0,3,2,1,10,19,99");
+    let text_len = "1,0,0,3,1,1,2,3,1,3,4,3,1,5,0,3,2,1,10,19,99".len();
+    assert!(encode(&sequence).len() < text_len);
+  }
+
+  #[test]
+  fn zigzag() {
+    assert_eq!(zigzag_encode(0), 0);
+    assert_eq!(zigzag_encode(-1), 1);
+    assert_eq!(zigzag_encode(1), 2);
+    assert_eq!(zigzag_encode(-2), 3);
+    for value in [i64::MIN, -300, -1, 0, 1, 300, i64::MAX].iter() {
+      assert_eq!(zigzag_decode(zigzag_encode(*value)), *value);
+    }
+  }
+
+  #[test]
+  fn errors() {
+    let bytes = encode(&vec![1, 2, 3, 99]);
+    assert_eq!(decode(b"1,2,3"), Err(DecodeError::BadMagic));
+
+    let mut wrong_version = bytes.clone();
+    wrong_version[4] = 9;
+    assert_eq!(
+      decode(&wrong_version),
+      Err(DecodeError::UnsupportedVersion(9))
+    );
+
+    assert_eq!(
+      decode(&bytes[..bytes.len() - 1]),
+      Err(DecodeError::Truncated)
+    );
+
+    let mut corrupted = bytes.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] = 0x42;
+    match decode(&corrupted) {
+      Err(DecodeError::ChecksumMismatch { .. }) => (),
+      other => panic!("Expected checksum mismatch, got {:?}", other),
+    }
+
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert_eq!(
+      decode(&trailing),
+      Err(DecodeError::TrailingBytes { offset: 15 })
+    );
+  }
+
+  #[test]
+  fn error_messages() {
+    let mismatch = DecodeError::ChecksumMismatch {
+      expected: 0xdead_beef,
+      actual: 0x42,
+    };
+    assert_eq!(
+      mismatch.to_string(),
+      "Checksum is 00000042, but the header says deadbeef"
+    );
+    assert_eq!(
+      DecodeError::UnsupportedVersion(9).to_string(),
+      "Unsupported version 9, expected 1"
+    );
+    let mut truncated = encode(&vec![1, 2, 3, 99]);
+    truncated.pop();
+    assert_eq!(
+      super::super::load_program(&truncated),
+      Err("Invalid binary program: Ends partway through".into())
+    );
+  }
+}
@@ -8,6 +8,21 @@ pub fn compute_v02(sequence: &mut super::IntcodeSequence) -> usize {
   sequence[0].try_into().unwrap()
 }
 
+/// Like `compute_v02`, but faults if the program uses anything introduced after Day 02.
+pub fn compute_v02_strict(sequence: &mut super::IntcodeSequence) -> Result<usize, super::Fault> {
+  let computer = super::IntcodeComputer::new(sequence.clone())
+    .with_strict_profile(super::Profile::V02)
+    .start();
+  match computer {
+    super::IntcodeComputer::Halt(state) => {
+      *sequence = state.borrow_memory().clone();
+      Ok(sequence[0].try_into().unwrap())
+    }
+    super::IntcodeComputer::Fault(state) => Err(state.fault),
+    _ => unreachable!("V02 programs can't do input or output"),
+  }
+}
+
 pub fn compute_instruction_v02(
   sequence: &mut super::IntcodeSequence,
   instruction_pointer: usize,
@@ -20,7 +35,31 @@ pub fn parse_and_compute_v02(input: &str) -> usize {
   compute_v02(&mut sequence)
 }
 
+/// How a `run_v05` program stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum V05Halt {
+  Halted,
+  /// The program asked for more than the one input
+  MissingInput,
+  Fault(super::Fault),
+}
+
+/// Computes as defined in Day 05, with at most one input. Panics if the program faults or
+/// needs more input.
 pub fn compute_v05(sequence: &mut super::IntcodeSequence, input: Option<isize>) -> Option<isize> {
+  match run_v05(sequence, input) {
+    (output, V05Halt::Halted) => output,
+    (_, V05Halt::MissingInput) => panic!("Program expected input but there was one"),
+    (_, V05Halt::Fault(fault)) => panic!("{}", fault),
+  }
+}
+
+/// Like `compute_v05`, but reports how the program stopped instead of panicking. Returns the
+/// last output, and leaves `sequence` as the memory at the point it stopped.
+pub fn run_v05(
+  sequence: &mut super::IntcodeSequence,
+  input: Option<isize>,
+) -> (Option<isize>, V05Halt) {
   let mut input = input;
   let mut output: Option<isize> = None;
   let computer = super::IntcodeComputer::new(sequence.clone());
@@ -32,7 +71,10 @@ pub fn compute_v05(sequence: &mut super::IntcodeSequence, input: Option<isize>)
           computer = state.execute(input_value);
           input = None;
         }
-        None => panic!("Program expected input but there was one"),
+        None => {
+          *sequence = state.borrow_memory().clone();
+          return (output, V05Halt::MissingInput);
+        }
       },
       super::IntcodeComputer::Output(state) => {
         output = Some(state.output);
@@ -40,8 +82,13 @@ pub fn compute_v05(sequence: &mut super::IntcodeSequence, input: Option<isize>)
       }
       super::IntcodeComputer::Halt(state) => {
         *sequence = state.borrow_memory().clone();
-        return output;
+        return (output, V05Halt::Halted);
+      }
+      super::IntcodeComputer::Fault(state) => {
+        *sequence = state.borrow_memory().clone();
+        return (output, V05Halt::Fault(state.fault));
       }
+      super::IntcodeComputer::Break(_) => unreachable!("No debugger is attached"),
     }
   }
 }
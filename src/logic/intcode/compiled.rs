@@ -0,0 +1,484 @@
+// Runs programs by translating them ahead of time into closures, one per basic block.
+//
+// Opcodes and parameter modes are decoded once, when the program is compiled, but
+// parameters are still read from memory as each instruction runs. That keeps compiled
+// code valid when only parameters change, as with day02's noun and verb. If the program
+// overwrites an opcode that was compiled, the run falls back to the interpreter.
+//
+// Only the standard instruction set is supported, overflowing arithmetic faults as under
+// `OverflowPolicy::Fault`, and runs don't keep statistics or call observers.
+//
+// `benches/engines.rs` times it against `IntcodeComputer` and `Batch`. `Batch` wins when
+// many runs of one program can go in lockstep, so searches and day07 use that; this is for
+// runs that each go their own way, like a single long run or amplifiers fed one at a time.
+
+use super::disasm::{CodeMap, DecodedInstruction};
+use super::instructions::STANDARD_INSTRUCTION_SET;
+use super::patch::PatchError;
+use super::{Fault, InstructionSet, IntcodeSequence, PatchSet, ProgramState};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+type BlockFn = dyn Fn(&mut [isize]) -> Exit + Send + Sync;
+type OpFn = dyn Fn(&mut [isize]) -> Op + Send + Sync;
+type ExitFn = dyn Fn(&mut [isize]) -> Exit + Send + Sync;
+
+/// How a block finished.
+enum Exit {
+  Continue(usize),
+  /// At the input instruction at this address
+  Input(usize),
+  Output {
+    output: isize,
+    pointer: usize,
+  },
+  /// At the halt instruction at this address
+  Halt(usize),
+  /// An instruction overwrote an opcode; carry on from `pointer` with the interpreter
+  CodeModified(usize),
+  /// The instruction at this address overflowed; let the interpreter report it
+  Overflow(usize),
+}
+
+/// How a single compiled instruction finished.
+enum Op {
+  Continue,
+  CodeModified,
+  Overflow,
+}
+
+/// Why a `CompiledRun` stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+  NeedsInput,
+  Output(isize),
+  Halt,
+  Fault(Fault),
+}
+
+fn to_address(value: isize) -> usize {
+  usize::try_from(value).unwrap()
+}
+
+fn read(memory: &[isize], address: usize, mode: u8) -> isize {
+  let value = memory[address];
+  if mode == 1 {
+    value
+  } else {
+    memory[to_address(value)]
+  }
+}
+
+/// Writes to the address in `param_address`, returning whether it held a compiled opcode.
+fn write(memory: &mut [isize], opcodes: &[bool], param_address: usize, value: isize) -> bool {
+  let address = to_address(memory[param_address]);
+  memory[address] = value;
+  opcodes.get(address) == Some(&true)
+}
+
+pub struct CompiledProgram {
+  sequence: IntcodeSequence,
+  /// Indexed by the address each block starts at
+  blocks: Vec<Option<Box<BlockFn>>>,
+  /// Which addresses hold the opcode of a compiled instruction
+  opcodes: Arc<Vec<bool>>,
+}
+
+impl CompiledProgram {
+  pub fn new(sequence: &IntcodeSequence) -> CompiledProgram {
+    let code_map = CodeMap::new(sequence, &InstructionSet::standard());
+    let mut opcodes = vec![false; sequence.len()];
+    for address in code_map.instructions.keys() {
+      opcodes[*address] = true;
+    }
+    let opcodes = Arc::new(opcodes);
+
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for instruction in code_map.instructions.values() {
+      if ends_block(instruction) {
+        leaders.insert(instruction.address + instruction.len());
+      }
+      if let Some(target) = instruction.static_jump_target() {
+        leaders.insert(target);
+      }
+    }
+
+    let mut blocks: Vec<Option<Box<BlockFn>>> = (0..sequence.len()).map(|_| None).collect();
+    for leader in leaders.iter().cloned() {
+      // Each op with the address it's at and the one after it
+      let mut ops: Vec<(Box<OpFn>, usize, usize)> = vec![];
+      let mut address = leader;
+      let exit = loop {
+        let instruction = match code_map.instructions.get(&address) {
+          Some(instruction) => instruction,
+          None => break continue_at(address),
+        };
+        address += instruction.len();
+        if ends_block(instruction) {
+          break compile_exit(instruction);
+        }
+        let op = compile_op(instruction, opcodes.clone());
+        ops.push((op, instruction.address, address));
+        if leaders.contains(&address) {
+          break continue_at(address);
+        }
+      };
+      if code_map.instructions.contains_key(&leader) {
+        blocks[leader] = Some(Box::new(move |memory: &mut [isize]| {
+          for (op, address, next) in ops.iter() {
+            match op(memory) {
+              Op::Continue => (),
+              Op::CodeModified => return Exit::CodeModified(*next),
+              Op::Overflow => return Exit::Overflow(*address),
+            }
+          }
+          exit(memory)
+        }));
+      }
+    }
+
+    CompiledProgram {
+      sequence: sequence.clone(),
+      blocks,
+      opcodes,
+    }
+  }
+
+  pub fn block_count(&self) -> usize {
+    self.blocks.iter().filter(|block| block.is_some()).count()
+  }
+
+  pub fn start(&self) -> CompiledRun<'_> {
+    CompiledRun {
+      program: self,
+      memory: self.sequence.clone(),
+      pointer: 0,
+      interpreting: false,
+      waiting_for_input: false,
+    }
+  }
+
+  /// Starts a run with `patches` applied. Patching parameters keeps the compiled code,
+  /// but patching an opcode means interpreting the whole run.
+  pub fn start_patched(&self, patches: &PatchSet) -> Result<CompiledRun<'_>, PatchError> {
+    let mut run = self.start();
+    let originals = patches.apply(&mut run.memory)?;
+    run.interpreting = originals
+      .iter()
+      .any(|patch| self.is_opcode(patch.address) && run.memory[patch.address] != patch.value);
+    Ok(run)
+  }
+
+  fn is_opcode(&self, address: usize) -> bool {
+    self.opcodes.get(address) == Some(&true)
+  }
+}
+
+fn ends_block(instruction: &DecodedInstruction) -> bool {
+  matches!(instruction.opcode, 3 | 4 | 5 | 6 | 99)
+}
+
+fn continue_at(pointer: usize) -> Box<ExitFn> {
+  Box::new(move |_| Exit::Continue(pointer))
+}
+
+fn compile_op(instruction: &DecodedInstruction, opcodes: Arc<Vec<bool>>) -> Box<OpFn> {
+  let address = instruction.address;
+  let (m0, m1) = (
+    instruction.parameter_modes[0],
+    instruction.parameter_modes[1],
+  );
+  let operation: fn(isize, isize) -> Option<isize> = match instruction.opcode {
+    1 => |a, b| a.checked_add(b),
+    2 => |a, b| a.checked_mul(b),
+    7 => |a, b| Some(if a < b { 1 } else { 0 }),
+    8 => |a, b| Some(if a == b { 1 } else { 0 }),
+    opcode => unreachable!("Opcode {} doesn't compile to an operation", opcode),
+  };
+  Box::new(move |memory| {
+    match operation(read(memory, address + 1, m0), read(memory, address + 2, m1)) {
+      Some(value) if write(memory, &opcodes, address + 3, value) => Op::CodeModified,
+      Some(_) => Op::Continue,
+      None => Op::Overflow,
+    }
+  })
+}
+
+fn compile_exit(instruction: &DecodedInstruction) -> Box<ExitFn> {
+  let address = instruction.address;
+  let next = address + instruction.len();
+  let modes = instruction.parameter_modes.clone();
+  match instruction.opcode {
+    3 => Box::new(move |_| Exit::Input(address)),
+    4 => Box::new(move |memory| Exit::Output {
+      output: read(memory, address + 1, modes[0]),
+      pointer: next,
+    }),
+    5 | 6 => {
+      let jump_if = instruction.opcode == 5;
+      Box::new(move |memory| {
+        if (read(memory, address + 1, modes[0]) != 0) == jump_if {
+          Exit::Continue(to_address(read(memory, address + 2, modes[1])))
+        } else {
+          Exit::Continue(next)
+        }
+      })
+    }
+    _ => Box::new(move |_| Exit::Halt(address)),
+  }
+}
+
+/// One run of a compiled program, with its own memory.
+pub struct CompiledRun<'a> {
+  program: &'a CompiledProgram,
+  memory: IntcodeSequence,
+  pointer: usize,
+  interpreting: bool,
+  waiting_for_input: bool,
+}
+
+impl CompiledRun<'_> {
+  pub fn memory(&self) -> &IntcodeSequence {
+    &self.memory
+  }
+
+  pub fn into_memory(self) -> IntcodeSequence {
+    self.memory
+  }
+
+  pub fn pointer(&self) -> usize {
+    self.pointer
+  }
+
+  /// Whether the run has fallen back to the interpreter for good.
+  pub fn is_interpreting(&self) -> bool {
+    self.interpreting
+  }
+
+  /// Runs until the program needs input, outputs, halts or faults.
+  pub fn resume(&mut self) -> Stop {
+    if self.waiting_for_input {
+      return Stop::NeedsInput;
+    }
+    loop {
+      if !self.interpreting {
+        if let Some(Some(block)) = self.program.blocks.get(self.pointer) {
+          match block(&mut self.memory) {
+            Exit::Continue(pointer) => self.pointer = pointer,
+            Exit::Input(pointer) => {
+              self.pointer = pointer;
+              self.waiting_for_input = true;
+              return Stop::NeedsInput;
+            }
+            Exit::Output { output, pointer } => {
+              self.pointer = pointer;
+              return Stop::Output(output);
+            }
+            Exit::Halt(pointer) => {
+              self.pointer = pointer;
+              return Stop::Halt;
+            }
+            Exit::CodeModified(pointer) => {
+              self.pointer = pointer;
+              self.interpreting = true;
+            }
+            Exit::Overflow(pointer) => {
+              self.pointer = pointer;
+              if let Some(stop) = self.interpret() {
+                return stop;
+              }
+            }
+          }
+          continue;
+        }
+      }
+      if let Some(stop) = self.interpret() {
+        return stop;
+      }
+    }
+  }
+
+  /// Executes the instruction at the pointer with the interpreter.
+  fn interpret(&mut self) -> Option<Stop> {
+    if !self.interpreting {
+      // The interpreter doesn't report where it writes, so check before it does
+      if let Ok(def) = STANDARD_INSTRUCTION_SET.decode(&self.memory, self.pointer) {
+        let program = self.program;
+        let pointer = self.pointer;
+        let memory = &self.memory;
+        self.interpreting = def.writes.iter().any(|param| {
+          memory
+            .get(pointer + 1 + usize::from(*param))
+            .and_then(|address| usize::try_from(*address).ok())
+            .is_some_and(|address| program.is_opcode(address))
+        });
+      }
+    }
+    match STANDARD_INSTRUCTION_SET.compute_instruction(&mut self.memory, self.pointer) {
+      ProgramState::Continue(pointer) => {
+        self.pointer = pointer;
+        None
+      }
+      ProgramState::WaitForInput => {
+        self.waiting_for_input = true;
+        Some(Stop::NeedsInput)
+      }
+      ProgramState::OutputAndContinue { pointer, output } => {
+        self.pointer = pointer;
+        Some(Stop::Output(output))
+      }
+      ProgramState::Halt => Some(Stop::Halt),
+      ProgramState::Fault(fault) => Some(Stop::Fault(fault)),
+    }
+  }
+
+  /// Supplies the input the program is waiting for. Call `resume` to carry on.
+  pub fn provide_input(&mut self, input: isize) {
+    assert!(
+      self.waiting_for_input,
+      "The program isn't waiting for input"
+    );
+    self.waiting_for_input = false;
+    if write(
+      &mut self.memory,
+      &self.program.opcodes,
+      self.pointer + 1,
+      input,
+    ) {
+      self.interpreting = true;
+    }
+    self.pointer += 2;
+  }
+
+  /// Runs to completion, feeding in `inputs` in order. Returns the outputs, or why the
+  /// program stopped early.
+  pub fn run_to_halt(&mut self, inputs: &[isize]) -> Result<Vec<isize>, Stop> {
+    let mut inputs = inputs.iter();
+    let mut outputs = vec![];
+    loop {
+      match self.resume() {
+        Stop::NeedsInput => match inputs.next() {
+          Some(input) => self.provide_input(*input),
+          None => return Err(Stop::NeedsInput),
+        },
+        Stop::Output(output) => outputs.push(output),
+        Stop::Halt => return Ok(outputs),
+        Stop::Fault(fault) => return Err(Stop::Fault(fault)),
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::fuzz::{self, Engine, HaltKind, Outputs, ProgramGenerator, RunOutcome};
+  use super::super::{compat, parse};
+  use super::*;
+
+  /// Compiles every program it's given, for comparing against the interpreter.
+  struct CompiledEngine;
+
+  impl Engine for CompiledEngine {
+    fn name(&self) -> &str {
+      "CompiledProgram"
+    }
+
+    fn run(&self, sequence: &IntcodeSequence, inputs: &[isize]) -> RunOutcome {
+      let program = CompiledProgram::new(sequence);
+      let mut run = program.start();
+      let (outputs, halt) = match run.run_to_halt(inputs) {
+        Ok(outputs) => (outputs, HaltKind::Halted),
+        Err(Stop::NeedsInput) => (vec![], HaltKind::NeedsInput),
+        Err(Stop::Fault(fault)) => (vec![], HaltKind::Faulted(fault)),
+        Err(stop) => unreachable!("{:?}", stop),
+      };
+      RunOutcome {
+        outputs: Outputs::All(outputs),
+        memory: Some(run.into_memory()),
+        halt,
+      }
+    }
+  }
+
+  #[test]
+  fn matches_interpreter() {
+    let engines: [&dyn Engine; 2] = [&fuzz::StateMachineEngine, &CompiledEngine];
+    if let Err(divergence) = fuzz::differential_test(&engines, &ProgramGenerator::default(), 0, 500)
+    {
+      panic!("{:#?}", divergence);
+    }
+  }
+
+  #[test]
+  fn runs_day_five_programs() {
+    // Outputs 999, 1000 or 1001 as the input is below, equal to or above 8
+    let program = CompiledProgram::new(&parse(
+      "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99",
+    ));
+    for (input, expected) in [(7, 999), (8, 1000), (9, 1001)] {
+      let mut run = program.start();
+      assert_eq!(run.run_to_halt(&[input]), Ok(vec![expected]));
+      assert!(!run.is_interpreting());
+    }
+  }
+
+  #[test]
+  fn patched_parameters_stay_compiled() {
+    let sequence = parse("1,0,0,7,99,30,40,0");
+    let program = CompiledProgram::new(&sequence);
+    let mut run = program.start_patched(&"1=5,2=6".parse().unwrap()).unwrap();
+    assert_eq!(run.run_to_halt(&[]), Ok(vec![]));
+    assert_eq!(run.memory()[7], 70);
+    assert!(!run.is_interpreting());
+
+    let run = program.start_patched(&"0=2".parse().unwrap()).unwrap();
+    assert!(run.is_interpreting());
+  }
+
+  #[test]
+  fn falls_back_on_self_modification() {
+    // The first instruction turns the 33 at address 4 into a halt
+    let sequence = parse("1002,4,3,4,33");
+    let program = CompiledProgram::new(&sequence);
+    let mut run = program.start();
+    assert_eq!(run.run_to_halt(&[]), Ok(vec![]));
+    assert_eq!(run.memory()[4], 99);
+
+    // Overwrites the add at 4 with a multiply before it runs
+    let sequence = parse("1101,1101,1,4,1101,3,3,0,99");
+    let program = CompiledProgram::new(&sequence);
+    let mut run = program.start();
+    assert_eq!(run.run_to_halt(&[]), Ok(vec![]));
+    assert!(run.is_interpreting());
+    let mut expected = sequence.clone();
+    compat::compute_v05(&mut expected, None);
+    assert_eq!(run.memory(), &expected);
+    assert_eq!(run.memory()[0], 9);
+  }
+
+  #[test]
+  fn input_can_modify_code() {
+    // Reads over the opcode of the add at 2
+    let program = CompiledProgram::new(&parse("3,2,1101,2,3,0,99"));
+    let mut run = program.start();
+    assert_eq!(run.resume(), Stop::NeedsInput);
+    run.provide_input(1102);
+    assert!(run.is_interpreting());
+    assert_eq!(run.resume(), Stop::Halt);
+    assert_eq!(run.memory()[0], 6);
+  }
+
+  #[test]
+  fn overflow_faults() {
+    // Doubles address 9 until it overflows
+    let program = CompiledProgram::new(&parse("4,9,1002,9,2,9,1105,1,0,1"));
+    let mut run = program.start();
+    let outputs = run.run_to_halt(&[]);
+    assert_eq!(outputs, Err(Stop::Fault(Fault::Overflow { pointer: 2 })));
+    assert_eq!(run.pointer(), 2);
+    assert_eq!(run.memory()[9], 1 << 62);
+    assert!(!run.is_interpreting());
+  }
+}
@@ -0,0 +1,426 @@
+// Breakpoints, watchpoints and stepping, for pausing a program partway through.
+
+use super::disasm::{decode_at, DecodedInstruction};
+use super::word::Word;
+use super::{InstructionSet, IntcodeMemory};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+type Condition<W> = dyn Fn(&IntcodeMemory<W>) -> bool + Send + Sync;
+
+/// Which memory accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+  Read,
+  Write,
+  ReadWrite,
+}
+
+impl Access {
+  fn includes(self, access: Access) -> bool {
+    self == Access::ReadWrite || self == access
+  }
+}
+
+/// Why a computer stopped in `IntcodeComputer::Break`. Except after a step over an
+/// output, the instruction at the pointer hasn't run yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakReason<W = isize> {
+  Breakpoint(usize),
+  Watchpoint {
+    address: usize,
+    access: Access,
+  },
+  /// A single step finished or the debugger was paused. `output` is the value output by
+  /// the stepped-over instruction.
+  Step {
+    output: Option<W>,
+  },
+}
+
+impl<W: fmt::Display> fmt::Display for BreakReason<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      BreakReason::Breakpoint(address) => write!(f, "Breakpoint at {}", address),
+      BreakReason::Watchpoint { address, access } => {
+        write!(f, "Watchpoint on {:?} of {}", access, address)
+      }
+      BreakReason::Step { output: None } => write!(f, "Step"),
+      BreakReason::Step {
+        output: Some(output),
+      } => write!(f, "Step over output {}", output),
+    }
+  }
+}
+
+/// The computer's registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+  pub pointer: usize,
+  pub relative_base: isize,
+}
+
+struct Breakpoint<W> {
+  condition: Option<Arc<Condition<W>>>,
+}
+
+// Not derived, since that would require `W: Clone`
+impl<W> Clone for Breakpoint<W> {
+  fn clone(&self) -> Self {
+    Breakpoint {
+      condition: self.condition.clone(),
+    }
+  }
+}
+
+/// Breakpoints and watchpoints for one computer. Attach with
+/// `IntcodeComputerStart::with_debugger`, then use `IntcodeComputerState::debugger_mut`
+/// to change them at any point.
+pub struct Debugger<W = isize> {
+  breakpoints: BTreeMap<usize, Breakpoint<W>>,
+  watchpoints: BTreeMap<usize, Access>,
+  /// Lets the instruction at the pointer run after a break, rather than breaking again
+  resuming: bool,
+  stepping: bool,
+  stepping_over_output: bool,
+}
+
+impl<W: Word> Debugger<W> {
+  pub fn new() -> Self {
+    Debugger {
+      breakpoints: BTreeMap::new(),
+      watchpoints: BTreeMap::new(),
+      resuming: false,
+      stepping: false,
+      stepping_over_output: false,
+    }
+  }
+
+  pub fn add_breakpoint(&mut self, address: usize) {
+    self
+      .breakpoints
+      .insert(address, Breakpoint { condition: None });
+  }
+
+  /// Breaks at `address` only when `condition` holds for the memory at that point.
+  pub fn add_conditional_breakpoint<F>(&mut self, address: usize, condition: F)
+  where
+    F: Fn(&IntcodeMemory<W>) -> bool + Send + Sync + 'static,
+  {
+    self.breakpoints.insert(
+      address,
+      Breakpoint {
+        condition: Some(Arc::new(condition)),
+      },
+    );
+  }
+
+  pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+    self.breakpoints.remove(&address).is_some()
+  }
+
+  pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+    self.breakpoints.keys().cloned()
+  }
+
+  /// Breaks before any instruction that reads or writes `address` through one of its
+  /// parameters.
+  pub fn add_watchpoint(&mut self, address: usize, access: Access) {
+    self.watchpoints.insert(address, access);
+  }
+
+  pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+    self.watchpoints.remove(&address).is_some()
+  }
+
+  pub fn watchpoints(&self) -> impl Iterator<Item = (usize, Access)> + '_ {
+    self
+      .watchpoints
+      .iter()
+      .map(|(address, access)| (*address, *access))
+  }
+
+  /// Breaks before the next instruction, e.g. to stop straight after an output.
+  pub fn pause(&mut self) {
+    self.resuming = false;
+    self.stepping = true;
+    self.stepping_over_output = false;
+  }
+
+  pub(super) fn resume(&mut self) {
+    self.resuming = true;
+    self.stepping = false;
+    self.stepping_over_output = false;
+  }
+
+  pub(super) fn step(&mut self, over_output: bool) {
+    self.resuming = true;
+    self.stepping = true;
+    self.stepping_over_output = over_output;
+  }
+
+  /// Whether an output should end the current step instead of stopping the computer in
+  /// `IntcodeComputer::Output`.
+  pub(super) fn take_output_step(&mut self) -> bool {
+    let take = self.stepping && self.stepping_over_output;
+    if take {
+      self.stepping = false;
+      self.stepping_over_output = false;
+    }
+    take
+  }
+
+  /// Called before each instruction executes.
+  pub(super) fn check(
+    &mut self,
+    sequence: &IntcodeMemory<W>,
+    pointer: usize,
+    relative_base: isize,
+    instruction_set: &InstructionSet<W>,
+  ) -> Option<BreakReason<W>> {
+    if self.resuming {
+      self.resuming = false;
+      return None;
+    }
+    if self.stepping {
+      self.stepping = false;
+      self.stepping_over_output = false;
+      return Some(BreakReason::Step { output: None });
+    }
+    if let Some(breakpoint) = self.breakpoints.get(&pointer) {
+      let hit = match &breakpoint.condition {
+        Some(condition) => condition(sequence),
+        None => true,
+      };
+      if hit {
+        return Some(BreakReason::Breakpoint(pointer));
+      }
+    }
+    if self.watchpoints.is_empty() {
+      return None;
+    }
+    let instruction = decode_at(sequence, pointer, instruction_set)?;
+    let hit = accesses(&instruction, relative_base).find(|(address, access)| {
+      self
+        .watchpoints
+        .get(address)
+        .is_some_and(|watched| watched.includes(*access))
+    });
+    hit.map(|(address, access)| BreakReason::Watchpoint { address, access })
+  }
+}
+
+impl<W> Clone for Debugger<W> {
+  fn clone(&self) -> Self {
+    Debugger {
+      breakpoints: self.breakpoints.clone(),
+      watchpoints: self.watchpoints.clone(),
+      resuming: self.resuming,
+      stepping: self.stepping,
+      stepping_over_output: self.stepping_over_output,
+    }
+  }
+}
+
+impl<W: Word> Default for Debugger<W> {
+  fn default() -> Self {
+    Debugger::new()
+  }
+}
+
+impl<W> fmt::Debug for Debugger<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Debugger")
+      .field("breakpoints", &self.breakpoints.keys().collect::<Vec<_>>())
+      .field("watchpoints", &self.watchpoints)
+      .finish()
+  }
+}
+
+/// The memory an instruction reads and writes through its position and relative mode
+/// parameters.
+fn accesses<W: Word>(
+  instruction: &DecodedInstruction<W>,
+  relative_base: isize,
+) -> impl Iterator<Item = (usize, Access)> + '_ {
+  (0..instruction.parameters.len()).filter_map(move |i| {
+    let access = if instruction.writes.contains(&(i as u8)) {
+      Access::Write
+    } else {
+      Access::Read
+    };
+    instruction
+      .address(i, relative_base)
+      .map(|address| (address, access))
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, IntcodeComputer, IntcodeComputerState};
+  use super::*;
+
+  // Counts address 11 down from 3, outputting each value
+  const COUNTDOWN: &str = "1001,11,-1,11,4,11,1005,11,0,99,0,3";
+
+  fn start(program: &str, setup: impl FnOnce(&mut Debugger)) -> IntcodeComputer {
+    let mut debugger = Debugger::new();
+    setup(&mut debugger);
+    IntcodeComputer::new(parse(program))
+      .with_debugger(debugger)
+      .start()
+  }
+
+  #[test]
+  fn breakpoint() {
+    let computer = start(COUNTDOWN, |debugger| debugger.add_breakpoint(4))
+      .as_break()
+      .unwrap();
+    assert_eq!(computer.reason, BreakReason::Breakpoint(4));
+    assert_eq!(
+      computer.registers(),
+      Registers {
+        pointer: 4,
+        relative_base: 0
+      }
+    );
+    assert_eq!(computer.borrow_memory()[11], 2);
+    assert_eq!(
+      computer.current_instruction().unwrap().to_string(),
+      "out [11]"
+    );
+
+    let computer = computer.resume().as_output().unwrap();
+    assert_eq!(computer.output, 2);
+    let computer = computer.execute().as_break().unwrap();
+    assert_eq!(computer.borrow_memory()[11], 1);
+  }
+
+  #[test]
+  fn conditional_breakpoint() {
+    let computer = start(COUNTDOWN, |debugger| {
+      debugger.add_conditional_breakpoint(0, |memory| memory[11] == 1)
+    });
+    let mut outputs = vec![];
+    let mut computer = computer;
+    while let IntcodeComputer::Output(state) = computer {
+      outputs.push(state.output);
+      computer = state.execute();
+    }
+    assert_eq!(outputs, vec![2, 1]);
+    let computer = computer.as_break().unwrap();
+    assert_eq!(computer.reason, BreakReason::Breakpoint(0));
+  }
+
+  #[test]
+  fn relative_watchpoints() {
+    // Outputs address 5 through the relative base
+    let mut debugger = Debugger::new();
+    debugger.add_watchpoint(5, Access::Read);
+    let computer = IntcodeComputer::new(parse("109,5,204,0,99,7"))
+      .with_instruction_set(InstructionSet::relative())
+      .with_debugger(debugger)
+      .start()
+      .as_break()
+      .unwrap();
+    assert_eq!(computer.get_pointer(), 2);
+    assert_eq!(
+      computer.registers(),
+      Registers {
+        pointer: 2,
+        relative_base: 5
+      }
+    );
+    assert_eq!(
+      computer.current_instruction().unwrap().to_string(),
+      "out rb[0]"
+    );
+  }
+
+  #[test]
+  fn watchpoints() {
+    let computer = start(COUNTDOWN, |debugger| {
+      debugger.add_watchpoint(11, Access::Write)
+    })
+    .as_break()
+    .unwrap();
+    assert_eq!(
+      computer.reason,
+      BreakReason::Watchpoint {
+        address: 11,
+        access: Access::Write
+      }
+    );
+    assert_eq!(computer.get_pointer(), 0);
+
+    let mut computer = start(COUNTDOWN, |debugger| {
+      debugger.add_watchpoint(11, Access::Read)
+    })
+    .as_break()
+    .unwrap();
+    assert_eq!(computer.get_pointer(), 0);
+    computer.debugger_mut().unwrap().remove_watchpoint(11);
+    computer
+      .debugger_mut()
+      .unwrap()
+      .add_watchpoint(11, Access::Write);
+    let computer = computer
+      .resume()
+      .as_output()
+      .unwrap()
+      .execute()
+      .as_break()
+      .unwrap();
+    assert_eq!(computer.get_pointer(), 0);
+    assert_eq!(computer.borrow_memory()[11], 2);
+  }
+
+  #[test]
+  fn steps() {
+    let computer = start(COUNTDOWN, |debugger| debugger.add_breakpoint(0))
+      .as_break()
+      .unwrap();
+    let computer = computer.step().as_break().unwrap();
+    assert_eq!(computer.reason, BreakReason::Step { output: None });
+    assert_eq!(computer.get_pointer(), 4);
+
+    // A plain step stops at the output like running normally would
+    let computer = computer.step().as_output().unwrap();
+    assert_eq!(computer.output, 2);
+    let computer = computer.execute().as_break().unwrap();
+    assert_eq!(computer.get_pointer(), 6);
+  }
+
+  #[test]
+  fn steps_over_output() {
+    let computer = start(COUNTDOWN, |debugger| debugger.add_breakpoint(4))
+      .as_break()
+      .unwrap();
+    let computer = computer.step_over_output().as_break().unwrap();
+    assert_eq!(computer.reason, BreakReason::Step { output: Some(2) });
+    assert_eq!(computer.get_pointer(), 6);
+    assert_eq!(computer.stats().outputs_emitted, 1);
+  }
+
+  #[test]
+  fn pauses() {
+    let computer = start(COUNTDOWN, |debugger| debugger.pause())
+      .as_break()
+      .unwrap();
+    assert_eq!(computer.get_pointer(), 0);
+
+    let mut computer = computer.resume().as_output().unwrap();
+    computer.debugger_mut().unwrap().pause();
+    let computer = computer.execute().as_break().unwrap();
+    assert_eq!(computer.get_pointer(), 6);
+  }
+
+  #[test]
+  fn runs_normally_without_breakpoints() {
+    let computer = start("3,0,4,0,99", |_| ()).as_input().unwrap();
+    let computer = computer.execute(5).as_output().unwrap();
+    assert_eq!(computer.output, 5);
+    assert!(computer.debugger().is_some());
+  }
+}
@@ -0,0 +1,345 @@
+// Memory-mapped peripherals: address ranges whose reads and writes go to a device
+// instead of RAM.
+
+use super::fuzz::Rng;
+use super::word::Word;
+use super::IntcodeMemory;
+use std::any::Any;
+use std::fmt;
+use std::ops::Range;
+
+/// Something mapped into a computer's memory with `IntcodeComputerStart::with_device`.
+/// Offsets are relative to the start of the device's address range.
+///
+/// Only position and relative mode parameters are routed to devices, so the RAM underneath is still
+/// there for instructions to be fetched from and for tools that inspect memory. Parameters
+/// an instruction writes to are never read from the device, so reads only happen when a
+/// program actually uses the value. Device writes aren't reported to observers.
+pub trait Device<W: Word = isize>: Any + Send {
+  fn read(&mut self, offset: usize) -> W;
+
+  fn write(&mut self, offset: usize, value: W);
+
+  /// Called after every instruction the computer executes.
+  fn tick(&mut self) {}
+}
+
+/// The devices attached to a computer.
+pub(super) struct DeviceMap<W: Word> {
+  devices: Vec<(Range<usize>, Box<dyn Device<W>>)>,
+  /// Whether a device was read or written since the last `take_accessed`
+  accessed: bool,
+}
+
+impl<W: Word> DeviceMap<W> {
+  pub(super) fn new() -> DeviceMap<W> {
+    DeviceMap {
+      devices: vec![],
+      accessed: false,
+    }
+  }
+
+  pub(super) fn attach(&mut self, range: Range<usize>, device: Box<dyn Device<W>>) {
+    assert!(
+      self
+        .devices
+        .iter()
+        .all(|(mapped, _)| range.end <= mapped.start || range.start >= mapped.end),
+      "Device at {:?} overlaps another device",
+      range
+    );
+    self.devices.push((range, device));
+  }
+
+  fn find(&mut self, address: usize) -> Option<(&mut dyn Device<W>, usize)> {
+    self
+      .devices
+      .iter_mut()
+      .find(|(range, _)| range.contains(&address))
+      .map(|(range, device)| (device.as_mut(), address - range.start))
+  }
+
+  pub(super) fn is_mapped(&self, address: usize) -> bool {
+    self
+      .devices
+      .iter()
+      .any(|(range, _)| range.contains(&address))
+  }
+
+  /// Reads `address` from its device, or from `memory` if no device is mapped there.
+  pub(super) fn read(&mut self, memory: &IntcodeMemory<W>, address: usize) -> W {
+    match self.find(address) {
+      Some((device, offset)) => {
+        let value = device.read(offset);
+        self.accessed = true;
+        value
+      }
+      None => memory[address].clone(),
+    }
+  }
+
+  /// Writes `value` to the device at `address`, or gives it back if no device is mapped
+  /// there, for the caller to write to RAM.
+  pub(super) fn write(&mut self, address: usize, value: W) -> Option<W> {
+    match self.find(address) {
+      Some((device, offset)) => {
+        device.write(offset, value);
+        self.accessed = true;
+        None
+      }
+      None => Some(value),
+    }
+  }
+
+  pub(super) fn tick(&mut self) {
+    for (_, device) in self.devices.iter_mut() {
+      device.tick();
+    }
+  }
+
+  pub(super) fn take_accessed(&mut self) -> bool {
+    std::mem::replace(&mut self.accessed, false)
+  }
+
+  /// The first attached device that's a `T`.
+  pub(super) fn downcast_ref<T: Device<W>>(&self) -> Option<&T> {
+    self
+      .devices
+      .iter()
+      .find_map(|(_, device)| (device.as_ref() as &dyn Any).downcast_ref())
+  }
+
+  pub(super) fn downcast_mut<T: Device<W>>(&mut self) -> Option<&mut T> {
+    self
+      .devices
+      .iter_mut()
+      .find_map(|(_, device)| (device.as_mut() as &mut dyn Any).downcast_mut())
+  }
+}
+
+impl<W: Word> fmt::Debug for DeviceMap<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_list()
+      .entries(self.devices.iter().map(|(range, _)| range))
+      .finish()
+  }
+}
+
+/// Counts the instructions executed since it was attached. Writing sets the count.
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+  pub ticks: u64,
+}
+
+impl Clock {
+  pub fn new() -> Clock {
+    Clock::default()
+  }
+}
+
+impl<W: Word> Device<W> for Clock {
+  fn read(&mut self, _offset: usize) -> W {
+    W::from_isize(self.ticks as isize)
+  }
+
+  fn write(&mut self, _offset: usize, value: W) {
+    self.ticks = value.to_isize().map_or(0, |ticks| ticks.max(0) as u64);
+  }
+
+  fn tick(&mut self) {
+    self.ticks += 1;
+  }
+}
+
+/// Gives a new random number in `0..2^31` on every read, from a fixed seed so runs can be
+/// repeated. Writing reseeds it.
+#[derive(Debug, Clone)]
+pub struct Random {
+  rng: Rng,
+}
+
+impl Random {
+  pub fn new(seed: u64) -> Random {
+    Random {
+      rng: Rng::new(seed),
+    }
+  }
+}
+
+impl<W: Word> Device<W> for Random {
+  fn read(&mut self, _offset: usize) -> W {
+    W::from_isize((self.rng.next_u64() >> 33) as isize)
+  }
+
+  fn write(&mut self, _offset: usize, value: W) {
+    self.rng = Rng::new(value.to_isize().unwrap_or(0) as u64);
+  }
+}
+
+/// A `width` by `height` grid of pixels, one word each, row by row. Map it to a range of
+/// `width * height` addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer<W = isize> {
+  pub width: usize,
+  pub height: usize,
+  pixels: Vec<W>,
+}
+
+impl<W: Word> Framebuffer<W> {
+  pub fn new(width: usize, height: usize) -> Framebuffer<W> {
+    Framebuffer {
+      width,
+      height,
+      pixels: vec![W::from_isize(0); width * height],
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.pixels.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.pixels.is_empty()
+  }
+
+  pub fn pixel(&self, x: usize, y: usize) -> &W {
+    &self.pixels[y * self.width + x]
+  }
+}
+
+impl<W: Word> Device<W> for Framebuffer<W> {
+  fn read(&mut self, offset: usize) -> W {
+    self.pixels[offset].clone()
+  }
+
+  fn write(&mut self, offset: usize, value: W) {
+    self.pixels[offset] = value;
+  }
+}
+
+/// Draws set pixels as `#` and clear ones as `.`.
+impl<W: Word> fmt::Display for Framebuffer<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for row in self.pixels.chunks(self.width) {
+      let line: String = row
+        .iter()
+        .map(|pixel| if pixel.is_zero() { '.' } else { '#' })
+        .collect();
+      writeln!(f, "{}", line)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, Fault, IntcodeComputer, IntcodeComputerState};
+  use super::*;
+
+  #[test]
+  fn reads_clock() {
+    // Outputs the clock at address 9 twice
+    let computer = IntcodeComputer::new(parse("4,9,1101,0,0,10,4,9,99,0,0"))
+      .with_device(9..10, Clock::new())
+      .start()
+      .as_output()
+      .unwrap();
+    assert_eq!(computer.output, 0);
+    let computer = computer.execute().as_output().unwrap();
+    assert_eq!(computer.output, 2);
+    let computer = computer.execute();
+    assert_eq!(computer.device::<Clock>().unwrap().ticks, 4);
+  }
+
+  #[test]
+  fn random_numbers_repeat_with_the_same_seed() {
+    let program = parse("4,11,4,11,1101,0,7,11,4,11,99,0");
+    let run = |seed| {
+      let mut outputs = vec![];
+      let mut computer = IntcodeComputer::new(program.clone())
+        .with_device(11..12, Random::new(seed))
+        .start();
+      while let IntcodeComputer::Output(state) = computer {
+        outputs.push(state.output);
+        computer = state.execute();
+      }
+      assert!(computer.as_halt().is_ok());
+      outputs
+    };
+    let outputs = run(1);
+    assert_eq!(outputs, run(1));
+    assert_ne!(outputs, run(2));
+    assert_ne!(outputs[0], outputs[1]);
+    assert!(outputs.iter().all(|output| (0..1 << 31).contains(output)));
+    // Writing 7 reseeded it
+    let mut reseeded = Random::new(7);
+    assert_eq!(outputs[2], Device::<isize>::read(&mut reseeded, 0));
+  }
+
+  #[test]
+  fn draws_to_framebuffer() {
+    // Sets pixels 1 and 4 of a 3x2 framebuffer at 9..15
+    let computer = IntcodeComputer::new(parse("1101,0,1,10,1101,5,0,13,99,0,0,0,0,0,0"))
+      .with_device(9..15, Framebuffer::new(3, 2))
+      .start();
+    let framebuffer = computer.device::<Framebuffer>().unwrap();
+    assert_eq!(framebuffer.to_string(), ".#.\n.#.\n");
+    assert_eq!(*framebuffer.pixel(1, 1), 5);
+    // RAM under the device is untouched
+    assert_eq!(computer.borrow_memory()[10], 0);
+    assert!(computer.device::<Clock>().is_none());
+  }
+
+  #[test]
+  fn writes_never_read_the_device() {
+    // Only writes to the device, so it's never read
+    struct Counter(usize);
+    impl Device for Counter {
+      fn read(&mut self, _offset: usize) -> isize {
+        self.0 += 1;
+        0
+      }
+      fn write(&mut self, _offset: usize, _value: isize) {}
+    }
+    let computer = IntcodeComputer::new(parse("3,7,1101,1,1,7,99,0"))
+      .with_device(7..8, Counter(0))
+      .start()
+      .as_input()
+      .unwrap()
+      .execute(3);
+    assert_eq!(computer.device::<Counter>().unwrap().0, 0);
+  }
+
+  #[test]
+  fn device_reads_count_as_io_for_the_watchdog() {
+    // Waits for the clock at 8 to reach 1000
+    let computer = IntcodeComputer::new(parse("1007,8,1000,9,1005,9,0,99,0,0"))
+      .with_device(8..9, Clock::new())
+      .with_watchdog(10)
+      .start();
+    assert!(computer.as_halt().is_ok());
+
+    let computer = IntcodeComputer::new(parse("1105,1,0"))
+      .with_device(2..3, Clock::new())
+      .with_watchdog(10)
+      .start();
+    assert_eq!(
+      computer.as_fault().unwrap().fault,
+      Fault::InfiniteLoop { start: 0, end: 0 }
+    );
+  }
+
+  #[test]
+  #[should_panic(expected = "outside memory")]
+  fn rejects_devices_outside_memory() {
+    IntcodeComputer::new(parse("99,0,0")).with_device(2..4, Clock::new());
+  }
+
+  #[test]
+  #[should_panic(expected = "overlaps another device")]
+  fn rejects_overlapping_devices() {
+    IntcodeComputer::new(parse("99,0,0"))
+      .with_device(0..2, Clock::new())
+      .with_device(1..3, Clock::new());
+  }
+}
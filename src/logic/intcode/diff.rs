@@ -0,0 +1,227 @@
+// Comparing two memory snapshots, e.g. a program before and after it runs.
+
+use super::disasm::CodeMap;
+use super::{InstructionSet, IntcodeSequence};
+use std::fmt;
+
+/// Changes this many words apart or closer are reported as one run.
+pub const RUN_GAP: usize = 4;
+
+/// One changed word. Either side is `None` if the address is past the end of that memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryChange {
+  pub address: usize,
+  pub old: Option<isize>,
+  pub new: Option<isize>,
+  /// Where the instruction containing this word starts, if the word was part of the
+  /// program's code in the old memory, or became part of it in the new one
+  pub instruction: Option<usize>,
+}
+
+impl MemoryChange {
+  pub fn is_self_modification(&self) -> bool {
+    self.instruction.is_some()
+  }
+}
+
+/// Changes to nearby addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeRun {
+  pub changes: Vec<MemoryChange>,
+}
+
+impl ChangeRun {
+  pub fn start(&self) -> usize {
+    self.changes[0].address
+  }
+
+  pub fn end(&self) -> usize {
+    self.changes[self.changes.len() - 1].address
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDiff {
+  pub old_len: usize,
+  pub new_len: usize,
+  pub runs: Vec<ChangeRun>,
+}
+
+impl MemoryDiff {
+  pub fn is_empty(&self) -> bool {
+    self.runs.is_empty()
+  }
+
+  pub fn changes(&self) -> impl Iterator<Item = &MemoryChange> {
+    self.runs.iter().flat_map(|run| run.changes.iter())
+  }
+}
+
+/// Lists the words that differ between `old` and `new`, using the standard instruction set
+/// to work out which of them were code.
+pub fn diff_memory(old: &IntcodeSequence, new: &IntcodeSequence) -> MemoryDiff {
+  diff_memory_with(old, new, &InstructionSet::standard())
+}
+
+pub fn diff_memory_with(
+  old: &IntcodeSequence,
+  new: &IntcodeSequence,
+  instruction_set: &InstructionSet,
+) -> MemoryDiff {
+  // A change can also turn data into code, like a `99` written over the next instruction,
+  // which only the new memory's code map knows about
+  let (old_code, new_code) = (
+    CodeMap::new(old, instruction_set),
+    CodeMap::new(new, instruction_set),
+  );
+  let mut runs: Vec<ChangeRun> = vec![];
+  for address in 0..old.len().max(new.len()) {
+    let (old_value, new_value) = (old.get(address).cloned(), new.get(address).cloned());
+    if old_value == new_value {
+      continue;
+    }
+    let change = MemoryChange {
+      address,
+      old: old_value,
+      new: new_value,
+      instruction: old_code
+        .instruction_containing(address)
+        .or_else(|| new_code.instruction_containing(address))
+        .map(|instruction| instruction.address),
+    };
+    match runs.last_mut() {
+      Some(run) if address - run.end() <= RUN_GAP => run.changes.push(change),
+      _ => runs.push(ChangeRun {
+        changes: vec![change],
+      }),
+    }
+  }
+  MemoryDiff {
+    old_len: old.len(),
+    new_len: new.len(),
+    runs,
+  }
+}
+
+fn format_value(value: Option<isize>) -> String {
+  value.map_or("-".into(), |value| value.to_string())
+}
+
+impl fmt::Display for MemoryDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_empty() {
+      return write!(f, "memories are identical");
+    }
+    let count = self.changes().count();
+    write!(
+      f,
+      "{} changed word{} in {} run{}",
+      count,
+      if count == 1 { "" } else { "s" },
+      self.runs.len(),
+      if self.runs.len() == 1 { "" } else { "s" }
+    )?;
+    if self.old_len != self.new_len {
+      write!(f, ", length {} -> {}", self.old_len, self.new_len)?;
+    }
+    for run in &self.runs {
+      write!(f, "\n@{}..={}", run.start(), run.end())?;
+      for change in &run.changes {
+        write!(
+          f,
+          "\n  {:>6}: {} -> {}",
+          change.address,
+          format_value(change.old),
+          format_value(change.new)
+        )?;
+        if let Some(instruction) = change.instruction {
+          write!(f, "  (self-modification of instruction at {})", instruction)?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Like `assert_eq!` for two memories, but reports which addresses differ.
+#[macro_export]
+macro_rules! assert_memory_eq {
+  ($actual:expr, $expected:expr $(,)?) => {{
+    let diff = $crate::intcode::diff::diff_memory(&$expected, &$actual);
+    if !diff.is_empty() {
+      panic!("memory differs from expected: {}", diff);
+    }
+  }};
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::parse;
+  use super::*;
+
+  #[test]
+  fn identical() {
+    let diff = diff_memory(&parse("1,0,0,0,99"), &parse("1,0,0,0,99"));
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "memories are identical");
+  }
+
+  #[test]
+  fn groups_runs() {
+    let old = parse("1,9,10,3,2,3,11,0,99,30,40,50");
+    let new = parse("3500,9,10,70,2,3,11,0,99,30,41,50,0,0,0,0,0,0,1");
+    let diff = diff_memory(&old, &new);
+    assert_eq!(
+      diff
+        .runs
+        .iter()
+        .map(|run| (run.start(), run.end()))
+        .collect::<Vec<_>>(),
+      vec![(0, 3), (10, 18)]
+    );
+    assert_eq!(
+      diff.runs[1].changes[1],
+      MemoryChange {
+        address: 12,
+        old: None,
+        new: Some(0),
+        instruction: None,
+      }
+    );
+  }
+
+  #[test]
+  fn annotates_self_modification() {
+    let old = parse("1002,4,3,4,33");
+    let new = parse("1002,4,3,4,99");
+    assert_eq!(
+      diff_memory(&old, &new).to_string(),
+      "1 changed word in 1 run\n@4..=4\n       4: 33 -> 99  (self-modification of instruction at 4)"
+    );
+
+    // Overwriting an instruction that was going to run
+    let old = parse("1101,1,98,5,1101,0,0,0");
+    let new = parse("1101,1,98,5,1101,99,0,0");
+    let diff = diff_memory(&old, &new);
+    assert_eq!(diff.runs[0].changes[0].instruction, Some(4));
+    assert_eq!(
+      diff.to_string(),
+      "1 changed word in 1 run\n@5..=5\n       5: 0 -> 99  (self-modification of instruction at 4)"
+    );
+  }
+
+  #[test]
+  fn reports_length_changes() {
+    let diff = diff_memory(&parse("99"), &parse("99,1"));
+    assert_eq!(
+      diff.to_string(),
+      "1 changed word in 1 run, length 1 -> 2\n@1..=1\n       1: - -> 1"
+    );
+  }
+
+  #[test]
+  #[should_panic(expected = "memory differs from expected: 1 changed word")]
+  fn assert_macro() {
+    assert_memory_eq!(parse("1,0,0,0,99"), parse("2,0,0,0,99"));
+  }
+}
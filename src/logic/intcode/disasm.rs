@@ -0,0 +1,252 @@
+// Decoding programs into instructions without running them.
+
+use super::word::Word;
+use super::{parameter_modes, InstructionSet, IntcodeMemory, IntcodeSequence};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction<W = isize> {
+  pub address: usize,
+  pub opcode: u8,
+  pub name: &'static str,
+  pub parameter_modes: Vec<u8>,
+  pub parameters: Vec<W>,
+  pub writes: Vec<u8>,
+}
+
+impl<W: Word> DecodedInstruction<W> {
+  pub fn len(&self) -> usize {
+    1 + self.parameters.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    false
+  }
+
+  pub fn contains(&self, address: usize) -> bool {
+    address >= self.address && address < self.address + self.len()
+  }
+
+  /// The address parameter `i` refers to, for a position or relative mode parameter
+  /// that's a valid address.
+  pub fn address(&self, i: usize, relative_base: isize) -> Option<usize> {
+    match self.parameter_modes[i] {
+      0 => self.parameters[i].to_address(),
+      2 => self.parameters[i]
+        .to_isize()
+        .and_then(|offset| offset.checked_add(relative_base))
+        .and_then(|address| address.to_address()),
+      _ => None,
+    }
+  }
+
+  /// Where a jump instruction goes, if it can be worked out without running the program.
+  pub fn static_jump_target(&self) -> Option<usize> {
+    match (self.opcode, self.parameter_modes.get(1)) {
+      (5, Some(1)) | (6, Some(1)) => self.parameters[1].to_address(),
+      _ => None,
+    }
+  }
+
+  /// Whether execution can carry on to the next instruction.
+  pub fn falls_through(&self) -> bool {
+    match (self.opcode, self.parameter_modes.first()) {
+      (99, _) => false,
+      // Jumps whose condition is an immediate value always or never jump
+      (5, Some(1)) => self.parameters[0].is_zero(),
+      (6, Some(1)) => !self.parameters[0].is_zero(),
+      _ => true,
+    }
+  }
+}
+
+impl<W: fmt::Display> fmt::Display for DecodedInstruction<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name)?;
+    for (i, (param, mode)) in self
+      .parameters
+      .iter()
+      .zip(self.parameter_modes.iter())
+      .enumerate()
+    {
+      let separator = if i == 0 { " " } else { ", " };
+      match mode {
+        1 => write!(f, "{}#{}", separator, param)?,
+        2 => write!(f, "{}rb[{}]", separator, param)?,
+        _ => write!(f, "{}[{}]", separator, param)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Decodes the instruction at `address`, if there is a known opcode there.
+pub fn decode_at<W: Word>(
+  sequence: &IntcodeMemory<W>,
+  address: usize,
+  instruction_set: &InstructionSet<W>,
+) -> Option<DecodedInstruction<W>> {
+  let word = sequence.get(address)?.to_isize()?;
+  if word < 0 {
+    return None;
+  }
+  let def = instruction_set.get(word % 100)?;
+  let arity = usize::from(def.arity);
+  let parameters = sequence.get(address + 1..address + 1 + arity)?.to_vec();
+  let modes = parameter_modes(word, def.arity);
+  if !modes
+    .iter()
+    .all(|mode| instruction_set.parameter_modes().contains(mode))
+  {
+    return None;
+  }
+  Some(DecodedInstruction {
+    address,
+    opcode: def.opcode,
+    name: def.name,
+    parameter_modes: modes,
+    parameters,
+    writes: def.writes.clone(),
+  })
+}
+
+/// The instructions reachable from address 0, found by following every path through
+/// the program that can be worked out statically.
+#[derive(Debug, Clone)]
+pub struct CodeMap {
+  pub instructions: BTreeMap<usize, DecodedInstruction>,
+  /// Jumps whose target is only known at runtime
+  pub dynamic_jumps: BTreeSet<usize>,
+  /// Reachable addresses that don't hold an instruction of the instruction set, so the
+  /// program faults there or was written for another set
+  pub undecodable: BTreeSet<usize>,
+}
+
+impl CodeMap {
+  pub fn new(sequence: &IntcodeSequence, instruction_set: &InstructionSet) -> CodeMap {
+    let mut instructions = BTreeMap::new();
+    let mut dynamic_jumps = BTreeSet::new();
+    let mut undecodable = BTreeSet::new();
+    let mut pending = vec![0];
+    while let Some(address) = pending.pop() {
+      if instructions.contains_key(&address) {
+        continue;
+      }
+      let instruction = match decode_at(sequence, address, instruction_set) {
+        Some(instruction) => instruction,
+        None => {
+          if address < sequence.len() {
+            undecodable.insert(address);
+          }
+          continue;
+        }
+      };
+      if instruction.falls_through() {
+        pending.push(address + instruction.len());
+      }
+      if let 5 | 6 = instruction.opcode {
+        match instruction.static_jump_target() {
+          Some(target) => pending.push(target),
+          None => {
+            dynamic_jumps.insert(address);
+          }
+        }
+      }
+      instructions.insert(address, instruction);
+    }
+    CodeMap {
+      instructions,
+      dynamic_jumps,
+      undecodable,
+    }
+  }
+
+  /// The instruction that `address` is part of.
+  pub fn instruction_containing(&self, address: usize) -> Option<&DecodedInstruction> {
+    self
+      .instructions
+      .range(..=address)
+      .next_back()
+      .map(|(_, instruction)| instruction)
+      .filter(|instruction| instruction.contains(address))
+  }
+}
+
+/// A listing of the program, with unreachable words shown as data.
+pub fn disassemble(sequence: &IntcodeSequence, instruction_set: &InstructionSet) -> String {
+  let code_map = CodeMap::new(sequence, instruction_set);
+  let mut lines = vec![];
+  let mut address = 0;
+  while address < sequence.len() {
+    match code_map.instructions.get(&address) {
+      Some(instruction) => {
+        lines.push(format!("{:>6}: {}", address, instruction));
+        address += instruction.len();
+      }
+      None => {
+        lines.push(format!("{:>6}: data {}", address, sequence[address]));
+        address += 1;
+      }
+    }
+  }
+  lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::parse;
+  use super::*;
+
+  #[test]
+  fn decodes() {
+    let set = InstructionSet::standard();
+    let instruction = decode_at(&parse("1002,4,3,4,33"), 0, &set).unwrap();
+    assert_eq!(instruction.name, "mul");
+    assert_eq!(instruction.parameter_modes, vec![0, 1, 0]);
+    assert_eq!(instruction.to_string(), "mul [4], #3, [4]");
+    assert_eq!(decode_at(&parse("1002,4,3"), 0, &set), None);
+    assert_eq!(decode_at(&parse("42"), 0, &set), None);
+  }
+
+  #[test]
+  fn follows_control_flow() {
+    // Reads a number and jumps over the data at 5 and 6 if it's nonzero
+    let sequence = parse("3,11,1005,11,7,0,0,104,1,99,0,0");
+    let code_map = CodeMap::new(&sequence, &InstructionSet::standard());
+    assert_eq!(
+      code_map.instructions.keys().cloned().collect::<Vec<_>>(),
+      vec![0, 2, 7, 9]
+    );
+    assert!(code_map.dynamic_jumps.is_empty());
+    assert_eq!(code_map.instruction_containing(3).unwrap().address, 2);
+    assert_eq!(code_map.instruction_containing(10), None);
+  }
+
+  #[test]
+  fn stops_at_unconditional_jumps() {
+    let sequence = parse("1105,1,4,1,99");
+    let code_map = CodeMap::new(&sequence, &InstructionSet::standard());
+    assert_eq!(
+      code_map.instructions.keys().cloned().collect::<Vec<_>>(),
+      vec![0, 4]
+    );
+  }
+
+  #[test]
+  fn listing() {
+    let sequence = parse("1,9,10,3,2,3,11,0,99,30,40,50");
+    assert_eq!(
+      disassemble(&sequence, &InstructionSet::standard()),
+      [
+        "     0: add [9], [10], [3]",
+        "     4: mul [3], [11], [0]",
+        "     8: halt",
+        "     9: data 30",
+        "    10: data 40",
+        "    11: data 50",
+      ]
+      .join("\n")
+    );
+  }
+}
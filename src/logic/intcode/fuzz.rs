@@ -0,0 +1,547 @@
+// Differential testing for Intcode engines: generates random well-formed programs and
+// checks that every engine agrees on outputs, final memory and how the run ended.
+
+use super::compat::V05Halt;
+use super::word::Word;
+use super::{Fault, IntcodeComputer, IntcodeComputerState, IntcodeMemory, IntcodeSequence};
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+/// Small deterministic PRNG (xorshift64*), so failures can be reproduced from a seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+  pub fn new(seed: u64) -> Rng {
+    // Zero is a fixed point for xorshift
+    Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+  }
+
+  /// A value in `0..n`
+  pub fn below(&mut self, n: usize) -> usize {
+    (self.next_u64() % n as u64) as usize
+  }
+
+  /// A value in `min..max`
+  pub fn between(&mut self, min: isize, max: isize) -> isize {
+    min + isize::try_from(self.below((max - min) as usize)).unwrap()
+  }
+
+  pub fn chance(&mut self, percent: usize) -> bool {
+    self.below(100) < percent
+  }
+}
+
+/// Settings for random program generation. Generated programs only write to their data
+/// section and only jump forwards, so they always halt.
+#[derive(Debug, Clone)]
+pub struct ProgramGenerator {
+  pub instructions: usize,
+  pub data_len: usize,
+  pub max_inputs: usize,
+  pub max_outputs: usize,
+  pub value_range: (isize, isize),
+}
+
+impl Default for ProgramGenerator {
+  fn default() -> ProgramGenerator {
+    ProgramGenerator {
+      instructions: 24,
+      data_len: 8,
+      max_inputs: 3,
+      max_outputs: 6,
+      value_range: (-100, 100),
+    }
+  }
+}
+
+/// A program along with the inputs to run it with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzCase {
+  pub sequence: IntcodeSequence,
+  pub inputs: Vec<isize>,
+}
+
+impl ProgramGenerator {
+  pub fn generate(&self, rng: &mut Rng) -> FuzzCase {
+    let mut inputs_left = self.max_inputs;
+    let mut outputs_left = self.max_outputs;
+    let opcodes: Vec<u8> = (0..self.instructions)
+      .map(|_| loop {
+        let opcode = [1, 2, 3, 4, 5, 6, 7, 8][rng.below(8)];
+        match opcode {
+          3 if inputs_left == 0 => continue,
+          3 => inputs_left -= 1,
+          4 if outputs_left == 0 => continue,
+          4 => outputs_left -= 1,
+          _ => (),
+        }
+        break opcode;
+      })
+      .collect();
+
+    // Lay out instruction addresses first so jumps can target later instructions
+    let mut addresses = vec![];
+    let mut code_len = 0;
+    for opcode in opcodes.iter() {
+      addresses.push(code_len);
+      code_len += 1 + arity(*opcode);
+    }
+    // The final halt instruction
+    addresses.push(code_len);
+    code_len += 1;
+
+    let data_start = code_len;
+    let data_address = |rng: &mut Rng| (data_start + rng.below(self.data_len)) as isize;
+    let (min, max) = self.value_range;
+
+    let mut sequence = vec![];
+    for (i, opcode) in opcodes.iter().enumerate() {
+      let mut modes = 0;
+      let mut params = vec![];
+      for param in 0..arity(*opcode) {
+        let writes = match opcode {
+          1 | 2 | 7 | 8 => param == 2,
+          3 => true,
+          _ => false,
+        };
+        let jump_target = (*opcode == 5 || *opcode == 6) && param == 1;
+        if jump_target {
+          let target = addresses[i + 1 + rng.below(addresses.len() - i - 1)];
+          modes += 10usize.pow(param as u32);
+          params.push(target as isize);
+        } else if !writes && rng.chance(50) {
+          modes += 10usize.pow(param as u32);
+          params.push(rng.between(min, max));
+        } else {
+          params.push(data_address(rng));
+        }
+      }
+      sequence.push((modes * 100 + usize::from(*opcode)) as isize);
+      sequence.extend(params);
+    }
+    sequence.push(99);
+    sequence.extend((0..self.data_len).map(|_| rng.between(min, max)));
+
+    let inputs = (0..self.max_inputs)
+      .map(|_| rng.between(min, max))
+      .collect();
+    FuzzCase { sequence, inputs }
+  }
+}
+
+fn arity(opcode: u8) -> usize {
+  match opcode {
+    1 | 2 | 7 | 8 => 3,
+    5 | 6 => 2,
+    3 | 4 => 1,
+    _ => 0,
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HaltKind {
+  Halted,
+  NeedsInput,
+  Faulted(Fault),
+  Panicked(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outputs {
+  All(Vec<isize>),
+  /// For engines that only report the most recent output
+  Last(Option<isize>),
+}
+
+impl Outputs {
+  fn last(&self) -> Option<isize> {
+    match self {
+      Outputs::All(outputs) => outputs.last().cloned(),
+      Outputs::Last(output) => *output,
+    }
+  }
+
+  fn matches(&self, other: &Outputs) -> bool {
+    match (self, other) {
+      (Outputs::All(a), Outputs::All(b)) => a == b,
+      _ => self.last() == other.last(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunOutcome {
+  pub outputs: Outputs,
+  /// Final memory, if the engine was able to report it
+  pub memory: Option<IntcodeSequence>,
+  pub halt: HaltKind,
+}
+
+impl RunOutcome {
+  fn matches(&self, other: &RunOutcome) -> bool {
+    let halt_matches = match (&self.halt, &other.halt) {
+      // Engines word their panics differently; it's enough that both gave up
+      (HaltKind::Panicked(_), HaltKind::Panicked(_)) => true,
+      (a, b) => a == b,
+    };
+    if !halt_matches {
+      return false;
+    }
+    if let HaltKind::Panicked(_) = self.halt {
+      return true;
+    }
+    let memory_matches = match (&self.memory, &other.memory) {
+      (Some(a), Some(b)) => a == b,
+      _ => true,
+    };
+    memory_matches && self.outputs.matches(&other.outputs)
+  }
+}
+
+/// Something that can run an Intcode program to completion.
+pub trait Engine {
+  fn name(&self) -> &str;
+  fn run(&self, sequence: &IntcodeSequence, inputs: &[isize]) -> RunOutcome;
+}
+
+/// The `IntcodeComputer` state machine.
+pub struct StateMachineEngine;
+
+impl Engine for StateMachineEngine {
+  fn name(&self) -> &str {
+    "IntcodeComputer"
+  }
+
+  fn run(&self, sequence: &IntcodeSequence, inputs: &[isize]) -> RunOutcome {
+    run_state_machine(sequence.clone(), inputs)
+  }
+}
+
+/// The `IntcodeComputer` state machine with memory made of `W`s, for checking that
+/// other word types agree with `isize`. Panics on values that don't fit back in an `isize`.
+pub struct WordEngine<W>(PhantomData<W>);
+
+impl<W> Default for WordEngine<W> {
+  fn default() -> Self {
+    WordEngine(PhantomData)
+  }
+}
+
+impl<W: Word> Engine for WordEngine<W> {
+  fn name(&self) -> &str {
+    std::any::type_name::<W>()
+  }
+
+  fn run(&self, sequence: &IntcodeSequence, inputs: &[isize]) -> RunOutcome {
+    let sequence = sequence.iter().map(|word| W::from_isize(*word)).collect();
+    run_state_machine::<W>(sequence, inputs)
+  }
+}
+
+fn to_isizes<W: Word>(words: &[W]) -> IntcodeSequence {
+  words
+    .iter()
+    .map(|word| word.to_isize().expect("Word doesn't fit in an isize"))
+    .collect()
+}
+
+fn run_state_machine<W: Word>(sequence: IntcodeMemory<W>, inputs: &[isize]) -> RunOutcome {
+  let mut inputs = inputs.iter();
+  let mut outputs = vec![];
+  let mut computer = IntcodeComputer::from_words(sequence).start();
+  loop {
+    match computer {
+      IntcodeComputer::Input(state) => match inputs.next() {
+        Some(input) => computer = state.execute(W::from_isize(*input)),
+        None => {
+          return RunOutcome {
+            outputs: Outputs::All(to_isizes(&outputs)),
+            memory: Some(to_isizes(state.borrow_memory())),
+            halt: HaltKind::NeedsInput,
+          }
+        }
+      },
+      IntcodeComputer::Output(state) => {
+        outputs.push(state.output.clone());
+        computer = state.execute();
+      }
+      IntcodeComputer::Halt(state) => {
+        return RunOutcome {
+          outputs: Outputs::All(to_isizes(&outputs)),
+          memory: Some(to_isizes(state.borrow_memory())),
+          halt: HaltKind::Halted,
+        }
+      }
+      IntcodeComputer::Fault(state) => {
+        return RunOutcome {
+          outputs: Outputs::All(to_isizes(&outputs)),
+          memory: Some(to_isizes(state.borrow_memory())),
+          halt: HaltKind::Faulted(state.fault),
+        }
+      }
+      IntcodeComputer::Break(_) => unreachable!("No debugger is attached"),
+    }
+  }
+}
+
+/// `compat::run_v05`, which takes at most one input and only reports the last output.
+pub struct CompatV05Engine;
+
+impl Engine for CompatV05Engine {
+  fn name(&self) -> &str {
+    "compat::run_v05"
+  }
+
+  fn run(&self, sequence: &IntcodeSequence, inputs: &[isize]) -> RunOutcome {
+    assert!(
+      inputs.len() <= 1,
+      "compute_v05 only supports a single input"
+    );
+    let mut memory = sequence.clone();
+    let (output, halt) = super::compat::run_v05(&mut memory, inputs.first().cloned());
+    RunOutcome {
+      outputs: Outputs::Last(output),
+      memory: Some(memory),
+      halt: match halt {
+        V05Halt::Halted => HaltKind::Halted,
+        V05Halt::MissingInput => HaltKind::NeedsInput,
+        V05Halt::Fault(fault) => HaltKind::Faulted(fault),
+      },
+    }
+  }
+}
+
+thread_local! {
+  static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keeps the panic hook from printing panics that `run_catching` expects, while leaving
+/// panics elsewhere, including on other test threads, to the previous hook.
+fn silence_caught_panics() {
+  static INSTALL: Once = Once::new();
+  INSTALL.call_once(|| {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+      if !CATCHING.with(|catching| catching.get()) {
+        previous(info);
+      }
+    }));
+  });
+}
+
+/// Runs `engine`, turning a panic into `HaltKind::Panicked`.
+pub fn run_catching(
+  engine: &dyn Engine,
+  sequence: &IntcodeSequence,
+  inputs: &[isize],
+) -> RunOutcome {
+  silence_caught_panics();
+  CATCHING.with(|catching| catching.set(true));
+  let result = panic::catch_unwind(AssertUnwindSafe(|| engine.run(sequence, inputs)));
+  CATCHING.with(|catching| catching.set(false));
+  result.unwrap_or_else(|err| {
+    let message = err
+      .downcast_ref::<String>()
+      .cloned()
+      .or_else(|| err.downcast_ref::<&str>().map(|s| s.to_string()))
+      .unwrap_or_default();
+    RunOutcome {
+      outputs: Outputs::All(vec![]),
+      memory: None,
+      halt: HaltKind::Panicked(message),
+    }
+  })
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+  pub seed: u64,
+  pub case: FuzzCase,
+  pub expected_engine: String,
+  pub expected: RunOutcome,
+  pub actual_engine: String,
+  pub actual: RunOutcome,
+}
+
+/// Runs one case through every engine and compares each against the first.
+pub fn check_case(
+  engines: &[&dyn Engine],
+  case: &FuzzCase,
+  seed: u64,
+) -> Result<(), Box<Divergence>> {
+  let (reference, others) = engines.split_first().expect("Need at least one engine");
+  let expected = run_catching(*reference, &case.sequence, &case.inputs);
+  for engine in others.iter() {
+    let actual = run_catching(*engine, &case.sequence, &case.inputs);
+    if !expected.matches(&actual) {
+      return Err(Box::new(Divergence {
+        seed,
+        case: case.clone(),
+        expected_engine: reference.name().into(),
+        expected,
+        actual_engine: engine.name().into(),
+        actual,
+      }));
+    }
+  }
+  Ok(())
+}
+
+/// Generates `iterations` cases, starting from `seed`, and checks each of them.
+/// A failing case can be regenerated with `ProgramGenerator::generate(&mut Rng::new(seed))`.
+pub fn differential_test(
+  engines: &[&dyn Engine],
+  generator: &ProgramGenerator,
+  seed: u64,
+  iterations: u64,
+) -> Result<(), Box<Divergence>> {
+  for case_seed in seed..seed + iterations {
+    let case = generator.generate(&mut Rng::new(case_seed));
+    check_case(engines, &case, case_seed)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn generated_programs_halt() {
+    let generator = ProgramGenerator::default();
+    for seed in 0..200 {
+      let case = generator.generate(&mut Rng::new(seed));
+      let outcome = run_catching(&StateMachineEngine, &case.sequence, &case.inputs);
+      match outcome.halt {
+        HaltKind::Halted | HaltKind::Faulted(Fault::Overflow { .. }) => (),
+        HaltKind::NeedsInput => panic!("Seed {} ran out of input", seed),
+        HaltKind::Panicked(message) => panic!("Seed {} panicked: {}", seed, message),
+        HaltKind::Faulted(fault) => panic!("Seed {} faulted: {}", seed, fault),
+      }
+    }
+  }
+
+  #[test]
+  fn generation_is_deterministic() {
+    let generator = ProgramGenerator::default();
+    assert_eq!(
+      generator.generate(&mut Rng::new(7)),
+      generator.generate(&mut Rng::new(7))
+    );
+    assert_ne!(
+      generator.generate(&mut Rng::new(7)),
+      generator.generate(&mut Rng::new(8))
+    );
+  }
+
+  #[test]
+  fn generated_programs_mostly_succeed() {
+    let generator = ProgramGenerator::default();
+    let panicked = (0..200)
+      .filter(|seed| {
+        let case = generator.generate(&mut Rng::new(*seed));
+        let outcome = run_catching(&StateMachineEngine, &case.sequence, &case.inputs);
+        outcome.halt != HaltKind::Halted
+      })
+      .count();
+    assert!(panicked < 20, "{} of 200 programs failed to halt", panicked);
+  }
+
+  #[test]
+  fn state_machine_matches_compat() {
+    let generator = ProgramGenerator {
+      max_inputs: 1,
+      ..ProgramGenerator::default()
+    };
+    let engines: [&dyn Engine; 2] = [&StateMachineEngine, &CompatV05Engine];
+    if let Err(divergence) = differential_test(&engines, &generator, 0, 500) {
+      panic!("{:#?}", divergence);
+    }
+  }
+
+  #[test]
+  fn compat_reports_how_it_stopped() {
+    let engines: [&dyn Engine; 2] = [&StateMachineEngine, &CompatV05Engine];
+    let cases = [
+      ("3,0,4,0,3,0,99", vec![5], HaltKind::NeedsInput),
+      (
+        "4,0,42",
+        vec![],
+        HaltKind::Faulted(Fault::UnknownOpcode {
+          opcode: 42,
+          pointer: 2,
+        }),
+      ),
+    ];
+    for (program, inputs, halt) in cases.iter() {
+      let case = FuzzCase {
+        sequence: super::super::parse(program),
+        inputs: inputs.clone(),
+      };
+      assert_eq!(
+        run_catching(&CompatV05Engine, &case.sequence, inputs).halt,
+        *halt
+      );
+      check_case(&engines, &case, 0).unwrap_or_else(|divergence| panic!("{:#?}", divergence));
+    }
+  }
+
+  #[test]
+  fn catches_panics() {
+    struct PanickingEngine;
+    impl Engine for PanickingEngine {
+      fn name(&self) -> &str {
+        "panicking"
+      }
+      fn run(&self, _: &IntcodeSequence, _: &[isize]) -> RunOutcome {
+        panic!("Expected panic")
+      }
+    }
+
+    let outcome = run_catching(&PanickingEngine, &vec![99], &[]);
+    assert_eq!(outcome.halt, HaltKind::Panicked("Expected panic".into()));
+  }
+
+  #[test]
+  fn i64_words_match_isize() {
+    let i64_engine = WordEngine::<i64>::default();
+    let engines: [&dyn Engine; 2] = [&StateMachineEngine, &i64_engine];
+    if let Err(divergence) = differential_test(&engines, &ProgramGenerator::default(), 0, 500) {
+      panic!("{:#?}", divergence);
+    }
+  }
+
+  #[test]
+  fn detects_divergence() {
+    struct BrokenEngine;
+    impl Engine for BrokenEngine {
+      fn name(&self) -> &str {
+        "broken"
+      }
+      fn run(&self, sequence: &IntcodeSequence, inputs: &[isize]) -> RunOutcome {
+        let mut outcome = StateMachineEngine.run(sequence, inputs);
+        if let Some(memory) = outcome.memory.as_mut() {
+          memory[0] += 1;
+        }
+        outcome
+      }
+    }
+
+    let engines: [&dyn Engine; 2] = [&StateMachineEngine, &BrokenEngine];
+    let divergence = differential_test(&engines, &ProgramGenerator::default(), 0, 10)
+      .expect_err("Expected engines to diverge");
+    assert_eq!(divergence.seed, 0);
+    assert_eq!(divergence.actual_engine, "broken");
+  }
+}
@@ -0,0 +1,790 @@
+use super::devices::DeviceMap;
+use super::observer::ComputeObserver;
+use super::word::Word;
+use super::{
+  parameter_address, parameter_modes, parse_instruction, Fault, IntcodeMemory, ProgramState,
+};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+type ExecuteFn<W> = dyn Fn(&mut InstructionContext<W>) -> ProgramState<W> + Send + Sync;
+
+/// The definition of a single opcode: how many parameters it takes, which of those
+/// parameters are addresses it writes to, and what it does when executed.
+pub struct OpcodeDef<W: Word = isize> {
+  pub opcode: u8,
+  pub name: &'static str,
+  pub arity: u8,
+  pub writes: Vec<u8>,
+  execute: Arc<ExecuteFn<W>>,
+}
+
+impl OpcodeDef {
+  pub fn new<F>(opcode: u8, name: &'static str, arity: u8, writes: &[u8], execute: F) -> Self
+  where
+    F: Fn(&mut InstructionContext) -> ProgramState + Send + Sync + 'static,
+  {
+    OpcodeDef::new_for_words(opcode, name, arity, writes, execute)
+  }
+}
+
+impl<W: Word> OpcodeDef<W> {
+  /// Like `new`, for computers with other word types.
+  pub fn new_for_words<F>(
+    opcode: u8,
+    name: &'static str,
+    arity: u8,
+    writes: &[u8],
+    execute: F,
+  ) -> Self
+  where
+    F: Fn(&mut InstructionContext<W>) -> ProgramState<W> + Send + Sync + 'static,
+  {
+    assert!(opcode < 100, "Opcodes must fit in two digits");
+    assert!(
+      writes.iter().all(|param| *param < arity),
+      "Opcode {} writes to a parameter it doesn't have",
+      name
+    );
+    OpcodeDef {
+      opcode,
+      name,
+      arity,
+      writes: writes.to_vec(),
+      execute: Arc::new(execute),
+    }
+  }
+
+  pub fn writes_to(&self, param: u8) -> bool {
+    self.writes.contains(&param)
+  }
+
+  /// Whether `other` looks like the same instruction, maybe for another word type. Only
+  /// the opcode, name and parameters are compared, since what it does can't be.
+  pub fn same_as<V: Word>(&self, other: &OpcodeDef<V>) -> bool {
+    self.opcode == other.opcode
+      && self.name == other.name
+      && self.arity == other.arity
+      && self.writes == other.writes
+  }
+}
+
+// Not derived, since that would require `W: Clone` on top of `Word`
+impl<W: Word> Clone for OpcodeDef<W> {
+  fn clone(&self) -> Self {
+    OpcodeDef {
+      opcode: self.opcode,
+      name: self.name,
+      arity: self.arity,
+      writes: self.writes.clone(),
+      execute: self.execute.clone(),
+    }
+  }
+}
+
+impl<W: Word> fmt::Debug for OpcodeDef<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("OpcodeDef")
+      .field("opcode", &self.opcode)
+      .field("name", &self.name)
+      .field("arity", &self.arity)
+      .field("writes", &self.writes)
+      .finish()
+  }
+}
+
+/// What an opcode's execute function can see and do while it runs.
+pub struct InstructionContext<'a, W: Word = isize> {
+  sequence: &'a mut IntcodeMemory<W>,
+  pointer: usize,
+  raw_parameters: Vec<W>,
+  parameter_modes: Vec<u8>,
+  parameters: Vec<W>,
+  next_pointer: usize,
+  relative_base: &'a mut isize,
+  overflow_policy: OverflowPolicy,
+  observer: Option<&'a mut dyn ComputeObserver<W>>,
+  devices: Option<&'a mut DeviceMap<W>>,
+}
+
+impl<W: Word> InstructionContext<'_, W> {
+  /// The value of a parameter, with its parameter mode applied.
+  pub fn param(&self, i: usize) -> W {
+    self.parameters[i].clone()
+  }
+
+  /// The parameter exactly as written in the program.
+  pub fn raw_param(&self, i: usize) -> W {
+    self.raw_parameters[i].clone()
+  }
+
+  /// The address a position or relative mode parameter refers to.
+  pub fn address(&self, i: usize) -> usize {
+    parameter_address(
+      &self.raw_parameters[i],
+      self.parameter_modes[i],
+      *self.relative_base,
+    )
+  }
+
+  /// Writes to the address given by a parameter, or to the device mapped there.
+  pub fn write(&mut self, i: usize, value: W) {
+    let address = self.address(i);
+    let value = match self.devices.as_mut() {
+      Some(devices) => match devices.write(address, value) {
+        Some(value) => value,
+        None => return,
+      },
+      None => value,
+    };
+    let old = std::mem::replace(&mut self.sequence[address], value);
+    if let Some(observer) = self.observer.as_mut() {
+      observer.on_memory_write(address, old, self.sequence[address].clone());
+    }
+  }
+
+  pub fn memory(&self) -> &IntcodeMemory<W> {
+    self.sequence
+  }
+
+  pub fn pointer(&self) -> usize {
+    self.pointer
+  }
+
+  pub fn next_pointer(&self) -> usize {
+    self.next_pointer
+  }
+
+  pub fn overflow_policy(&self) -> OverflowPolicy {
+    self.overflow_policy
+  }
+
+  pub fn relative_base(&self) -> isize {
+    *self.relative_base
+  }
+
+  /// Moves the relative base by `offset`, or faults if it no longer fits in an `isize`.
+  pub fn adjust_relative_base(&mut self, offset: W) -> ProgramState<W> {
+    let adjusted = offset
+      .to_isize()
+      .and_then(|offset| self.relative_base.checked_add(offset));
+    match adjusted {
+      Some(base) => {
+        *self.relative_base = base;
+        self.next()
+      }
+      None => self.overflow(),
+    }
+  }
+
+  /// Continues on to the next instruction.
+  pub fn next(&self) -> ProgramState<W> {
+    ProgramState::Continue(self.next_pointer)
+  }
+
+  /// Faults because the instruction's result doesn't fit in a word.
+  pub fn overflow(&self) -> ProgramState<W> {
+    ProgramState::Fault(Fault::Overflow {
+      pointer: self.pointer,
+    })
+  }
+
+  /// Writes `result` to the address given by a parameter, or faults if it overflowed.
+  pub fn write_arithmetic(&mut self, i: usize, result: Option<W>) -> ProgramState<W> {
+    match result {
+      Some(result) => {
+        self.write(i, result);
+        self.next()
+      }
+      None => self.overflow(),
+    }
+  }
+}
+
+/// What `add` and `mul` do when their result doesn't fit in a word. Without a policy,
+/// the result would depend on the build profile: a panic in debug builds and silent
+/// wrapping in release builds. Programs known to need exact results bigger than an
+/// `isize` can run on wider words from the start, see `IntcodeComputer::from_words`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+  /// Stop with `Fault::Overflow`
+  #[default]
+  Fault,
+  /// Wrap around in two's complement
+  Wrap,
+  /// Clamp to the largest or smallest word
+  Saturate,
+  /// Stop with `Fault::Overflow` like `Fault`, and let the run carry on from the same
+  /// instruction on wider words with `IntcodeComputerFaultState::promote`
+  Promote,
+}
+
+impl OverflowPolicy {
+  /// `a + b` under this policy, or `None` if the instruction should fault.
+  pub fn add<W: Word>(self, a: W, b: W) -> Option<W> {
+    match self {
+      OverflowPolicy::Fault | OverflowPolicy::Promote => a.checked_add(&b),
+      OverflowPolicy::Wrap => Some(a.wrapping_add(&b)),
+      OverflowPolicy::Saturate => Some(a.saturating_add(&b)),
+    }
+  }
+
+  /// `a * b` under this policy, or `None` if the instruction should fault.
+  pub fn mul<W: Word>(self, a: W, b: W) -> Option<W> {
+    match self {
+      OverflowPolicy::Fault | OverflowPolicy::Promote => a.checked_mul(&b),
+      OverflowPolicy::Wrap => Some(a.wrapping_mul(&b)),
+      OverflowPolicy::Saturate => Some(a.saturating_mul(&b)),
+    }
+  }
+}
+
+/// A registry of opcodes and parameter modes that the computer knows how to execute.
+pub struct InstructionSet<W: Word = isize> {
+  opcodes: Vec<Option<OpcodeDef<W>>>,
+  parameter_modes: Vec<u8>,
+}
+
+impl InstructionSet {
+  /// No opcodes, and only position mode.
+  pub fn empty() -> InstructionSet {
+    InstructionSet::empty_for_words()
+  }
+
+  /// Every opcode through Day 5.
+  pub fn standard() -> InstructionSet {
+    InstructionSet::standard_for_words()
+  }
+
+  /// The standard set plus Day 9's relative mode and `arb`, which adjusts the relative
+  /// base. Memory still ends where the program does.
+  pub fn relative() -> InstructionSet {
+    InstructionSet::relative_for_words()
+  }
+}
+
+impl<W: Word> InstructionSet<W> {
+  /// Like `empty`, for computers with other word types.
+  pub fn empty_for_words() -> Self {
+    InstructionSet {
+      opcodes: vec![None; 100],
+      parameter_modes: vec![0],
+    }
+  }
+
+  /// Like `standard`, for computers with other word types.
+  pub fn standard_for_words() -> Self {
+    let mut set = InstructionSet::empty_for_words();
+    set.set_parameter_modes(&[0, 1]);
+    set.register(OpcodeDef::new_for_words(1, "add", 3, &[2], |ctx| {
+      let result = ctx.overflow_policy().add(ctx.param(0), ctx.param(1));
+      ctx.write_arithmetic(2, result)
+    }));
+    set.register(OpcodeDef::new_for_words(2, "mul", 3, &[2], |ctx| {
+      let result = ctx.overflow_policy().mul(ctx.param(0), ctx.param(1));
+      ctx.write_arithmetic(2, result)
+    }));
+    // Input is supplied by IntcodeComputerInputState, which writes to the first parameter
+    set.register(OpcodeDef::new_for_words(3, "in", 1, &[0], |_| {
+      ProgramState::WaitForInput
+    }));
+    set.register(OpcodeDef::new_for_words(4, "out", 1, &[], |ctx| {
+      ProgramState::OutputAndContinue {
+        pointer: ctx.next_pointer(),
+        output: ctx.param(0),
+      }
+    }));
+    set.register(OpcodeDef::new_for_words(
+      5,
+      "jnz",
+      2,
+      &[],
+      |ctx: &mut InstructionContext<W>| {
+        if !ctx.param(0).is_zero() {
+          ProgramState::Continue(ctx.param(1).expect_address())
+        } else {
+          ctx.next()
+        }
+      },
+    ));
+    set.register(OpcodeDef::new_for_words(
+      6,
+      "jz",
+      2,
+      &[],
+      |ctx: &mut InstructionContext<W>| {
+        if ctx.param(0).is_zero() {
+          ProgramState::Continue(ctx.param(1).expect_address())
+        } else {
+          ctx.next()
+        }
+      },
+    ));
+    set.register(OpcodeDef::new_for_words(7, "lt", 3, &[2], |ctx| {
+      let result = if ctx.param(0) < ctx.param(1) { 1 } else { 0 };
+      ctx.write(2, W::from_isize(result));
+      ctx.next()
+    }));
+    set.register(OpcodeDef::new_for_words(8, "eq", 3, &[2], |ctx| {
+      let result = if ctx.param(0) == ctx.param(1) { 1 } else { 0 };
+      ctx.write(2, W::from_isize(result));
+      ctx.next()
+    }));
+    set.register(OpcodeDef::new_for_words(99, "halt", 0, &[], |_| {
+      ProgramState::Halt
+    }));
+    set
+  }
+
+  /// Like `relative`, for computers with other word types.
+  pub fn relative_for_words() -> Self {
+    let mut set = InstructionSet::standard_for_words();
+    set.set_parameter_modes(&[0, 1, 2]);
+    set.register(OpcodeDef::new_for_words(
+      9,
+      "arb",
+      1,
+      &[],
+      |ctx: &mut InstructionContext<W>| ctx.adjust_relative_base(ctx.param(0)),
+    ));
+    set
+  }
+
+  /// Adds an opcode, returning the definition it replaced, if any.
+  pub fn register(&mut self, def: OpcodeDef<W>) -> Option<OpcodeDef<W>> {
+    let index = usize::from(def.opcode);
+    self.opcodes[index].replace(def)
+  }
+
+  pub fn remove(&mut self, opcode: u8) -> Option<OpcodeDef<W>> {
+    self.opcodes[usize::from(opcode)].take()
+  }
+
+  pub fn get(&self, opcode: isize) -> Option<&OpcodeDef<W>> {
+    usize::try_from(opcode)
+      .ok()
+      .and_then(|opcode| self.opcodes.get(opcode))
+      .and_then(|def| def.as_ref())
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &OpcodeDef<W>> {
+    self.opcodes.iter().filter_map(|def| def.as_ref())
+  }
+
+  pub fn set_parameter_modes(&mut self, modes: &[u8]) {
+    self.parameter_modes = modes.to_vec();
+  }
+
+  pub fn parameter_modes(&self) -> &[u8] {
+    &self.parameter_modes
+  }
+
+  /// The same opcodes and parameter modes for another kind of word, if every opcode is a
+  /// built-in one. Custom opcodes only know how to work on `W`.
+  pub fn for_words<V: Word>(&self) -> Option<InstructionSet<V>> {
+    let built_in = InstructionSet::<V>::relative_for_words();
+    let mut set = InstructionSet::empty_for_words();
+    for def in self.iter() {
+      let same = built_in
+        .get(isize::from(def.opcode))
+        .filter(|other| other.same_as(def))?;
+      set.register(same.clone());
+    }
+    set.set_parameter_modes(self.parameter_modes());
+    Some(set)
+  }
+
+  /// Looks up the opcode for the instruction at `instruction_pointer`,
+  /// checking that it only uses supported parameter modes.
+  pub fn decode(
+    &self,
+    sequence: &IntcodeMemory<W>,
+    instruction_pointer: usize,
+  ) -> Result<&OpcodeDef<W>, Fault> {
+    // No opcode is anywhere near too wide for an isize, whatever the parameter modes
+    let instruction =
+      sequence[instruction_pointer]
+        .to_isize()
+        .ok_or(Fault::OversizedInstruction {
+          pointer: instruction_pointer,
+        })?;
+    let opcode = instruction % 100;
+    let def = self.get(opcode).ok_or(Fault::UnknownOpcode {
+      opcode,
+      pointer: instruction_pointer,
+    })?;
+    let unsupported_mode = parameter_modes(instruction, def.arity)
+      .into_iter()
+      .find(|mode| !self.parameter_modes.contains(mode));
+    match unsupported_mode {
+      Some(mode) => Err(Fault::UnsupportedParameterMode {
+        mode,
+        pointer: instruction_pointer,
+      }),
+      None => Ok(def),
+    }
+  }
+
+  /// Executes the instruction at `instruction_pointer`, with a relative base of 0 and the
+  /// default overflow policy.
+  pub fn compute_instruction(
+    &self,
+    sequence: &mut IntcodeMemory<W>,
+    instruction_pointer: usize,
+  ) -> ProgramState<W> {
+    self.compute_instruction_observed(sequence, instruction_pointer, None)
+  }
+
+  /// Like `compute_instruction`, telling `observer` about any memory writes.
+  pub fn compute_instruction_observed(
+    &self,
+    sequence: &mut IntcodeMemory<W>,
+    instruction_pointer: usize,
+    observer: Option<&mut dyn ComputeObserver<W>>,
+  ) -> ProgramState<W> {
+    self.compute_instruction_mapped(
+      sequence,
+      instruction_pointer,
+      &mut 0,
+      OverflowPolicy::default(),
+      observer,
+      None,
+    )
+  }
+
+  /// Like `compute_instruction_observed`, from `relative_base`, under `overflow_policy`,
+  /// and routing parameters to `devices` where they're mapped.
+  pub(super) fn compute_instruction_mapped(
+    &self,
+    sequence: &mut IntcodeMemory<W>,
+    instruction_pointer: usize,
+    relative_base: &mut isize,
+    overflow_policy: OverflowPolicy,
+    observer: Option<&mut dyn ComputeObserver<W>>,
+    mut devices: Option<&mut DeviceMap<W>>,
+  ) -> ProgramState<W> {
+    let def = match self.decode(sequence, instruction_pointer) {
+      Ok(def) => def,
+      Err(fault) => return ProgramState::Fault(fault),
+    };
+    let mut instruction =
+      parse_instruction(sequence, instruction_pointer, *relative_base, def.arity);
+    if let Some(devices) = devices.as_mut() {
+      for i in 0..def.arity {
+        let param = usize::from(i);
+        if instruction.parameter_modes[param] == 1 || def.writes_to(i) {
+          continue;
+        }
+        let address = instruction.address(param);
+        if devices.is_mapped(address) {
+          instruction.parameters[param] = devices.read(sequence, address);
+        }
+      }
+    }
+    let mut ctx = InstructionContext {
+      sequence,
+      pointer: instruction_pointer,
+      raw_parameters: instruction.raw_parameters,
+      parameter_modes: instruction.parameter_modes,
+      parameters: instruction.parameters,
+      next_pointer: instruction.next_pointer,
+      relative_base,
+      overflow_policy,
+      observer,
+      devices,
+    };
+    (def.execute)(&mut ctx)
+  }
+}
+
+impl<W: Word> Clone for InstructionSet<W> {
+  fn clone(&self) -> Self {
+    InstructionSet {
+      opcodes: self.opcodes.clone(),
+      parameter_modes: self.parameter_modes.clone(),
+    }
+  }
+}
+
+impl Default for InstructionSet {
+  fn default() -> InstructionSet {
+    InstructionSet::standard()
+  }
+}
+
+impl<W: Word> fmt::Debug for InstructionSet<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_list()
+      .entries(self.iter().map(|def| (def.opcode, def.name)))
+      .finish()
+  }
+}
+
+lazy_static! {
+  pub static ref STANDARD_INSTRUCTION_SET: Arc<InstructionSet> =
+    Arc::new(InstructionSet::standard());
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, BigInt, IntcodeComputer, IntcodeComputerState, Profile};
+  use super::*;
+  use std::sync::Mutex;
+
+  #[test]
+  fn standard_set() {
+    let set = InstructionSet::standard();
+    let names: Vec<_> = set.iter().map(|def| def.name).collect();
+    assert_eq!(
+      names,
+      vec!["add", "mul", "in", "out", "jnz", "jz", "lt", "eq", "halt"]
+    );
+    assert_eq!(set.get(1).unwrap().writes, vec![2]);
+    assert_eq!(set.get(1002).map(|def| def.name), None);
+    assert!(set.get(-1).is_none());
+  }
+
+  #[test]
+  fn custom_opcode() {
+    // Opcode 42 records its parameter without affecting the program
+    let printed = Arc::new(Mutex::new(vec![]));
+    let mut set = InstructionSet::standard();
+    let log = printed.clone();
+    set.register(OpcodeDef::new(42, "debug", 1, &[], move |ctx| {
+      log.lock().unwrap().push(ctx.param(0));
+      ctx.next()
+    }));
+
+    let computer = IntcodeComputer::new(parse("42,5,142,7,99,13"))
+      .with_instruction_set(set)
+      .start()
+      .as_halt()
+      .unwrap();
+    assert_eq!(computer.get_pointer(), 4);
+    assert_eq!(*printed.lock().unwrap(), vec![13, 7]);
+  }
+
+  #[test]
+  fn custom_writing_opcode() {
+    // Opcode 20 negates its first parameter into its second
+    let mut set = InstructionSet::standard();
+    set.register(OpcodeDef::new(20, "neg", 2, &[1], |ctx| {
+      let result = -ctx.param(0);
+      ctx.write(1, result);
+      ctx.next()
+    }));
+    let computer = IntcodeComputer::new(parse("120,9,10,20,10,11,4,11,99,0,0,0"))
+      .with_instruction_set(set)
+      .start()
+      .as_output()
+      .unwrap();
+    assert_eq!(computer.output, 9);
+    assert_eq!(computer.borrow_memory()[10..], [-9, 9]);
+  }
+
+  #[test]
+  fn replace_opcode() {
+    let mut set = InstructionSet::standard();
+    let previous = set.register(OpcodeDef::new(1, "sub", 3, &[2], |ctx| {
+      let result = ctx.param(0) - ctx.param(1);
+      ctx.write(2, result);
+      ctx.next()
+    }));
+    assert_eq!(previous.map(|def| def.name), Some("add"));
+
+    let mut sequence = parse("1101,5,3,0,99");
+    set.compute_instruction(&mut sequence, 0);
+    assert_eq!(sequence[0], 2);
+  }
+
+  #[test]
+  fn removed_opcode() {
+    let mut set = InstructionSet::standard();
+    set.remove(2);
+    assert_eq!(
+      set.compute_instruction(&mut parse("2,0,0,0,99"), 0),
+      ProgramState::Fault(Fault::UnknownOpcode {
+        opcode: 2,
+        pointer: 0
+      })
+    );
+  }
+
+  #[test]
+  fn unsupported_parameter_mode() {
+    let set = InstructionSet::standard();
+    assert_eq!(
+      set.compute_instruction(&mut parse("1,0,0,0,99"), 0),
+      ProgramState::Continue(4)
+    );
+    assert_eq!(
+      set.compute_instruction(&mut parse("1201,0,0,0,99"), 0),
+      ProgramState::Fault(Fault::UnsupportedParameterMode {
+        mode: 2,
+        pointer: 0
+      })
+    );
+  }
+
+  #[test]
+  fn relative_set() {
+    let set = InstructionSet::relative();
+    assert_eq!(set.get(9).map(|def| def.name), Some("arb"));
+    assert_eq!(set.parameter_modes(), &[0, 1, 2]);
+
+    // Moves the relative base to 10, reads an input into 9, then adds 11 to it into 12
+    let computer = IntcodeComputer::new(parse("109,10,203,-1,22201,-1,1,2,99,0,0,5,0"))
+      .with_instruction_set(set)
+      .start()
+      .as_input()
+      .unwrap()
+      .execute(3)
+      .as_halt()
+      .unwrap();
+    assert_eq!(computer.borrow_memory()[9..], [3, 0, 5, 8]);
+    assert_eq!(computer.get_relative_base(), 10);
+
+    let mut sequence = parse(&format!("109,{},109,1", isize::MAX));
+    assert_eq!(
+      InstructionSet::relative().compute_instruction(&mut sequence, 0),
+      ProgramState::Continue(2)
+    );
+    let computer = IntcodeComputer::new(sequence)
+      .with_instruction_set(InstructionSet::relative())
+      .start();
+    assert_eq!(
+      computer.as_fault().unwrap().fault,
+      Fault::Overflow { pointer: 2 }
+    );
+  }
+
+  fn overflowing(policy: OverflowPolicy) -> IntcodeComputer {
+    // Doubles 2^62, then adds isize::MIN to the result
+    let program = format!("1002,9,2,9,1,9,10,9,99,{},{}", 1isize << 62, isize::MIN);
+    IntcodeComputer::new(parse(&program))
+      .with_overflow_policy(policy)
+      .start()
+  }
+
+  #[test]
+  fn overflow_faults_by_default() {
+    let program = format!("1002,5,2,5,99,{}", 1isize << 62);
+    let computer = IntcodeComputer::new(parse(&program)).start();
+    let computer = computer.as_fault().unwrap();
+    assert_eq!(computer.fault, Fault::Overflow { pointer: 0 });
+    assert_eq!(
+      computer.fault.to_string(),
+      "Arithmetic overflow at instruction pointer 0"
+    );
+    // The result isn't written
+    assert_eq!(computer.borrow_memory()[5], 1 << 62);
+    assert_eq!(
+      overflowing(OverflowPolicy::Fault).as_fault().unwrap().fault,
+      Fault::Overflow { pointer: 0 }
+    );
+  }
+
+  #[test]
+  fn overflow_policies() {
+    let wrapped = overflowing(OverflowPolicy::Wrap).as_halt().unwrap();
+    assert_eq!(wrapped.borrow_memory()[9], 0);
+    let saturated = overflowing(OverflowPolicy::Saturate).as_halt().unwrap();
+    assert_eq!(saturated.borrow_memory()[9], -1);
+    // Wider words keep the exact result
+    let program = format!("1002,5,2,5,99,{}", 1isize << 62);
+    let words = parse(&program).into_iter().map(BigInt::from).collect();
+    let exact = IntcodeComputer::from_words(words)
+      .start()
+      .as_halt()
+      .unwrap();
+    assert_eq!(exact.borrow_memory()[5], BigInt::from(1i128 << 63));
+  }
+
+  #[test]
+  fn promotes_on_overflow() {
+    let max = isize::MAX;
+    let program = format!("1102,{},{},11,1002,11,{},11,4,11,99,0", max, max, max);
+    let computer = IntcodeComputer::new(parse(&program))
+      .with_overflow_policy(OverflowPolicy::Promote)
+      .with_instruction_set(InstructionSet::relative())
+      .start();
+    let fault = computer.as_fault().unwrap();
+    assert_eq!(fault.fault, Fault::Overflow { pointer: 0 });
+    // Squaring fits in an `i128`, but the cube doesn't
+    let fault = fault.promote::<i128>().unwrap().as_fault().unwrap();
+    assert_eq!(fault.fault, Fault::Overflow { pointer: 4 });
+    assert_eq!(fault.borrow_memory()[11], max as i128 * max as i128);
+    let output = fault.promote::<BigInt>().unwrap().as_output().unwrap();
+    let big = BigInt::from(max);
+    assert_eq!(output.output, &(&big * &big) * &big);
+    let halted = output.execute().as_halt().unwrap();
+    assert_eq!(halted.stats().instructions_executed, 4);
+
+    // Only overflows under `Promote` carry on
+    let computer = IntcodeComputer::new(parse(&program)).start();
+    assert!(computer.as_fault().unwrap().promote::<i128>().is_err());
+    let computer = IntcodeComputer::new(parse("1,0,0,0,42"))
+      .with_overflow_policy(OverflowPolicy::Promote)
+      .start();
+    assert!(computer.as_fault().unwrap().promote::<i128>().is_err());
+    // And custom opcodes can't be widened
+    let mut instruction_set = InstructionSet::standard();
+    instruction_set.register(OpcodeDef::new(42, "nop", 0, &[], |ctx| ctx.next()));
+    let computer = IntcodeComputer::new(parse(&program))
+      .with_overflow_policy(OverflowPolicy::Promote)
+      .with_instruction_set(instruction_set)
+      .start();
+    assert!(computer.as_fault().unwrap().promote::<i128>().is_err());
+  }
+
+  #[test]
+  fn policy_survives_instruction_set_changes() {
+    let program = format!("1002,5,2,5,99,{}", 1isize << 62);
+    let computer = IntcodeComputer::new(parse(&program))
+      .with_overflow_policy(OverflowPolicy::Wrap)
+      .with_instruction_set(InstructionSet::relative())
+      .with_strict_profile(Profile::V05)
+      .start();
+    assert_eq!(computer.as_halt().unwrap().borrow_memory()[5], isize::MIN);
+  }
+
+  #[test]
+  fn overflow_policy_arithmetic() {
+    let policies = [
+      OverflowPolicy::Fault,
+      OverflowPolicy::Wrap,
+      OverflowPolicy::Saturate,
+      OverflowPolicy::Promote,
+    ];
+    for policy in policies.iter() {
+      assert_eq!(policy.add(2isize, 3), Some(5));
+      assert_eq!(policy.mul(-4isize, 3), Some(-12));
+      assert_eq!(
+        policy.add(BigInt::from(2isize), BigInt::from(3isize)),
+        Some(BigInt::from(5isize))
+      );
+    }
+    // Big integers never overflow, whatever the policy
+    let big = BigInt::from(isize::MAX);
+    for policy in policies.iter() {
+      assert_eq!(
+        policy.mul(big.clone(), big.clone()),
+        Some(BigInt::from(isize::MAX as i128 * isize::MAX as i128))
+      );
+    }
+    assert_eq!(OverflowPolicy::Fault.mul(i128::MAX, 2), None);
+    assert_eq!(OverflowPolicy::Wrap.add(isize::MAX, 1), Some(isize::MIN));
+    assert_eq!(
+      OverflowPolicy::Saturate.mul(isize::MIN, 2),
+      Some(isize::MIN)
+    );
+    assert_eq!(
+      OverflowPolicy::Saturate.mul(isize::MIN, -1),
+      Some(isize::MAX)
+    );
+    assert_eq!(OverflowPolicy::Fault.mul(isize::MIN, -1), None);
+    assert_eq!(OverflowPolicy::Promote.add(isize::MAX, 1), None);
+    assert_eq!(OverflowPolicy::default(), OverflowPolicy::Fault);
+  }
+}
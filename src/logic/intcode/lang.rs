@@ -0,0 +1,809 @@
+// A compiler for a tiny language, for writing Intcode programs that people can read.
+//
+//   // Prints the numbers from the input down to one, doubled
+//   let n = input();
+//   while n > 0 {
+//     if n == 3 {
+//       output(-1);
+//     } else {
+//       output(n * 2);
+//     }
+//     n = n - 1;
+//   }
+//
+// Values are words. `let` declares a variable in the current block and `=` assigns to one
+// that's already declared. Expressions have `+`, `-` and `*`, unary `-`, and the
+// comparisons `==`, `!=`, `<`, `<=`, `>` and `>=`, which give 1 or 0. Conditions are true
+// when they aren't 0. There's no division, since Intcode has none. `input()` reads a
+// word and `output(value);` writes one. Comments run from `//` to the end of the line.
+//
+// Programs are compiled to `asm` source, then assembled. Every variable and temporary
+// has a fixed address after the code, so programs only need `InstructionSet::standard`.
+
+use super::{asm, IntcodeSequence};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Why a program failed to compile, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+  /// One-based
+  pub line: usize,
+  /// One-based, in characters
+  pub column: usize,
+  pub message: String,
+}
+
+impl fmt::Display for CompileError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}: {}", self.line, self.column, self.message)
+  }
+}
+
+impl std::error::Error for CompileError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+  line: usize,
+  column: usize,
+}
+
+impl Position {
+  fn error(self, message: String) -> CompileError {
+    CompileError {
+      line: self.line,
+      column: self.column,
+      message,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+  Number(isize),
+  Name(String),
+  Symbol(&'static str),
+  End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+  kind: TokenKind,
+  position: Position,
+}
+
+impl fmt::Display for TokenKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      TokenKind::Number(value) => write!(f, "`{}`", value),
+      TokenKind::Name(name) => write!(f, "`{}`", name),
+      TokenKind::Symbol(symbol) => write!(f, "`{}`", symbol),
+      TokenKind::End => write!(f, "the end of the program"),
+    }
+  }
+}
+
+/// Longest first, so `<=` isn't read as `<` then `=`.
+const SYMBOLS: &[&str] = &[
+  "==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "(", ")", "{", "}", ";",
+];
+
+const KEYWORDS: &[&str] = &["let", "if", "else", "while", "input", "output"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, CompileError> {
+  let mut tokens = vec![];
+  for (index, line) in source.lines().enumerate() {
+    let chars: Vec<char> = line.chars().collect();
+    let mut column = 0;
+    while column < chars.len() {
+      let position = Position {
+        line: index + 1,
+        column: column + 1,
+      };
+      let c = chars[column];
+      if c.is_whitespace() {
+        column += 1;
+        continue;
+      }
+      if chars[column..].starts_with(&['/', '/']) {
+        break;
+      }
+      let kind = if c.is_ascii_digit() {
+        let start = column;
+        while column < chars.len() && chars[column].is_ascii_digit() {
+          column += 1;
+        }
+        let text: String = chars[start..column].iter().collect();
+        let value = text
+          .parse()
+          .map_err(|_| position.error(format!("{} is too big for a word", text)))?;
+        TokenKind::Number(value)
+      } else if c.is_ascii_alphabetic() || c == '_' {
+        let start = column;
+        while column < chars.len()
+          && (chars[column].is_ascii_alphanumeric() || chars[column] == '_')
+        {
+          column += 1;
+        }
+        TokenKind::Name(chars[start..column].iter().collect())
+      } else {
+        let symbol = SYMBOLS
+          .iter()
+          .find(|symbol| {
+            let symbol: Vec<char> = symbol.chars().collect();
+            chars[column..].starts_with(&symbol)
+          })
+          .ok_or_else(|| position.error(format!("Unexpected character {:?}", c)))?;
+        column += symbol.len();
+        TokenKind::Symbol(symbol)
+      };
+      tokens.push(Token { kind, position });
+    }
+  }
+  let lines = source.lines().count();
+  tokens.push(Token {
+    kind: TokenKind::End,
+    position: Position {
+      line: lines.max(1),
+      column: source.lines().last().map_or(0, |line| line.chars().count()) + 1,
+    },
+  });
+  Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+  Add,
+  Sub,
+  Mul,
+  Equal,
+  NotEqual,
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+  Number(isize),
+  Variable(String, Position),
+  Input,
+  Negate(Box<Expr>),
+  Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+  Let(String, Position, Expr),
+  Assign(String, Position, Expr),
+  Output(Expr),
+  If(Expr, Vec<Stmt>, Vec<Stmt>),
+  While(Expr, Vec<Stmt>),
+}
+
+/// A recursive descent parser. Comparisons bind loosest, then `+` and `-`, then `*`.
+struct Parser {
+  tokens: Vec<Token>,
+  next: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> &Token {
+    &self.tokens[self.next]
+  }
+
+  fn advance(&mut self) -> Token {
+    let token = self.tokens[self.next].clone();
+    if token.kind != TokenKind::End {
+      self.next += 1;
+    }
+    token
+  }
+
+  fn accept(&mut self, symbol: &str) -> bool {
+    let found = matches!(&self.peek().kind, TokenKind::Symbol(s) if *s == symbol)
+      || matches!(&self.peek().kind, TokenKind::Name(name) if name == symbol);
+    if found {
+      self.next += 1;
+    }
+    found
+  }
+
+  fn expect(&mut self, symbol: &str) -> Result<(), CompileError> {
+    if self.accept(symbol) {
+      Ok(())
+    } else {
+      Err(self.unexpected(&format!("`{}`", symbol)))
+    }
+  }
+
+  fn unexpected(&self, expected: &str) -> CompileError {
+    let token = self.peek();
+    token
+      .position
+      .error(format!("Expected {}, found {}", expected, token.kind))
+  }
+
+  fn name(&mut self) -> Result<(String, Position), CompileError> {
+    match &self.peek().kind {
+      TokenKind::Name(name) if !KEYWORDS.contains(&name.as_str()) => {
+        let token = self.advance();
+        match token.kind {
+          TokenKind::Name(name) => Ok((name, token.position)),
+          _ => unreachable!(),
+        }
+      }
+      _ => Err(self.unexpected("a variable name")),
+    }
+  }
+
+  fn program(&mut self) -> Result<Vec<Stmt>, CompileError> {
+    let mut statements = vec![];
+    while self.peek().kind != TokenKind::End {
+      statements.push(self.statement()?);
+    }
+    Ok(statements)
+  }
+
+  fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+    self.expect("{")?;
+    let mut statements = vec![];
+    while !self.accept("}") {
+      if self.peek().kind == TokenKind::End {
+        return Err(self.unexpected("`}`"));
+      }
+      statements.push(self.statement()?);
+    }
+    Ok(statements)
+  }
+
+  fn statement(&mut self) -> Result<Stmt, CompileError> {
+    if self.accept("let") {
+      let (name, position) = self.name()?;
+      self.expect("=")?;
+      let value = self.expression()?;
+      self.expect(";")?;
+      Ok(Stmt::Let(name, position, value))
+    } else if self.accept("output") {
+      self.expect("(")?;
+      let value = self.expression()?;
+      self.expect(")")?;
+      self.expect(";")?;
+      Ok(Stmt::Output(value))
+    } else if self.accept("if") {
+      let condition = self.expression()?;
+      let then = self.block()?;
+      let otherwise = if !self.accept("else") {
+        vec![]
+      } else if matches!(&self.peek().kind, TokenKind::Name(name) if name == "if") {
+        vec![self.statement()?]
+      } else {
+        self.block()?
+      };
+      Ok(Stmt::If(condition, then, otherwise))
+    } else if self.accept("while") {
+      let condition = self.expression()?;
+      Ok(Stmt::While(condition, self.block()?))
+    } else if matches!(&self.peek().kind, TokenKind::Name(_)) {
+      let (name, position) = self.name()?;
+      self.expect("=")?;
+      let value = self.expression()?;
+      self.expect(";")?;
+      Ok(Stmt::Assign(name, position, value))
+    } else {
+      Err(self.unexpected("a statement"))
+    }
+  }
+
+  fn expression(&mut self) -> Result<Expr, CompileError> {
+    let mut left = self.sum()?;
+    loop {
+      let op = match &self.peek().kind {
+        TokenKind::Symbol("==") => BinaryOp::Equal,
+        TokenKind::Symbol("!=") => BinaryOp::NotEqual,
+        TokenKind::Symbol("<") => BinaryOp::Less,
+        TokenKind::Symbol("<=") => BinaryOp::LessOrEqual,
+        TokenKind::Symbol(">") => BinaryOp::Greater,
+        TokenKind::Symbol(">=") => BinaryOp::GreaterOrEqual,
+        _ => return Ok(left),
+      };
+      self.advance();
+      left = Expr::Binary(op, Box::new(left), Box::new(self.sum()?));
+    }
+  }
+
+  fn sum(&mut self) -> Result<Expr, CompileError> {
+    let mut left = self.product()?;
+    loop {
+      let op = match &self.peek().kind {
+        TokenKind::Symbol("+") => BinaryOp::Add,
+        TokenKind::Symbol("-") => BinaryOp::Sub,
+        _ => return Ok(left),
+      };
+      self.advance();
+      left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+    }
+  }
+
+  fn product(&mut self) -> Result<Expr, CompileError> {
+    let mut left = self.unary()?;
+    while self.accept("*") {
+      left = Expr::Binary(BinaryOp::Mul, Box::new(left), Box::new(self.unary()?));
+    }
+    Ok(left)
+  }
+
+  fn unary(&mut self) -> Result<Expr, CompileError> {
+    if self.accept("-") {
+      return Ok(Expr::Negate(Box::new(self.unary()?)));
+    }
+    match self.peek().kind.clone() {
+      TokenKind::Number(value) => {
+        self.advance();
+        Ok(Expr::Number(value))
+      }
+      TokenKind::Symbol("(") => {
+        self.advance();
+        let inner = self.expression()?;
+        self.expect(")")?;
+        Ok(inner)
+      }
+      TokenKind::Name(ref name) if name == "input" => {
+        self.advance();
+        self.expect("(")?;
+        self.expect(")")?;
+        Ok(Expr::Input)
+      }
+      TokenKind::Name(ref name) if !KEYWORDS.contains(&name.as_str()) => {
+        let (name, position) = self.name()?;
+        Ok(Expr::Variable(name, position))
+      }
+      _ => Err(self.unexpected("an expression")),
+    }
+  }
+}
+
+/// Where a value is, written as an `asm` operand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operand {
+  Immediate(isize),
+  Address(String),
+}
+
+impl fmt::Display for Operand {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Operand::Immediate(value) => write!(f, "#{}", value),
+      Operand::Address(label) => write!(f, "[{}]", label),
+    }
+  }
+}
+
+/// Emits `asm` source. Temporaries are numbered by how deeply they're nested in an
+/// expression, so the operands of a binary operation never share one.
+struct Generator<'a> {
+  lines: Vec<&'a str>,
+  code: Vec<String>,
+  scopes: Vec<HashMap<String, String>>,
+  variables: Vec<String>,
+  temporaries: usize,
+  labels: usize,
+}
+
+fn temporary(depth: usize) -> String {
+  format!("t_{}", depth)
+}
+
+impl Generator<'_> {
+  fn emit(&mut self, instruction: String) {
+    self.code.push(format!("    {}", instruction));
+  }
+
+  fn label(&mut self) -> String {
+    self.labels += 1;
+    format!("l_{}", self.labels)
+  }
+
+  fn place(&mut self, label: &str) {
+    self.code.push(format!("{}:", label));
+  }
+
+  fn lookup(&self, name: &str, position: Position) -> Result<String, CompileError> {
+    self
+      .scopes
+      .iter()
+      .rev()
+      .find_map(|scope| scope.get(name).cloned())
+      .ok_or_else(|| position.error(format!("`{}` isn't declared", name)))
+  }
+
+  fn block(&mut self, statements: &[Stmt]) -> Result<(), CompileError> {
+    self.scopes.push(HashMap::new());
+    for statement in statements {
+      self.statement(statement)?;
+    }
+    self.scopes.pop();
+    Ok(())
+  }
+
+  fn statement(&mut self, statement: &Stmt) -> Result<(), CompileError> {
+    match statement {
+      Stmt::Let(name, position, value) => {
+        let value = self.expression(value, 0)?;
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(name) {
+          return Err(position.error(format!("`{}` is already declared in this block", name)));
+        }
+        let label = format!("v_{}_{}", name, self.variables.len());
+        scope.insert(name.clone(), label.clone());
+        self.variables.push(label.clone());
+        self.comment(*position);
+        self.emit(format!("add {}, #0, [{}]", value, label));
+      }
+      Stmt::Assign(name, position, value) => {
+        let label = self.lookup(name, *position)?;
+        let value = self.expression(value, 0)?;
+        self.comment(*position);
+        self.emit(format!("add {}, #0, [{}]", value, label));
+      }
+      Stmt::Output(value) => {
+        let value = self.expression(value, 0)?;
+        self.emit(format!("out {}", value));
+      }
+      Stmt::If(condition, then, otherwise) => {
+        let (otherwise_label, end) = (self.label(), self.label());
+        let condition = self.expression(condition, 0)?;
+        self.emit(format!("jz {}, #{}", condition, otherwise_label));
+        self.block(then)?;
+        self.emit(format!("jz #0, #{}", end));
+        self.place(&otherwise_label);
+        self.block(otherwise)?;
+        self.place(&end);
+      }
+      Stmt::While(condition, body) => {
+        let (top, end) = (self.label(), self.label());
+        self.place(&top);
+        let condition = self.expression(condition, 0)?;
+        self.emit(format!("jz {}, #{}", condition, end));
+        self.block(body)?;
+        self.emit(format!("jz #0, #{}", top));
+        self.place(&end);
+      }
+    }
+    Ok(())
+  }
+
+  /// Puts the source line of a statement in the listing, to make it easier to follow.
+  fn comment(&mut self, position: Position) {
+    let line = self.lines[position.line - 1].trim();
+    self.code.push(format!("    ; {}", line));
+  }
+
+  /// Emits code for `expr` and says where its value ends up, using temporaries from
+  /// `depth` up.
+  fn expression(&mut self, expr: &Expr, depth: usize) -> Result<Operand, CompileError> {
+    Ok(match expr {
+      Expr::Number(value) => Operand::Immediate(*value),
+      Expr::Variable(name, position) => Operand::Address(self.lookup(name, *position)?),
+      Expr::Input => {
+        let result = self.temporary(depth);
+        self.emit(format!("in [{}]", result));
+        Operand::Address(result)
+      }
+      Expr::Negate(inner) => {
+        let inner = self.expression(inner, depth)?;
+        if let Operand::Immediate(value) = inner {
+          if let Some(value) = value.checked_neg() {
+            return Ok(Operand::Immediate(value));
+          }
+        }
+        let result = self.temporary(depth);
+        self.emit(format!("mul {}, #-1, [{}]", inner, result));
+        Operand::Address(result)
+      }
+      Expr::Binary(op, left, right) => {
+        let left = self.expression(left, depth)?;
+        let right = self.expression(right, depth + 1)?;
+        if let (Operand::Immediate(a), Operand::Immediate(b)) = (&left, &right) {
+          if let Some(value) = fold(*op, *a, *b) {
+            return Ok(Operand::Immediate(value));
+          }
+        }
+        let result = self.temporary(depth);
+        self.binary(*op, left, right, &result, depth);
+        Operand::Address(result)
+      }
+    })
+  }
+
+  fn binary(&mut self, op: BinaryOp, left: Operand, right: Operand, result: &str, depth: usize) {
+    let (name, left, right, negate) = match op {
+      BinaryOp::Add => ("add", left, right, false),
+      BinaryOp::Sub => {
+        let right = match right {
+          Operand::Immediate(value) if value.checked_neg().is_some() => Operand::Immediate(-value),
+          // Can't be negated, but `left - MIN` is `left + MAX + 1`, which overflows for the
+          // same values of `left`
+          Operand::Immediate(isize::MIN) => {
+            self.emit(format!("add {}, #{}, [{}]", left, isize::MAX, result));
+            self.emit(format!("add [{}], #1, [{}]", result, result));
+            return;
+          }
+          right => {
+            let negated = self.temporary(depth + 1);
+            self.emit(format!("mul {}, #-1, [{}]", right, negated));
+            Operand::Address(negated)
+          }
+        };
+        ("add", left, right, false)
+      }
+      BinaryOp::Mul => ("mul", left, right, false),
+      BinaryOp::Equal => ("eq", left, right, false),
+      BinaryOp::NotEqual => ("eq", left, right, true),
+      BinaryOp::Less => ("lt", left, right, false),
+      BinaryOp::LessOrEqual => ("lt", right, left, true),
+      BinaryOp::Greater => ("lt", right, left, false),
+      BinaryOp::GreaterOrEqual => ("lt", left, right, true),
+    };
+    self.emit(format!("{} {}, {}, [{}]", name, left, right, result));
+    if negate {
+      self.emit(format!("eq [{}], #0, [{}]", result, result));
+    }
+  }
+
+  fn temporary(&mut self, depth: usize) -> String {
+    self.temporaries = self.temporaries.max(depth + 1);
+    temporary(depth)
+  }
+}
+
+/// Works out an operation on two constants, unless it would overflow.
+fn fold(op: BinaryOp, a: isize, b: isize) -> Option<isize> {
+  match op {
+    BinaryOp::Add => a.checked_add(b),
+    BinaryOp::Sub => a.checked_sub(b),
+    BinaryOp::Mul => a.checked_mul(b),
+    BinaryOp::Equal => Some((a == b) as isize),
+    BinaryOp::NotEqual => Some((a != b) as isize),
+    BinaryOp::Less => Some((a < b) as isize),
+    BinaryOp::LessOrEqual => Some((a <= b) as isize),
+    BinaryOp::Greater => Some((a > b) as isize),
+    BinaryOp::GreaterOrEqual => Some((a >= b) as isize),
+  }
+}
+
+/// Compiles `source` to `asm` source, with the lines that declare and assign variables in
+/// comments.
+pub fn compile_to_asm(source: &str) -> Result<String, CompileError> {
+  let statements = Parser {
+    tokens: tokenize(source)?,
+    next: 0,
+  }
+  .program()?;
+  let mut generator = Generator {
+    lines: source.lines().collect(),
+    code: vec![],
+    scopes: vec![],
+    variables: vec![],
+    temporaries: 0,
+    labels: 0,
+  };
+  generator.block(&statements)?;
+  generator.emit("halt".to_string());
+  for variable in generator.variables.clone() {
+    generator.code.push(format!("{}: data 0", variable));
+  }
+  for depth in 0..generator.temporaries {
+    generator.code.push(format!("{}: data 0", temporary(depth)));
+  }
+  let mut listing = generator.code.join("\n");
+  listing.push('\n');
+  Ok(listing)
+}
+
+/// Compiles `source` to a program that runs with the standard instruction set.
+pub fn compile(source: &str) -> Result<IntcodeSequence, CompileError> {
+  let listing = compile_to_asm(source)?;
+  // Only a bug in the compiler gets here, so there's no better place in the source to
+  // point at
+  let assembly = asm::assemble(&listing).map_err(|err| {
+    let start = Position { line: 1, column: 1 };
+    start.error(format!("Compiled code didn't assemble: {}", err))
+  })?;
+  Ok(assembly.sequence)
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{Fault, IntcodeComputer};
+  use super::*;
+
+  fn run(source: &str, inputs: &[isize]) -> Vec<isize> {
+    let sequence = compile(source).unwrap_or_else(|err| panic!("{}", err));
+    let mut inputs = inputs.iter();
+    let mut outputs = vec![];
+    let mut computer = IntcodeComputer::new(sequence).start();
+    loop {
+      computer = match computer {
+        IntcodeComputer::Input(state) => state.execute(*inputs.next().unwrap()),
+        IntcodeComputer::Output(state) => {
+          outputs.push(state.output);
+          state.execute()
+        }
+        IntcodeComputer::Halt(_) => return outputs,
+        computer => panic!("Unexpected {:?}", computer),
+      }
+    }
+  }
+
+  fn error(source: &str) -> String {
+    compile(source).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn arithmetic() {
+    let source = "
+      let a = input();
+      let b = input();
+      output(a + b);
+      output(a - b);
+      output(a * b);
+      output(-a);
+      output(2 + 3 * -4 - (1 - a) * b);
+    ";
+    assert_eq!(run(source, &[7, 3]), vec![10, 4, 21, -7, 8]);
+  }
+
+  #[test]
+  fn comparisons() {
+    let source = "
+      let a = input();
+      let b = input();
+      output(a == b);
+      output(a != b);
+      output(a < b);
+      output(a <= b);
+      output(a > b);
+      output(a >= b);
+    ";
+    assert_eq!(run(source, &[1, 2]), vec![0, 1, 1, 1, 0, 0]);
+    assert_eq!(run(source, &[2, 2]), vec![1, 0, 0, 1, 0, 1]);
+    assert_eq!(run(source, &[3, 2]), vec![0, 1, 0, 0, 1, 1]);
+  }
+
+  #[test]
+  fn folds_constants() {
+    let listing = compile_to_asm("output(2 * (3 + 4) < 15);").unwrap();
+    assert_eq!(listing, "    out #1\n    halt\n");
+  }
+
+  #[test]
+  fn most_negative_word() {
+    let min = "(-9223372036854775807 - 1)";
+    assert_eq!(run(&format!("output({});", min), &[]), vec![isize::MIN]);
+    let source = format!("let x = input();\noutput(x - {});", min);
+    assert_eq!(run(&source, &[-1]), vec![isize::MAX]);
+    assert_eq!(run(&source, &[isize::MIN]), vec![0]);
+    // Negating it overflows, when the program runs
+    for source in [
+      format!("output(-{});", min),
+      format!("output(0 - {});", min),
+    ] {
+      let sequence = compile(&source).unwrap();
+      let computer = IntcodeComputer::new(sequence).start();
+      let fault = computer.as_fault().map(|state| state.fault.clone());
+      assert!(
+        matches!(fault, Ok(Fault::Overflow { .. })),
+        "{}: {:?}",
+        source,
+        fault
+      );
+    }
+  }
+
+  #[test]
+  fn control_flow() {
+    let source = "
+      // Collatz steps
+      let n = input();
+      let steps = 0;
+      while n != 1 {
+        let half = 0;
+        while half * 2 < n {
+          half = half + 1;
+        }
+        if half * 2 == n {
+          n = half;
+        } else {
+          n = 3 * n + 1;
+        }
+        steps = steps + 1;
+      }
+      output(steps);
+    ";
+    assert_eq!(run(source, &[6]), vec![8]);
+    assert_eq!(run(source, &[27]), vec![111]);
+  }
+
+  #[test]
+  fn else_if() {
+    let source = "
+      let x = input();
+      if x < 0 {
+        output(-1);
+      } else if x == 0 {
+        output(0);
+      } else {
+        output(1);
+      }
+    ";
+    assert_eq!(run(source, &[-5]), vec![-1]);
+    assert_eq!(run(source, &[0]), vec![0]);
+    assert_eq!(run(source, &[5]), vec![1]);
+  }
+
+  #[test]
+  fn scopes() {
+    let source = "
+      let x = 1;
+      if 1 {
+        let x = x + 10;
+        output(x);
+      }
+      output(x);
+    ";
+    assert_eq!(run(source, &[]), vec![11, 1]);
+  }
+
+  #[test]
+  fn runs_with_standard_instructions() {
+    let sequence = compile("output(input() * 2);").unwrap();
+    let computer = IntcodeComputer::new(sequence).start();
+    let computer = match computer {
+      IntcodeComputer::Input(state) => state.execute(21),
+      computer => panic!("Unexpected {:?}", computer),
+    };
+    match computer {
+      IntcodeComputer::Output(state) => {
+        assert_eq!(state.output, 42);
+        assert!(matches!(state.execute(), IntcodeComputer::Halt(_)));
+      }
+      computer => panic!("Unexpected {:?}", computer),
+    }
+  }
+
+  #[test]
+  fn errors() {
+    assert_eq!(error("output(y);"), "1:8: `y` isn't declared");
+    assert_eq!(
+      error("let x = 1;\nx = 2 $ 3;"),
+      "2:7: Unexpected character '$'"
+    );
+    assert_eq!(
+      error("let x = 1\noutput(x);"),
+      "2:1: Expected `;`, found `output`"
+    );
+    assert_eq!(
+      error("let x = 1;\nlet x = 2;"),
+      "2:5: `x` is already declared in this block"
+    );
+    assert_eq!(
+      error("output(1 + );"),
+      "1:12: Expected an expression, found `)`"
+    );
+    assert_eq!(
+      error("while 1 {\n  output(1);"),
+      "2:13: Expected `}`, found the end of the program"
+    );
+    assert_eq!(
+      error("let while = 1;"),
+      "1:5: Expected a variable name, found `while`"
+    );
+    assert_eq!(error("  1 = 2;"), "1:3: Expected a statement, found `1`");
+    assert_eq!(
+      error("output(99999999999999999999);"),
+      "1:8: 99999999999999999999 is too big for a word"
+    );
+  }
+}
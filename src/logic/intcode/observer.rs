@@ -0,0 +1,135 @@
+// Hooks for watching a computer run, for tracers, profilers and the like.
+
+use super::word::Word;
+use super::{Fault, IntcodeMemory};
+use std::any::Any;
+use std::fmt;
+
+/// Callbacks made while a computer runs. Every method does nothing by default, so an
+/// observer only needs to implement the events it cares about.
+pub trait ComputeObserver<W: Word = isize>: Any + Send {
+  /// Called before the instruction at `pointer` executes, including instructions that
+  /// go on to fault or wait for input.
+  fn before_instruction(&mut self, _pointer: usize, _memory: &IntcodeMemory<W>) {}
+
+  /// Called after an instruction writes to memory, including writes of unchanged values.
+  fn on_memory_write(&mut self, _address: usize, _old: W, _new: W) {}
+
+  fn on_input(&mut self, _input: W) {}
+
+  fn on_output(&mut self, _output: W) {}
+
+  fn on_halt(&mut self, _pointer: usize) {}
+
+  fn on_fault(&mut self, _fault: &Fault) {}
+}
+
+/// An observer attached to a computer.
+pub(super) struct Attached<W: Word>(pub(super) Box<dyn ComputeObserver<W>>);
+
+impl<W: Word> fmt::Debug for Attached<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ComputeObserver")
+  }
+}
+
+impl<W: Word> Attached<W> {
+  pub(super) fn downcast_ref<T: ComputeObserver<W>>(&self) -> Option<&T> {
+    (self.0.as_ref() as &dyn Any).downcast_ref()
+  }
+
+  pub(super) fn downcast_mut<T: ComputeObserver<W>>(&mut self) -> Option<&mut T> {
+    (self.0.as_mut() as &mut dyn Any).downcast_mut()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, IntcodeComputer, IntcodeComputerState, IntcodeSequence};
+  use super::*;
+
+  #[derive(Default)]
+  struct Tracer {
+    events: Vec<String>,
+  }
+
+  impl ComputeObserver for Tracer {
+    fn before_instruction(&mut self, pointer: usize, memory: &IntcodeSequence) {
+      self
+        .events
+        .push(format!("step {} ({})", pointer, memory[pointer]));
+    }
+    fn on_memory_write(&mut self, address: usize, old: isize, new: isize) {
+      self
+        .events
+        .push(format!("write {}: {} -> {}", address, old, new));
+    }
+    fn on_input(&mut self, input: isize) {
+      self.events.push(format!("in {}", input));
+    }
+    fn on_output(&mut self, output: isize) {
+      self.events.push(format!("out {}", output));
+    }
+    fn on_halt(&mut self, pointer: usize) {
+      self.events.push(format!("halt {}", pointer));
+    }
+    fn on_fault(&mut self, fault: &Fault) {
+      self.events.push(format!("fault {}", fault));
+    }
+  }
+
+  #[test]
+  fn traces_run() {
+    let computer = IntcodeComputer::new(parse("3,9,8,9,10,9,4,9,99,-1,8"))
+      .with_observer(Tracer::default())
+      .start()
+      .as_input()
+      .unwrap()
+      .execute(8)
+      .as_output()
+      .unwrap()
+      .execute();
+    assert_eq!(
+      computer.observer::<Tracer>().unwrap().events,
+      vec![
+        "step 0 (3)",
+        "in 8",
+        "write 9: -1 -> 8",
+        "step 2 (8)",
+        "write 9: 8 -> 1",
+        "step 6 (4)",
+        "out 1",
+        "step 8 (99)",
+        "halt 8",
+      ]
+    );
+  }
+
+  #[test]
+  fn sees_faults() {
+    let mut computer = IntcodeComputer::new(parse("1101,1,1,0,42"))
+      .with_observer(Tracer::default())
+      .start();
+    let tracer = computer.observer_mut::<Tracer>().unwrap();
+    assert_eq!(
+      tracer.events.last().unwrap(),
+      "fault Unrecognized opcode 42 at instruction pointer 4"
+    );
+    tracer.events.clear();
+    assert!(computer.observer::<Tracer>().unwrap().events.is_empty());
+  }
+
+  #[test]
+  fn only_downcasts_to_attached_type() {
+    struct Other;
+    impl ComputeObserver for Other {}
+    let computer = IntcodeComputer::new(parse("99"))
+      .with_observer(Tracer::default())
+      .start();
+    assert!(computer.observer::<Other>().is_none());
+    assert!(IntcodeComputer::new(parse("99"))
+      .start()
+      .observer::<Tracer>()
+      .is_none());
+  }
+}
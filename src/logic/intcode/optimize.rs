@@ -0,0 +1,494 @@
+// A peephole optimizer for Intcode programs.
+//
+// Without relative mode, every address a program reads or writes is a constant in one of
+// its position-mode parameters, so the words used as data can all be found up front.
+// Instructions that touch none of those words are rewritten in place, and ones that end
+// up doing nothing are removed, moving everything after them down. Programs whose jump
+// targets are only known at runtime are left alone, since any word could be code, and so
+// are programs that use relative mode or that the instruction set can't fully decode.
+//
+// Removing words changes addresses, so callers that patch a program or read its memory
+// afterwards should go through `Optimized::relocate`.
+
+use super::disasm::{decode_at, CodeMap, DecodedInstruction};
+use super::{InstructionSet, IntcodeSequence};
+use std::fmt;
+
+/// What the optimizer did to a program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptimizerStats {
+  pub folded_constants: usize,
+  pub simplified_multiplications: usize,
+  pub removed_jumps: usize,
+  pub removed_moves: usize,
+  pub words_before: usize,
+  pub words_after: usize,
+  /// Why the program was left as it was, if it was
+  pub skipped: Option<&'static str>,
+  /// Why nothing could be removed, if instructions were still rewritten
+  pub kept_layout: Option<&'static str>,
+}
+
+impl fmt::Display for OptimizerStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(reason) = self.skipped {
+      return write!(f, "not optimized: {}", reason);
+    }
+    writeln!(f, "constant expressions folded: {}", self.folded_constants)?;
+    writeln!(
+      f,
+      "multiplications simplified: {}",
+      self.simplified_multiplications
+    )?;
+    writeln!(
+      f,
+      "jumps to the next instruction removed: {}",
+      self.removed_jumps
+    )?;
+    writeln!(f, "no-op moves removed: {}", self.removed_moves)?;
+    write!(
+      f,
+      "size: {} -> {} words",
+      self.words_before, self.words_after
+    )?;
+    if let Some(reason) = self.kept_layout {
+      write!(f, "\nnothing removed: {}", reason)?;
+    }
+    Ok(())
+  }
+}
+
+/// An optimized program, along with where its words came from.
+#[derive(Debug, Clone)]
+pub struct Optimized {
+  pub sequence: IntcodeSequence,
+  pub stats: OptimizerStats,
+  original: IntcodeSequence,
+  /// The new address of each original word, or of the next word kept if it was removed
+  relocations: Vec<usize>,
+  /// Original words that are only ever executed, never read or written
+  code: Vec<bool>,
+}
+
+impl Optimized {
+  /// Where the word at `address` in the original program ended up. Addresses past the end
+  /// are left as they are, so they stay out of bounds.
+  pub fn relocate(&self, address: usize) -> usize {
+    self.relocations.get(address).cloned().unwrap_or(address)
+  }
+
+  /// Lays out `memory` from a run of the optimized program like the original program's
+  /// memory, so the two can be compared. Code words are taken from the original program.
+  pub fn original_layout(&self, memory: &IntcodeSequence) -> IntcodeSequence {
+    (0..self.original.len())
+      .map(|address| match self.code[address] {
+        true => self.original[address],
+        false => memory[self.relocate(address)],
+      })
+      .collect()
+  }
+}
+
+fn is_position(instruction: &DecodedInstruction, param: usize) -> bool {
+  instruction.parameter_modes[param] == 0
+}
+
+fn is_immediate(instruction: &DecodedInstruction, param: usize, value: isize) -> bool {
+  instruction.parameter_modes[param] == 1 && instruction.parameters[param] == value
+}
+
+/// Encodes an instruction from its opcode and (mode, value) parameters.
+fn encode(opcode: isize, params: &[(u8, isize)]) -> Vec<isize> {
+  let modes = params
+    .iter()
+    .rev()
+    .fold(0, |modes, (mode, _)| modes * 10 + isize::from(*mode));
+  let mut words = vec![modes * 100 + opcode];
+  words.extend(params.iter().map(|(_, value)| *value));
+  words
+}
+
+/// `add #value, #0, [destination]`
+fn constant_move(value: isize, destination: isize) -> Vec<isize> {
+  encode(1, &[(1, value), (1, 0), (0, destination)])
+}
+
+/// Replaces arithmetic on two immediate values with a move of the result.
+fn fold(instruction: &DecodedInstruction) -> Option<Vec<isize>> {
+  if !matches!(instruction.opcode, 1 | 2 | 7 | 8) || instruction.parameter_modes[..2] != [1, 1] {
+    return None;
+  }
+  let (a, b) = (instruction.parameters[0], instruction.parameters[1]);
+  let value = match instruction.opcode {
+    // Leave overflow for the computer to report under its own policy
+    1 => a.checked_add(b)?,
+    2 => a.checked_mul(b)?,
+    7 => isize::from(a < b),
+    _ => isize::from(a == b),
+  };
+  let folded = constant_move(value, instruction.parameters[2]);
+  // Already a move
+  let params: Vec<_> = instruction
+    .parameter_modes
+    .iter()
+    .cloned()
+    .zip(instruction.parameters.iter().cloned())
+    .collect();
+  match folded == encode(isize::from(instruction.opcode), &params) {
+    true => None,
+    false => Some(folded),
+  }
+}
+
+/// Turns multiplications by one into moves and multiplications by zero into constants.
+fn simplify_multiplication(instruction: &DecodedInstruction) -> Option<Vec<isize>> {
+  if instruction.opcode != 2 {
+    return None;
+  }
+  let destination = instruction.parameters[2];
+  if (0..2).any(|param| is_immediate(instruction, param, 0)) {
+    return Some(constant_move(0, destination));
+  }
+  let other = (0..2)
+    .find(|param| is_immediate(instruction, *param, 1))
+    .map(|param| 1 - param)?;
+  Some(encode(
+    1,
+    &[
+      (
+        instruction.parameter_modes[other],
+        instruction.parameters[other],
+      ),
+      (1, 0),
+      (0, destination),
+    ],
+  ))
+}
+
+/// `add [x], #0, [x]` and the like, which write back the value already there.
+fn is_noop_move(instruction: &DecodedInstruction) -> bool {
+  let identity = match instruction.opcode {
+    1 => 0,
+    2 => 1,
+    _ => return false,
+  };
+  (0..2).any(|param| {
+    let source = 1 - param;
+    is_immediate(instruction, param, identity)
+      && is_position(instruction, source)
+      && instruction.parameters[source] == instruction.parameters[2]
+  })
+}
+
+/// Returns the optimized program, leaving `sequence` as it is.
+pub fn optimize(sequence: &IntcodeSequence) -> Optimized {
+  optimize_with(sequence, &InstructionSet::standard())
+}
+
+/// Like `optimize`, for programs meant to run with `instruction_set`.
+pub fn optimize_with(sequence: &IntcodeSequence, instruction_set: &InstructionSet) -> Optimized {
+  let code_map = CodeMap::new(sequence, instruction_set);
+  let len = sequence.len();
+  let mut stats = OptimizerStats {
+    words_before: len,
+    words_after: len,
+    ..OptimizerStats::default()
+  };
+  let unchanged = |stats| Optimized {
+    sequence: sequence.clone(),
+    stats,
+    original: sequence.clone(),
+    relocations: (0..len).collect(),
+    code: vec![false; len],
+  };
+  if !code_map.dynamic_jumps.is_empty() {
+    stats.skipped = Some("the program has jumps whose targets are only known at runtime");
+    return unchanged(stats);
+  }
+  // Whatever comes after an undecodable word can't be found, so it would be moved without
+  // being relocated
+  if !code_map.undecodable.is_empty() {
+    stats.skipped = Some("some reachable words aren't instructions of the instruction set");
+    return unchanged(stats);
+  }
+  let uses_relative_mode = code_map
+    .instructions
+    .values()
+    .any(|instruction| instruction.parameter_modes.contains(&2));
+  if uses_relative_mode {
+    stats.skipped = Some("the program uses relative mode, so any word could be data");
+    return unchanged(stats);
+  }
+
+  // Words that are read or written as data, and instructions that must not be touched
+  let mut data = vec![false; len];
+  let mut executed = vec![0; len];
+  let mut untouchable = vec![];
+  for instruction in code_map.instructions.values() {
+    let mut touchable = true;
+    for (param, value) in instruction.parameters.iter().enumerate() {
+      let writes = instruction.writes.contains(&(param as u8));
+      match instruction.parameter_modes[param] {
+        0 if *value >= 0 && (*value as usize) < len => data[*value as usize] = true,
+        // Out of bounds accesses and immediate writes are left to fail as they would
+        1 if !writes => (),
+        _ => touchable = false,
+      }
+    }
+    if !touchable {
+      untouchable.push(instruction.address);
+    }
+    for count in executed[instruction.address..instruction.address + instruction.len()].iter_mut() {
+      *count += 1;
+    }
+  }
+  let is_code = |address: usize| executed[address] == 1 && !data[address];
+  let code: Vec<bool> = (0..len).map(is_code).collect();
+  let can_remove = (0..len).all(|address| executed[address] == 0 || code[address]);
+  if !can_remove {
+    stats.kept_layout = Some("some instructions overlap or are used as data");
+  }
+
+  let mut optimized = sequence.clone();
+  let mut removed = vec![false; len];
+  // Going backwards means the instructions after a jump are already dealt with
+  for address in code_map.instructions.keys().rev() {
+    let mut instruction = code_map.instructions[address].clone();
+    let words = instruction.address..instruction.address + instruction.len();
+    if untouchable.contains(address) || !words.clone().all(&is_code) {
+      continue;
+    }
+    let rewritten = match fold(&instruction) {
+      Some(words) => {
+        stats.folded_constants += 1;
+        Some(words)
+      }
+      None => {
+        simplify_multiplication(&instruction).inspect(|_| stats.simplified_multiplications += 1)
+      }
+    };
+    if let Some(words) = rewritten {
+      optimized[instruction.address..instruction.address + words.len()].copy_from_slice(&words);
+      instruction = decode_at(&optimized, instruction.address, instruction_set).unwrap();
+    }
+    if !can_remove {
+      continue;
+    }
+    let jumps_to_next = instruction.static_jump_target().is_some_and(|target| {
+      target >= words.end && (words.end..target).all(|address| removed.get(address) == Some(&true))
+    });
+    if jumps_to_next || is_noop_move(&instruction) {
+      if jumps_to_next {
+        stats.removed_jumps += 1;
+      } else {
+        stats.removed_moves += 1;
+      }
+      for address in words {
+        removed[address] = true;
+      }
+    }
+  }
+
+  let mut relocations = Vec::with_capacity(len);
+  let mut next = 0;
+  for is_removed in removed.iter() {
+    relocations.push(next);
+    if !is_removed {
+      next += 1;
+    }
+  }
+  let mut result = Optimized {
+    sequence: vec![],
+    stats,
+    original: sequence.clone(),
+    relocations,
+    code,
+  };
+  if next < len {
+    // Point every address parameter and jump target at where its word went
+    for address in code_map.instructions.keys() {
+      if removed[*address] {
+        continue;
+      }
+      let instruction = decode_at(&optimized, *address, instruction_set).unwrap();
+      let jump_target = instruction.static_jump_target().map(|_| 1);
+      for (param, value) in instruction.parameters.iter().enumerate() {
+        let is_address = is_position(&instruction, param) || jump_target == Some(param);
+        if is_address && *value >= 0 {
+          optimized[address + 1 + param] = result.relocate(*value as usize) as isize;
+        }
+      }
+    }
+  }
+  result.sequence = optimized
+    .into_iter()
+    .zip(removed)
+    .filter(|(_, removed)| !removed)
+    .map(|(word, _)| word)
+    .collect();
+  result.stats.words_after = result.sequence.len();
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::fuzz::{self, Engine, ProgramGenerator, RunOutcome, StateMachineEngine};
+  use super::super::parse;
+  use super::*;
+
+  #[test]
+  fn folds_constants() {
+    let optimized = optimize(&parse("1102,3,4,9,1107,5,2,10,99,0,0"));
+    assert_eq!(optimized.sequence, parse("1101,12,0,9,1101,0,0,10,99,0,0"));
+    assert_eq!(optimized.stats.folded_constants, 2);
+    // Overflow is left for the computer to handle
+    let overflowing = format!("1102,{},2,5,99,0", isize::MAX);
+    assert_eq!(optimize(&parse(&overflowing)).sequence, parse(&overflowing));
+  }
+
+  #[test]
+  fn simplifies_multiplications() {
+    let optimized = optimize(&parse("1002,13,1,14,102,0,13,15,2,13,14,14,99,6,0,0"));
+    assert_eq!(
+      optimized.sequence,
+      parse("1001,13,0,14,1101,0,0,15,2,13,14,14,99,6,0,0")
+    );
+    assert_eq!(optimized.stats.simplified_multiplications, 2);
+  }
+
+  #[test]
+  fn removes_jumps_to_next_instruction() {
+    // Both jumps end up at the output, once the move between them is gone
+    let optimized = optimize(&parse("1005,13,3,1005,13,10,1001,13,0,13,4,13,99,7"));
+    assert_eq!(optimized.sequence, parse("4,3,99,7"));
+    assert_eq!(optimized.stats.removed_jumps, 2);
+    assert_eq!(optimized.stats.removed_moves, 1);
+    assert_eq!(optimized.relocate(13), 3);
+    assert_eq!(optimized.relocate(3), 0);
+  }
+
+  #[test]
+  fn relocates_jump_targets() {
+    // Loops back to the output, past a no-op move
+    let sequence = parse("1001,14,0,14,4,15,1001,15,-1,15,1005,15,4,99,0,3");
+    let optimized = optimize(&sequence);
+    assert_eq!(
+      optimized.sequence,
+      parse("4,11,1001,11,-1,11,1005,11,0,99,0,3")
+    );
+    let expected = StateMachineEngine.run(&sequence, &[]);
+    let actual = StateMachineEngine.run(&optimized.sequence, &[]);
+    assert_eq!(actual.outputs, expected.outputs);
+    assert_eq!(
+      optimized.original_layout(&actual.memory.unwrap()),
+      expected.memory.unwrap()
+    );
+  }
+
+  #[test]
+  fn preserves_data() {
+    // The jump is read as data by the output instruction, so it has to stay put
+    let sequence = parse("1105,1,3,4,1,99");
+    let optimized = optimize(&sequence);
+    assert_eq!(optimized.sequence, sequence);
+    assert!(optimized.stats.kept_layout.is_some());
+    // A write into an instruction protects it
+    let sequence = parse("1101,2,3,6,1102,2,3,9,99,0");
+    assert_eq!(
+      optimize(&sequence).sequence,
+      parse("1101,5,0,6,1102,2,3,9,99,0")
+    );
+  }
+
+  #[test]
+  fn skips_dynamic_jumps() {
+    let sequence = parse("3,8,1105,1,5,5,8,8,99");
+    let optimized = optimize(&sequence);
+    assert_eq!(optimized.sequence, sequence);
+    assert_eq!(
+      optimized.stats.to_string(),
+      "not optimized: the program has jumps whose targets are only known at runtime"
+    );
+  }
+
+  #[test]
+  fn skips_other_instruction_sets() {
+    // Standard only up to the `109`, which would leave the `1101`'s address unrelocated
+    let sequence = parse("1105,1,3,109,5,1101,2,3,12,204,7,99,0");
+    let optimized = optimize(&sequence);
+    assert_eq!(optimized.sequence, sequence);
+    assert_eq!(
+      optimized.stats.skipped,
+      Some("some reachable words aren't instructions of the instruction set")
+    );
+    let optimized = optimize_with(&sequence, &InstructionSet::relative());
+    assert_eq!(optimized.sequence, sequence);
+    assert_eq!(
+      optimized.stats.skipped,
+      Some("the program uses relative mode, so any word could be data")
+    );
+    // Adjusting the relative base on its own doesn't get in the way
+    let optimized = optimize_with(
+      &parse("109,5,1105,1,5,4,8,99,42"),
+      &InstructionSet::relative(),
+    );
+    assert_eq!(optimized.sequence, parse("109,5,4,5,99,42"));
+  }
+
+  #[test]
+  fn reports() {
+    let optimized = optimize(&parse("1102,3,4,8,1105,1,7,99,0"));
+    assert_eq!(optimized.sequence, parse("1101,12,0,5,99,0"));
+    assert_eq!(
+      optimized.stats.to_string(),
+      "constant expressions folded: 1\n\
+       multiplications simplified: 0\n\
+       jumps to the next instruction removed: 1\n\
+       no-op moves removed: 0\n\
+       size: 9 -> 6 words"
+    );
+  }
+
+  struct OptimizedEngine;
+
+  impl Engine for OptimizedEngine {
+    fn name(&self) -> &str {
+      "optimized"
+    }
+
+    fn run(&self, sequence: &IntcodeSequence, inputs: &[isize]) -> RunOutcome {
+      let optimized = optimize(sequence);
+      let mut outcome = StateMachineEngine.run(&optimized.sequence, inputs);
+      outcome.memory = outcome
+        .memory
+        .map(|memory| optimized.original_layout(&memory));
+      outcome
+    }
+  }
+
+  #[test]
+  fn matches_unoptimized() {
+    // Small values make for plenty of constants, zeroes and ones to optimize
+    let generator = ProgramGenerator {
+      value_range: (-2, 2),
+      ..ProgramGenerator::default()
+    };
+    let engines: [&dyn Engine; 2] = [&StateMachineEngine, &OptimizedEngine];
+    if let Err(divergence) = fuzz::differential_test(&engines, &generator, 0, 500) {
+      panic!("{:#?}", divergence);
+    }
+    // Make sure the cases exercised every optimization
+    let mut totals = OptimizerStats::default();
+    for seed in 0..500 {
+      let stats = optimize(&generator.generate(&mut fuzz::Rng::new(seed)).sequence).stats;
+      totals.folded_constants += stats.folded_constants;
+      totals.simplified_multiplications += stats.simplified_multiplications;
+      totals.removed_jumps += stats.removed_jumps;
+      totals.removed_moves += stats.removed_moves;
+    }
+    assert!(totals.folded_constants > 0, "{:?}", totals);
+    assert!(totals.simplified_multiplications > 0, "{:?}", totals);
+    assert!(totals.removed_jumps > 0, "{:?}", totals);
+    assert!(totals.removed_moves > 0, "{:?}", totals);
+  }
+}
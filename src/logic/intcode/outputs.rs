@@ -0,0 +1,407 @@
+// Structured access to a computer's output stream, for programs that emit their
+// results in fixed-size groups like (x, y, tile).
+
+use super::{BreakReason, Fault, IntcodeComputer};
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+
+/// Why an `OutputStream` stopped producing values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEnd {
+  Halted,
+  /// The program asked for input after the supplied inputs ran out
+  NeedsInput,
+  Fault(Fault),
+  /// The debugger stopped the program; resume it from `into_computer`
+  Break(BreakReason),
+}
+
+/// Runs a computer, feeding it inputs as needed and yielding each of its outputs.
+pub struct OutputStream<I> {
+  computer: Option<IntcodeComputer>,
+  inputs: I,
+  end: Option<StreamEnd>,
+}
+
+impl IntcodeComputer {
+  pub fn outputs<I>(self, inputs: I) -> OutputStream<I::IntoIter>
+  where
+    I: IntoIterator<Item = isize>,
+  {
+    OutputStream {
+      computer: Some(self),
+      inputs: inputs.into_iter(),
+      end: None,
+    }
+  }
+}
+
+impl<I> OutputStream<I> {
+  /// Why the stream ended, or `None` if it hasn't yet.
+  pub fn end(&self) -> Option<&StreamEnd> {
+    self.end.as_ref()
+  }
+
+  /// The computer in whatever state it was left in.
+  pub fn into_computer(self) -> Option<IntcodeComputer> {
+    self.computer
+  }
+
+  /// Groups outputs into arrays of `N`. Panics if `N` is 0.
+  pub fn grouped<const N: usize>(self) -> Grouped<I, N> {
+    assert!(N > 0, "Can't group outputs in groups of 0");
+    Grouped {
+      outputs: self,
+      index: 0,
+      done: false,
+    }
+  }
+
+  /// Groups outputs and converts each group to `T`. Panics if `T::WIDTH` is 0.
+  pub fn decode<T: FromOutputs>(self) -> Decoded<I, T> {
+    assert!(T::WIDTH > 0, "Can't decode outputs in groups of 0");
+    Decoded {
+      outputs: self,
+      index: 0,
+      done: false,
+      decoded: PhantomData,
+    }
+  }
+}
+
+impl<I: Iterator<Item = isize>> Iterator for OutputStream<I> {
+  type Item = isize;
+
+  fn next(&mut self) -> Option<isize> {
+    loop {
+      let (computer, end) = match self.computer.take()? {
+        IntcodeComputer::Output(state) => {
+          let output = state.output;
+          self.computer = Some(state.execute());
+          return Some(output);
+        }
+        IntcodeComputer::Input(state) => match self.inputs.next() {
+          Some(input) => (state.execute(input), None),
+          None => (IntcodeComputer::Input(state), Some(StreamEnd::NeedsInput)),
+        },
+        IntcodeComputer::Halt(state) => (IntcodeComputer::Halt(state), Some(StreamEnd::Halted)),
+        IntcodeComputer::Fault(state) => {
+          let end = StreamEnd::Fault(state.fault.clone());
+          (IntcodeComputer::Fault(state), Some(end))
+        }
+        IntcodeComputer::Break(state) => {
+          let end = StreamEnd::Break(state.reason.clone());
+          (IntcodeComputer::Break(state), Some(end))
+        }
+      };
+      self.computer = Some(computer);
+      if end.is_some() {
+        self.end = end;
+        return None;
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputDecodeError {
+  /// The stream ended partway through a group
+  PartialGroup {
+    index: usize,
+    expected: usize,
+    received: Vec<isize>,
+  },
+  /// A complete group didn't describe a valid value
+  Invalid {
+    index: usize,
+    outputs: Vec<isize>,
+    message: String,
+  },
+  /// The stream ended between groups for some reason other than the program halting
+  Stopped { index: usize, end: StreamEnd },
+}
+
+/// Takes up to `n` values, reporting a partial group if the stream ends early, or why it
+/// ended if that wasn't a halt. Nothing more is taken once the stream has ended.
+fn next_group<I: Iterator<Item = isize>>(
+  outputs: &mut OutputStream<I>,
+  n: usize,
+  index: usize,
+  done: &mut bool,
+) -> Option<Result<Vec<isize>, OutputDecodeError>> {
+  if *done {
+    return None;
+  }
+  let group: Vec<isize> = outputs.take(n).collect();
+  if group.len() == n {
+    return Some(Ok(group));
+  }
+  *done = true;
+  if !group.is_empty() {
+    return Some(Err(OutputDecodeError::PartialGroup {
+      index,
+      expected: n,
+      received: group,
+    }));
+  }
+  match outputs.end() {
+    None | Some(StreamEnd::Halted) => None,
+    Some(end) => Some(Err(OutputDecodeError::Stopped {
+      index,
+      end: end.clone(),
+    })),
+  }
+}
+
+pub struct Grouped<I, const N: usize> {
+  outputs: OutputStream<I>,
+  index: usize,
+  done: bool,
+}
+
+impl<I, const N: usize> Grouped<I, N> {
+  /// The stream being grouped, to see why it ended or to get the computer back.
+  pub fn into_inner(self) -> OutputStream<I> {
+    self.outputs
+  }
+}
+
+impl<I: Iterator<Item = isize>, const N: usize> Iterator for Grouped<I, N> {
+  type Item = Result<[isize; N], OutputDecodeError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let group = next_group(&mut self.outputs, N, self.index, &mut self.done)?;
+    self.index += 1;
+    Some(group.map(|group| <[isize; N]>::try_from(group).unwrap()))
+  }
+}
+
+/// A value that a program describes with a fixed number of outputs.
+pub trait FromOutputs: Sized {
+  /// At least 1
+  const WIDTH: usize;
+  /// `outputs` always has exactly `WIDTH` values.
+  fn from_outputs(outputs: &[isize]) -> Result<Self, String>;
+}
+
+impl FromOutputs for (isize, isize) {
+  const WIDTH: usize = 2;
+  fn from_outputs(outputs: &[isize]) -> Result<Self, String> {
+    Ok((outputs[0], outputs[1]))
+  }
+}
+
+impl FromOutputs for (isize, isize, isize) {
+  const WIDTH: usize = 3;
+  fn from_outputs(outputs: &[isize]) -> Result<Self, String> {
+    Ok((outputs[0], outputs[1], outputs[2]))
+  }
+}
+
+pub struct Decoded<I, T> {
+  outputs: OutputStream<I>,
+  index: usize,
+  done: bool,
+  decoded: PhantomData<T>,
+}
+
+impl<I, T> Decoded<I, T> {
+  /// The stream being decoded, to see why it ended or to get the computer back.
+  pub fn into_inner(self) -> OutputStream<I> {
+    self.outputs
+  }
+}
+
+impl<I: Iterator<Item = isize>, T: FromOutputs> Iterator for Decoded<I, T> {
+  type Item = Result<T, OutputDecodeError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let index = self.index;
+    let group = next_group(&mut self.outputs, T::WIDTH, index, &mut self.done)?;
+    self.index += 1;
+    Some(group.and_then(|group| {
+      T::from_outputs(&group).map_err(|message| OutputDecodeError::Invalid {
+        index,
+        outputs: group,
+        message,
+      })
+    }))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::parse;
+  use super::*;
+
+  #[test]
+  fn stream_outputs() {
+    let computer = IntcodeComputer::new(parse("3,0,4,0,3,0,4,0,99"));
+    let mut outputs = computer.start().outputs(vec![5, 6]);
+    assert_eq!(outputs.by_ref().collect::<Vec<_>>(), vec![5, 6]);
+    assert_eq!(outputs.end(), Some(&StreamEnd::Halted));
+  }
+
+  #[test]
+  fn stream_runs_out_of_input() {
+    let computer = IntcodeComputer::new(parse("3,0,4,0,3,0,4,0,99"));
+    let mut outputs = computer.start().outputs(vec![5]);
+    assert_eq!(outputs.by_ref().collect::<Vec<_>>(), vec![5]);
+    assert_eq!(outputs.end(), Some(&StreamEnd::NeedsInput));
+    let computer = outputs.into_computer().unwrap();
+    assert_eq!(
+      computer
+        .as_input()
+        .unwrap()
+        .execute(7)
+        .as_output()
+        .unwrap()
+        .output,
+      7
+    );
+  }
+
+  #[test]
+  fn grouped() {
+    let computer = IntcodeComputer::new(parse("104,1,104,2,104,3,104,4,99"));
+    let groups: Vec<_> = computer.start().outputs(vec![]).grouped::<2>().collect();
+    assert_eq!(groups, vec![Ok([1, 2]), Ok([3, 4])]);
+  }
+
+  #[test]
+  fn partial_group() {
+    let computer = IntcodeComputer::new(parse("104,1,104,2,104,3,104,4,99"));
+    let groups: Vec<_> = computer.start().outputs(vec![]).grouped::<3>().collect();
+    assert_eq!(
+      groups,
+      vec![
+        Ok([1, 2, 3]),
+        Err(OutputDecodeError::PartialGroup {
+          index: 1,
+          expected: 3,
+          received: vec![4]
+        })
+      ]
+    );
+  }
+
+  #[test]
+  fn stops_between_groups() {
+    // Faults with an unknown opcode after a complete group
+    let computer = IntcodeComputer::new(parse("104,1,104,2,42"));
+    let mut groups = computer.start().outputs(vec![]).grouped::<2>();
+    assert_eq!(
+      groups.by_ref().collect::<Vec<_>>(),
+      vec![
+        Ok([1, 2]),
+        Err(OutputDecodeError::Stopped {
+          index: 1,
+          end: StreamEnd::Fault(Fault::UnknownOpcode {
+            opcode: 42,
+            pointer: 4
+          })
+        })
+      ]
+    );
+    assert!(groups
+      .into_inner()
+      .into_computer()
+      .unwrap()
+      .as_fault()
+      .is_ok());
+
+    // Waiting for input can be carried on from
+    let computer = IntcodeComputer::new(parse("104,1,104,2,3,0,4,0,104,3,99"));
+    let mut pairs = computer.start().outputs(vec![]).decode::<(isize, isize)>();
+    assert_eq!(pairs.next(), Some(Ok((1, 2))));
+    assert_eq!(
+      pairs.next(),
+      Some(Err(OutputDecodeError::Stopped {
+        index: 1,
+        end: StreamEnd::NeedsInput
+      }))
+    );
+    assert_eq!(pairs.next(), None);
+    let computer = pairs.into_inner().into_computer().unwrap();
+    let computer = computer.as_input().unwrap().execute(9);
+    let pairs: Result<Vec<(isize, isize)>, _> = computer.outputs(vec![]).decode().collect();
+    assert_eq!(pairs, Ok(vec![(9, 3)]));
+  }
+
+  #[test]
+  #[should_panic(expected = "groups of 0")]
+  fn rejects_empty_groups() {
+    struct Nothing;
+    impl FromOutputs for Nothing {
+      const WIDTH: usize = 0;
+      fn from_outputs(_: &[isize]) -> Result<Self, String> {
+        Ok(Nothing)
+      }
+    }
+
+    let computer = IntcodeComputer::new(parse("99"));
+    computer.start().outputs(vec![]).decode::<Nothing>();
+  }
+
+  #[derive(Debug, PartialEq)]
+  enum Tile {
+    Empty,
+    Wall,
+  }
+
+  #[derive(Debug, PartialEq)]
+  struct DrawTile {
+    x: isize,
+    y: isize,
+    tile: Tile,
+  }
+
+  impl FromOutputs for DrawTile {
+    const WIDTH: usize = 3;
+    fn from_outputs(outputs: &[isize]) -> Result<Self, String> {
+      let tile = match outputs[2] {
+        0 => Tile::Empty,
+        1 => Tile::Wall,
+        other => return Err(format!("Unknown tile {}", other)),
+      };
+      Ok(DrawTile {
+        x: outputs[0],
+        y: outputs[1],
+        tile,
+      })
+    }
+  }
+
+  #[test]
+  fn decode() {
+    let computer = IntcodeComputer::new(parse("104,1,104,2,104,1,104,3,104,4,104,7,99"));
+    let tiles: Vec<_> = computer
+      .start()
+      .outputs(vec![])
+      .decode::<DrawTile>()
+      .collect();
+    assert_eq!(
+      tiles,
+      vec![
+        Ok(DrawTile {
+          x: 1,
+          y: 2,
+          tile: Tile::Wall
+        }),
+        Err(OutputDecodeError::Invalid {
+          index: 1,
+          outputs: vec![3, 4, 7],
+          message: "Unknown tile 7".into()
+        })
+      ]
+    );
+  }
+
+  #[test]
+  fn decode_tuples() {
+    let computer = IntcodeComputer::new(parse("3,0,4,0,104,-1,99"));
+    let pairs: Result<Vec<(isize, isize)>, _> =
+      computer.start().outputs(vec![9]).decode().collect();
+    assert_eq!(pairs, Ok(vec![(9, -1)]));
+  }
+}
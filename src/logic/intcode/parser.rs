@@ -0,0 +1,166 @@
+use super::IntcodeSequence;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+  EmptyField,
+  InvalidNumber(String),
+}
+
+/// Describes where an Intcode program failed to parse. `offset` is a byte offset into the
+/// input and `field` is the zero-based index of the comma-separated field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+  pub offset: usize,
+  pub field: usize,
+  pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.kind {
+      ParseErrorKind::EmptyField => write!(
+        f,
+        "Empty field {} at byte offset {}",
+        self.field, self.offset
+      ),
+      ParseErrorKind::InvalidNumber(text) => write!(
+        f,
+        "Invalid number {:?} in field {} at byte offset {}",
+        text, self.field, self.offset
+      ),
+    }
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Field<'a> {
+  text: &'a str,
+  offset: usize,
+}
+
+/// Parses a comma-separated Intcode program. Whitespace (including newlines) around values
+/// is ignored, `#` starts a comment that runs to the end of the line, and a single trailing
+/// comma is allowed.
+pub fn parse_program(input: &str) -> Result<IntcodeSequence, ParseError> {
+  let mut fields: Vec<Field> = vec![];
+  let mut current: Option<Field> = None;
+  let mut in_comment = false;
+
+  for (offset, c) in input.char_indices() {
+    if in_comment {
+      in_comment = c != '\n';
+      continue;
+    }
+    match c {
+      '#' => in_comment = true,
+      ',' => fields.push(current.take().unwrap_or(Field { text: "", offset })),
+      c if c.is_whitespace() => (),
+      _ => match &mut current {
+        Some(field) => field.text = &input[field.offset..offset + c.len_utf8()],
+        None => {
+          current = Some(Field {
+            text: &input[offset..offset + c.len_utf8()],
+            offset,
+          })
+        }
+      },
+    }
+  }
+  if let Some(field) = current {
+    fields.push(field);
+  }
+
+  fields
+    .into_iter()
+    .enumerate()
+    .map(|(index, field)| {
+      if field.text.is_empty() {
+        return Err(ParseError {
+          offset: field.offset,
+          field: index,
+          kind: ParseErrorKind::EmptyField,
+        });
+      }
+      field.text.parse().map_err(|_| ParseError {
+        offset: field.offset,
+        field: index,
+        kind: ParseErrorKind::InvalidNumber(field.text.into()),
+      })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn simple() {
+    assert_eq!(parse_program("1,0,0,0,99"), Ok(vec![1, 0, 0, 0, 99]));
+    assert_eq!(
+      parse_program("1101,100,-1,4,0"),
+      Ok(vec![1101, 100, -1, 4, 0])
+    );
+    assert_eq!(parse_program(""), Ok(vec![]));
+  }
+
+  #[test]
+  fn whitespace_and_comments() {
+    let program = "
+      # Add two numbers
+      1, 5, 6, 0, # [0] = [5] + [6]
+      99,
+      # Data
+      2,
+      3,
+    ";
+    assert_eq!(parse_program(program), Ok(vec![1, 5, 6, 0, 99, 2, 3]));
+    assert_eq!(parse_program("1,0,0,0,99\n"), Ok(vec![1, 0, 0, 0, 99]));
+  }
+
+  #[test]
+  fn empty_field() {
+    assert_eq!(
+      parse_program("1,0,,0,99"),
+      Err(ParseError {
+        offset: 4,
+        field: 2,
+        kind: ParseErrorKind::EmptyField
+      })
+    );
+    assert_eq!(
+      parse_program(",1"),
+      Err(ParseError {
+        offset: 0,
+        field: 0,
+        kind: ParseErrorKind::EmptyField
+      })
+    );
+  }
+
+  #[test]
+  fn invalid_number() {
+    assert_eq!(
+      parse_program("1,0,\n  0x,0,99"),
+      Err(ParseError {
+        offset: 7,
+        field: 2,
+        kind: ParseErrorKind::InvalidNumber("0x".into())
+      })
+    );
+    assert_eq!(
+      parse_program("1,2 3"),
+      Err(ParseError {
+        offset: 2,
+        field: 1,
+        kind: ParseErrorKind::InvalidNumber("2 3".into())
+      })
+    );
+    assert_eq!(
+      parse_program("1,0,0,0,99,,").unwrap_err().to_string(),
+      "Empty field 5 at byte offset 11"
+    );
+  }
+}
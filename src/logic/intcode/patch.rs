@@ -0,0 +1,190 @@
+use super::IntcodeSequence;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Patch {
+  pub address: usize,
+  pub value: isize,
+}
+
+/// A set of memory overrides, keyed by address. Written and parsed as `1=12,2=2`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatchSet {
+  patches: BTreeMap<usize, isize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+  InvalidPatch(String),
+  OutOfBounds {
+    address: usize,
+    len: usize,
+  },
+  DuplicateAddress(usize),
+  /// A search has more combinations of values than can be counted in a `u64`
+  TooManyCandidates,
+}
+
+impl PatchSet {
+  pub fn new() -> PatchSet {
+    PatchSet::default()
+  }
+
+  pub fn insert(&mut self, address: usize, value: isize) {
+    self.patches.insert(address, value);
+  }
+
+  pub fn get(&self, address: usize) -> Option<isize> {
+    self.patches.get(&address).cloned()
+  }
+
+  pub fn len(&self) -> usize {
+    self.patches.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.patches.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = Patch> + '_ {
+    self
+      .patches
+      .iter()
+      .map(|(&address, &value)| Patch { address, value })
+  }
+
+  /// Writes every patch into `sequence`. Nothing is written if any address is out of bounds.
+  /// Returns the values that were overwritten, which will revert the change when applied.
+  pub fn apply(&self, sequence: &mut IntcodeSequence) -> Result<PatchSet, PatchError> {
+    if let Some(address) = self
+      .patches
+      .keys()
+      .find(|&&address| address >= sequence.len())
+    {
+      return Err(PatchError::OutOfBounds {
+        address: *address,
+        len: sequence.len(),
+      });
+    }
+
+    let mut original = PatchSet::new();
+    for patch in self.iter() {
+      original.insert(patch.address, sequence[patch.address]);
+      sequence[patch.address] = patch.value;
+    }
+    Ok(original)
+  }
+}
+
+/// Later patches to an address replace earlier ones, like `insert`.
+impl FromIterator<Patch> for PatchSet {
+  fn from_iter<I: IntoIterator<Item = Patch>>(iter: I) -> PatchSet {
+    PatchSet {
+      patches: iter
+        .into_iter()
+        .map(|patch| (patch.address, patch.value))
+        .collect(),
+    }
+  }
+}
+
+/// Unlike collecting patches, an address can only be given once, so that the text says
+/// exactly what will be written.
+impl FromStr for PatchSet {
+  type Err = PatchError;
+
+  fn from_str(input: &str) -> Result<PatchSet, PatchError> {
+    let patches: Vec<Patch> = input
+      .split(',')
+      .map(|field| field.trim())
+      .filter(|field| !field.is_empty())
+      .map(|field| {
+        let invalid = || PatchError::InvalidPatch(field.to_string());
+        let mut parts = field.splitn(2, '=');
+        let address = parts.next().ok_or_else(invalid)?;
+        let value = parts.next().ok_or_else(invalid)?;
+        Ok(Patch {
+          address: address.trim().parse().map_err(|_| invalid())?,
+          value: value.trim().parse().map_err(|_| invalid())?,
+        })
+      })
+      .collect::<Result<_, _>>()?;
+    let mut set = PatchSet::new();
+    for patch in patches {
+      if set.get(patch.address).is_some() {
+        return Err(PatchError::DuplicateAddress(patch.address));
+      }
+      set.insert(patch.address, patch.value);
+    }
+    Ok(set)
+  }
+}
+
+impl fmt::Display for PatchSet {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let fields: Vec<String> = self
+      .iter()
+      .map(|patch| format!("{}={}", patch.address, patch.value))
+      .collect();
+    write!(f, "{}", fields.join(","))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parse_and_display() {
+    let patches: PatchSet = "2=2, 1=12".parse().unwrap();
+    assert_eq!(patches.get(1), Some(12));
+    assert_eq!(patches.get(2), Some(2));
+    assert_eq!(patches.to_string(), "1=12,2=2");
+    assert_eq!("".parse::<PatchSet>(), Ok(PatchSet::new()));
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert_eq!(
+      "1=12,2".parse::<PatchSet>(),
+      Err(PatchError::InvalidPatch("2".into()))
+    );
+    assert_eq!(
+      "a=1".parse::<PatchSet>(),
+      Err(PatchError::InvalidPatch("a=1".into()))
+    );
+    assert_eq!(
+      "1=12,2=2,1=13".parse::<PatchSet>(),
+      Err(PatchError::DuplicateAddress(1))
+    );
+    assert_eq!(
+      "1=12, 1=12".parse::<PatchSet>(),
+      Err(PatchError::DuplicateAddress(1))
+    );
+  }
+
+  #[test]
+  fn apply_and_revert() {
+    let mut sequence = vec![1, 0, 0, 3, 99];
+    let patches: PatchSet = "1=12,2=2".parse().unwrap();
+    let original = patches.apply(&mut sequence).unwrap();
+    assert_eq!(sequence, vec![1, 12, 2, 3, 99]);
+    assert_eq!(original.to_string(), "1=0,2=0");
+    original.apply(&mut sequence).unwrap();
+    assert_eq!(sequence, vec![1, 0, 0, 3, 99]);
+  }
+
+  #[test]
+  fn apply_out_of_bounds() {
+    let mut sequence = vec![1, 0, 0, 3, 99];
+    let patches: PatchSet = "1=12,5=2".parse().unwrap();
+    assert_eq!(
+      patches.apply(&mut sequence),
+      Err(PatchError::OutOfBounds { address: 5, len: 5 })
+    );
+    assert_eq!(sequence, vec![1, 0, 0, 3, 99]);
+  }
+}
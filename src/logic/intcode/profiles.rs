@@ -0,0 +1,185 @@
+use super::word::Word;
+use super::InstructionSet;
+
+/// The instruction set as it stood after a given day's puzzle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+  /// Add, multiply and halt, in position mode only.
+  V02,
+  /// Adds input, output, jumps, comparisons and immediate mode.
+  V05,
+  /// Adds relative mode and `arb`, which adjusts the relative base.
+  V09,
+}
+
+impl Profile {
+  pub const ALL: [Profile; 3] = [Profile::V02, Profile::V05, Profile::V09];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Profile::V02 => "v02",
+      Profile::V05 => "v05",
+      Profile::V09 => "v09",
+    }
+  }
+
+  pub fn opcodes(self) -> &'static [u8] {
+    match self {
+      Profile::V02 => &[1, 2, 99],
+      Profile::V05 => &[1, 2, 3, 4, 5, 6, 7, 8, 99],
+      Profile::V09 => &[1, 2, 3, 4, 5, 6, 7, 8, 9, 99],
+    }
+  }
+
+  pub fn parameter_modes(self) -> &'static [u8] {
+    match self {
+      Profile::V02 => &[0],
+      Profile::V05 => &[0, 1],
+      Profile::V09 => &[0, 1, 2],
+    }
+  }
+
+  /// The part of the relative instruction set that this profile allows.
+  pub fn instruction_set(self) -> InstructionSet {
+    self.instruction_set_for_words()
+  }
+
+  /// Like `instruction_set`, for computers with other word types.
+  pub fn instruction_set_for_words<W: Word>(self) -> InstructionSet<W> {
+    let mut set = InstructionSet::empty_for_words();
+    for def in InstructionSet::relative_for_words().iter() {
+      if self.opcodes().contains(&def.opcode) {
+        set.register(def.clone());
+      }
+    }
+    set.set_parameter_modes(self.parameter_modes());
+    set
+  }
+
+  /// The profile `instruction_set` is exactly, if any. `InstructionSet::standard` is
+  /// `V05` and `InstructionSet::relative` is `V09`.
+  pub fn matching<W: Word>(instruction_set: &InstructionSet<W>) -> Option<Profile> {
+    let built_in = InstructionSet::<W>::relative_for_words();
+    let all_built_in = instruction_set.iter().all(|def| {
+      built_in
+        .get(isize::from(def.opcode))
+        .is_some_and(|other| other.same_as(def))
+    });
+    let opcodes: Vec<u8> = instruction_set.iter().map(|def| def.opcode).collect();
+    Profile::ALL.iter().cloned().find(|profile| {
+      all_built_in
+        && profile.opcodes() == opcodes.as_slice()
+        && profile.parameter_modes() == instruction_set.parameter_modes()
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, Fault, IntcodeComputer, IntcodeComputerState, OpcodeDef};
+  use super::*;
+
+  fn run_strict(profile: Profile, program: &str) -> IntcodeComputer {
+    IntcodeComputer::new(parse(program))
+      .with_strict_profile(profile)
+      .start()
+  }
+
+  #[test]
+  fn matches_instruction_sets() {
+    for profile in Profile::ALL.iter() {
+      let instruction_set = profile.instruction_set();
+      assert_eq!(Profile::matching(&instruction_set), Some(*profile));
+    }
+    let standard = InstructionSet::standard();
+    assert_eq!(Profile::matching(&standard), Some(Profile::V05));
+    let relative = InstructionSet::<i128>::relative_for_words();
+    assert_eq!(Profile::matching(&relative), Some(Profile::V09));
+    let mut missing = InstructionSet::standard();
+    missing.remove(8);
+    assert_eq!(Profile::matching(&missing), None);
+    let mut custom = InstructionSet::standard();
+    custom.register(OpcodeDef::new(1, "plus", 3, &[2], |ctx| ctx.next()));
+    assert_eq!(Profile::matching(&custom), None);
+  }
+
+  #[test]
+  fn v02_runs_day_two_programs() {
+    let computer = run_strict(Profile::V02, "1,9,10,3,2,3,11,0,99,30,40,50")
+      .as_halt()
+      .unwrap();
+    assert_eq!(computer.borrow_memory()[0], 3500);
+  }
+
+  #[test]
+  fn v02_rejects_later_opcodes() {
+    let fault = run_strict(Profile::V02, "3,0,4,0,99").as_fault().unwrap();
+    assert_eq!(
+      run_strict(Profile::V05, "109,1,99")
+        .as_fault()
+        .unwrap()
+        .fault,
+      Fault::UnknownOpcode {
+        opcode: 9,
+        pointer: 0
+      }
+    );
+    assert_eq!(
+      fault.fault,
+      Fault::UnknownOpcode {
+        opcode: 3,
+        pointer: 0
+      }
+    );
+  }
+
+  #[test]
+  fn v02_rejects_immediate_mode() {
+    let fault = run_strict(Profile::V02, "1,0,0,0,1101,100,-1,4,0")
+      .as_fault()
+      .unwrap();
+    assert_eq!(
+      fault.fault,
+      Fault::UnsupportedParameterMode {
+        mode: 1,
+        pointer: 4
+      }
+    );
+    assert_eq!(fault.borrow_memory()[0], 2);
+  }
+
+  #[test]
+  fn v05_runs_day_five_programs() {
+    let computer = run_strict(Profile::V05, "3,9,8,9,10,9,4,9,99,-1,8")
+      .as_input()
+      .unwrap()
+      .execute(8)
+      .as_output()
+      .unwrap();
+    assert_eq!(computer.output, 1);
+  }
+
+  #[test]
+  fn v09_runs_relative_mode() {
+    // Moves the relative base to 7 and outputs the word before it
+    let computer = run_strict(Profile::V09, "109,7,204,-1,99,0,42")
+      .as_output()
+      .unwrap();
+    assert_eq!(computer.output, 42);
+    assert_eq!(computer.get_relative_base(), 7);
+  }
+
+  #[test]
+  fn v05_rejects_relative_mode() {
+    let fault = run_strict(Profile::V05, "1201,0,0,0,99")
+      .as_fault()
+      .unwrap();
+    assert_eq!(
+      fault.fault,
+      Fault::UnsupportedParameterMode {
+        mode: 2,
+        pointer: 0
+      }
+    );
+  }
+}
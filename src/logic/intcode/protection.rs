@@ -0,0 +1,253 @@
+// Memory protection, for catching programs that scribble over their own data or code.
+
+use super::disasm::decode_at;
+use super::word::Word;
+use super::{Fault, InstructionSet, IntcodeMemory};
+use std::fmt;
+use std::ops::Range;
+
+/// One way a program can use a memory address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+  /// Read through a position mode parameter
+  Read,
+  /// Written through a position mode parameter
+  Write,
+  /// Fetched as part of an instruction
+  Execute,
+}
+
+impl fmt::Display for Permission {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Permission::Read => write!(f, "read"),
+      Permission::Write => write!(f, "write"),
+      Permission::Execute => write!(f, "execute"),
+    }
+  }
+}
+
+/// What a protected region of memory can be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+  /// Can be read and executed, but not written, e.g. lookup tables and code
+  ReadOnly,
+  /// Can be read and written, but not executed, e.g. data and buffers
+  NoExecute,
+  /// Can only be written, e.g. output buffers nothing should read back
+  WriteOnly,
+}
+
+impl Protection {
+  pub fn allows(self, permission: Permission) -> bool {
+    match self {
+      Protection::ReadOnly => permission != Permission::Write,
+      Protection::NoExecute => permission != Permission::Execute,
+      Protection::WriteOnly => permission == Permission::Write,
+    }
+  }
+}
+
+/// Protected regions of memory. Where regions overlap, an access has to be allowed by
+/// all of them. Attach with `IntcodeComputerStart::with_protection`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryProtection {
+  regions: Vec<(Range<usize>, Protection)>,
+}
+
+impl MemoryProtection {
+  pub fn new() -> MemoryProtection {
+    MemoryProtection::default()
+  }
+
+  pub fn protect(&mut self, range: Range<usize>, protection: Protection) {
+    self.regions.push((range, protection));
+  }
+
+  pub fn regions(&self) -> impl Iterator<Item = (Range<usize>, Protection)> + '_ {
+    self.regions.iter().cloned()
+  }
+
+  pub fn allows(&self, address: usize, permission: Permission) -> bool {
+    self
+      .regions
+      .iter()
+      .filter(|(range, _)| range.contains(&address))
+      .all(|(_, protection)| protection.allows(permission))
+  }
+
+  fn require(&self, address: usize, permission: Permission, pointer: usize) -> Result<(), Fault> {
+    if self.allows(address, permission) {
+      Ok(())
+    } else {
+      Err(Fault::ProtectionViolation {
+        address,
+        permission,
+        pointer,
+      })
+    }
+  }
+
+  /// Called before each instruction executes, so that it faults without touching memory.
+  pub(super) fn check<W: Word>(
+    &self,
+    sequence: &IntcodeMemory<W>,
+    pointer: usize,
+    relative_base: isize,
+    instruction_set: &InstructionSet<W>,
+  ) -> Result<(), Fault> {
+    self.require(pointer, Permission::Execute, pointer)?;
+    // Anything that doesn't decode faults or panics as usual when it runs
+    let instruction = match decode_at(sequence, pointer, instruction_set) {
+      Some(instruction) => instruction,
+      None => return Ok(()),
+    };
+    for address in pointer + 1..pointer + instruction.len() {
+      self.require(address, Permission::Execute, pointer)?;
+    }
+    for i in 0..instruction.parameters.len() {
+      let permission = if instruction.writes.contains(&(i as u8)) {
+        Permission::Write
+      } else {
+        Permission::Read
+      };
+      if let Some(address) = instruction.address(i, relative_base) {
+        self.require(address, permission, pointer)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, IntcodeComputer, IntcodeComputerState};
+  use super::*;
+
+  // Looks up the input in the table at 11..14, by writing its address into the output
+  // instruction at 6, and outputs it
+  const LOOKUP: &str = "3,10,1001,10,11,7,4,0,99,0,0,100,200,300";
+
+  fn fault(computer: IntcodeComputer) -> Fault {
+    computer.as_fault().unwrap().fault
+  }
+
+  #[test]
+  fn allows_permitted_accesses() {
+    let computer = IntcodeComputer::new(parse(LOOKUP))
+      .with_protection(0..7, Protection::ReadOnly)
+      .with_protection(8..9, Protection::ReadOnly)
+      .with_protection(9..11, Protection::NoExecute)
+      .with_protection(11..14, Protection::ReadOnly)
+      .start()
+      .as_input()
+      .unwrap()
+      .execute(1)
+      .as_output()
+      .unwrap();
+    assert_eq!(computer.output, 200);
+  }
+
+  #[test]
+  fn catches_writes_to_read_only_tables() {
+    // Writes over the table entry instead of reading it
+    let computer = IntcodeComputer::new(parse("1101,7,0,12,4,12,99,0,0,0,0,100,200,300"))
+      .with_protection(11..14, Protection::ReadOnly)
+      .start();
+    assert_eq!(computer.get_pointer(), 0);
+    assert_eq!(computer.borrow_memory()[12], 200);
+    assert_eq!(
+      fault(computer),
+      Fault::ProtectionViolation {
+        address: 12,
+        permission: Permission::Write,
+        pointer: 0
+      }
+    );
+  }
+
+  #[test]
+  fn faults_before_asking_for_input() {
+    let computer = IntcodeComputer::new(parse(LOOKUP))
+      .with_protection(10..11, Protection::ReadOnly)
+      .start();
+    assert_eq!(
+      fault(computer).to_string(),
+      "No write permission for address 10 at instruction pointer 0"
+    );
+  }
+
+  #[test]
+  fn catches_execution_of_data() {
+    // Jumps into the table
+    let computer = IntcodeComputer::new(parse("1105,1,4,0,99"))
+      .with_protection(4..5, Protection::NoExecute)
+      .start();
+    assert_eq!(
+      fault(computer),
+      Fault::ProtectionViolation {
+        address: 4,
+        permission: Permission::Execute,
+        pointer: 4
+      }
+    );
+
+    // Parameters count as executed too
+    let computer = IntcodeComputer::new(parse("1101,1,1,5,99,0"))
+      .with_protection(2..3, Protection::NoExecute)
+      .start();
+    assert_eq!(
+      fault(computer),
+      Fault::ProtectionViolation {
+        address: 2,
+        permission: Permission::Execute,
+        pointer: 0
+      }
+    );
+  }
+
+  #[test]
+  fn catches_reads_of_write_only_memory() {
+    let computer = IntcodeComputer::new(parse("1101,1,1,7,4,7,99,0"))
+      .with_protection(7..8, Protection::WriteOnly)
+      .start();
+    assert_eq!(
+      fault(computer),
+      Fault::ProtectionViolation {
+        address: 7,
+        permission: Permission::Read,
+        pointer: 4
+      }
+    );
+  }
+
+  #[test]
+  fn checks_relative_mode() {
+    // Writes through the relative base into a read-only word
+    let computer = IntcodeComputer::new(parse("109,7,21101,1,1,0,99,0"))
+      .with_instruction_set(InstructionSet::relative())
+      .with_protection(7..8, Protection::ReadOnly)
+      .start();
+    assert_eq!(
+      fault(computer),
+      Fault::ProtectionViolation {
+        address: 7,
+        permission: Permission::Write,
+        pointer: 2
+      }
+    );
+  }
+
+  #[test]
+  fn overlapping_regions() {
+    let mut protection = MemoryProtection::new();
+    protection.protect(0..10, Protection::ReadOnly);
+    protection.protect(5..15, Protection::NoExecute);
+    assert!(protection.allows(2, Permission::Execute));
+    assert!(!protection.allows(7, Permission::Execute));
+    assert!(!protection.allows(7, Permission::Write));
+    assert!(protection.allows(7, Permission::Read));
+    assert!(protection.allows(12, Permission::Write));
+    assert!(protection.allows(100, Permission::Execute));
+  }
+}
@@ -0,0 +1,690 @@
+// Recording and replaying the inputs given to an IntcodeComputer.
+//
+// Replay files are plain text, one setting or event per line:
+//
+//   intcode-replay 3
+//   program 8f1c3e2a9b7d6c5e
+//   profile v05
+//   overflow fault
+//   in 5
+//   out 1d2e3f4a5b6c7d8e
+//   halt
+//
+// `out` lines hold a running checksum of every output so far, and are only written when
+// output checksums are enabled. A session that faults ends with a line like
+// `fault unknown-opcode 42 7`, naming the fault and its fields in order. Lines starting
+// with `#` are ignored.
+//
+// The lines after the program say how the computer was set up: its profile (`custom` for
+// other instruction sets), its overflow policy, and `watchdog N`, `protection` and
+// `devices` if it had them. Replays set the computer up the same way. What the file
+// can't describe, like which regions were protected, has to be added with
+// `Replay::replay_with`, and replays refuse to run until the setup matches.
+
+use super::word::Word;
+use super::{
+  Fault, IntcodeComputer, IntcodeComputerInternalState, IntcodeComputerStart, IntcodeSequence,
+  OverflowPolicy, Permission, Profile,
+};
+use std::fmt;
+
+const HEADER: &str = "intcode-replay 3";
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayEvent {
+  Input(isize),
+  /// The running checksum of all outputs up to and including this one
+  Output(u64),
+  Halt,
+  Fault(Fault),
+}
+
+/// How the computer was set up, as far as it changes what the program does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayConfig {
+  /// `None` if the instruction set isn't one of the profiles
+  pub profile: Option<Profile>,
+  pub overflow_policy: OverflowPolicy,
+  /// The watchdog's quiet steps, if there was one
+  pub watchdog: Option<u64>,
+  /// Whether any memory was protected. The regions aren't recorded.
+  pub protection: bool,
+  /// Whether any devices were attached. The devices aren't recorded.
+  pub devices: bool,
+}
+
+impl Default for ReplayConfig {
+  /// A computer fresh from `IntcodeComputer::new`.
+  fn default() -> ReplayConfig {
+    ReplayConfig {
+      profile: Some(Profile::V05),
+      overflow_policy: OverflowPolicy::default(),
+      watchdog: None,
+      protection: false,
+      devices: false,
+    }
+  }
+}
+
+impl ReplayConfig {
+  pub(super) fn of<W: Word>(state: &IntcodeComputerInternalState<W>) -> ReplayConfig {
+    ReplayConfig {
+      profile: Profile::matching(&state.instruction_set),
+      overflow_policy: state.overflow_policy,
+      watchdog: state
+        .watchdog
+        .as_ref()
+        .map(|watchdog| watchdog.quiet_steps()),
+      protection: state.protection.is_some(),
+      devices: state.devices.is_some(),
+    }
+  }
+
+  /// Sets up as much of `start` as the file describes.
+  fn apply(&self, mut start: IntcodeComputerStart) -> IntcodeComputerStart {
+    if let Some(profile) = self.profile {
+      start = start.with_strict_profile(profile);
+    }
+    if let Some(quiet_steps) = self.watchdog {
+      start = start.with_watchdog(quiet_steps);
+    }
+    start.with_overflow_policy(self.overflow_policy)
+  }
+}
+
+/// Everything needed to reproduce a session against the same program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+  pub program_checksum: u64,
+  pub output_checksums: bool,
+  pub config: ReplayConfig,
+  pub events: Vec<ReplayEvent>,
+}
+
+/// Builds up a `Replay` while a computer runs.
+#[derive(Debug)]
+pub(super) struct Recorder {
+  pub(super) replay: Replay,
+  output_checksum: u64,
+}
+
+impl Recorder {
+  pub(super) fn new(program: &IntcodeSequence, output_checksums: bool) -> Recorder {
+    Recorder {
+      replay: Replay {
+        program_checksum: checksum_program(program),
+        output_checksums,
+        // Filled in once the computer starts
+        config: ReplayConfig::default(),
+        events: vec![],
+      },
+      output_checksum: FNV_OFFSET,
+    }
+  }
+
+  pub(super) fn record_input(&mut self, input: isize) {
+    self.replay.events.push(ReplayEvent::Input(input));
+  }
+
+  pub(super) fn record_output(&mut self, output: isize) {
+    self.output_checksum = checksum_word(self.output_checksum, output);
+    if self.replay.output_checksums {
+      self
+        .replay
+        .events
+        .push(ReplayEvent::Output(self.output_checksum));
+    }
+  }
+
+  pub(super) fn record_halt(&mut self) {
+    self.replay.events.push(ReplayEvent::Halt);
+  }
+
+  pub(super) fn record_fault(&mut self, fault: &Fault) {
+    self.replay.events.push(ReplayEvent::Fault(fault.clone()));
+  }
+}
+
+/// What the computer actually did when the replay expected something else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Observed {
+  WaitingForInput,
+  Output(u64),
+  Halt,
+  Fault(Fault),
+  /// The replay was recorded against a program with a different checksum
+  DifferentProgram(u64),
+  /// The computer wasn't set up the way the replay was recorded
+  DifferentConfig(ReplayConfig),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+  /// Index into `Replay::events`
+  pub event: usize,
+  pub expected: Option<ReplayEvent>,
+  pub observed: Observed,
+}
+
+impl Replay {
+  pub fn inputs(&self) -> impl Iterator<Item = isize> + '_ {
+    self.events.iter().filter_map(|event| match event {
+      ReplayEvent::Input(input) => Some(*input),
+      _ => None,
+    })
+  }
+
+  /// Runs `program` with the recorded inputs and setup, checking it against every
+  /// recorded event. Returns the computer as it was after the last event.
+  pub fn replay(&self, program: IntcodeSequence) -> Result<IntcodeComputer, Divergence> {
+    self.replay_with(program, |start| start)
+  }
+
+  /// Like `replay`, but lets `configure` add what the file can't describe: a custom
+  /// instruction set, memory protection or devices. The result still has to match the
+  /// recorded setup.
+  pub fn replay_with<F>(
+    &self,
+    program: IntcodeSequence,
+    configure: F,
+  ) -> Result<IntcodeComputer, Divergence>
+  where
+    F: FnOnce(IntcodeComputerStart) -> IntcodeComputerStart,
+  {
+    let program_checksum = checksum_program(&program);
+    if program_checksum != self.program_checksum {
+      return Err(Divergence {
+        event: 0,
+        expected: self.events.first().cloned(),
+        observed: Observed::DifferentProgram(program_checksum),
+      });
+    }
+
+    let start = configure(self.config.apply(IntcodeComputer::new(program)));
+    let config = ReplayConfig::of(&start.internal_state);
+    if config != self.config {
+      return Err(Divergence {
+        event: 0,
+        expected: self.events.first().cloned(),
+        observed: Observed::DifferentConfig(config),
+      });
+    }
+
+    let mut output_checksum = FNV_OFFSET;
+    let mut computer = start.start();
+    for (i, expected) in self.events.iter().enumerate() {
+      // Outputs aren't recorded without checksums, so run straight past them
+      if !self.output_checksums {
+        while let IntcodeComputer::Output(state) = computer {
+          output_checksum = checksum_word(output_checksum, state.output);
+          computer = state.execute();
+        }
+      }
+
+      let diverged = |observed| Divergence {
+        event: i,
+        expected: Some(expected.clone()),
+        observed,
+      };
+      computer = match (expected, computer) {
+        (ReplayEvent::Input(input), IntcodeComputer::Input(state)) => state.execute(*input),
+        (ReplayEvent::Output(expected_checksum), IntcodeComputer::Output(state)) => {
+          output_checksum = checksum_word(output_checksum, state.output);
+          if output_checksum != *expected_checksum {
+            return Err(diverged(Observed::Output(output_checksum)));
+          }
+          state.execute()
+        }
+        (ReplayEvent::Halt, computer @ IntcodeComputer::Halt(_)) => computer,
+        (ReplayEvent::Fault(fault), IntcodeComputer::Fault(state)) if state.fault == *fault => {
+          IntcodeComputer::Fault(state)
+        }
+        (_, IntcodeComputer::Input(_)) => return Err(diverged(Observed::WaitingForInput)),
+        (_, IntcodeComputer::Output(state)) => {
+          output_checksum = checksum_word(output_checksum, state.output);
+          return Err(diverged(Observed::Output(output_checksum)));
+        }
+        (_, IntcodeComputer::Halt(_)) => return Err(diverged(Observed::Halt)),
+        (_, IntcodeComputer::Fault(state)) => return Err(diverged(Observed::Fault(state.fault))),
+        (_, IntcodeComputer::Break(_)) => unreachable!("No debugger is attached"),
+      };
+    }
+    Ok(computer)
+  }
+
+  pub fn parse(input: &str) -> Result<Replay, String> {
+    let mut lines = input
+      .lines()
+      .enumerate()
+      .map(|(i, line)| (i + 1, line.trim()))
+      .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    match lines.next() {
+      Some((_, HEADER)) => (),
+      _ => return Err(format!("Expected replay to start with \"{}\"", HEADER)),
+    }
+    let program_checksum = match lines.next() {
+      Some((line_number, line)) => match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["program", checksum] => parse_hex(checksum, line_number)?,
+        _ => return Err(format!("Line {}: expected program checksum", line_number)),
+      },
+      None => return Err("Missing program checksum".into()),
+    };
+
+    let mut output_checksums = false;
+    let mut config = ReplayConfig::default();
+    let mut events = vec![];
+    for (line_number, line) in lines {
+      let invalid =
+        |what: &str, value: &str| format!("Line {}: invalid {} {:?}", line_number, what, value);
+      let event = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["profile", "custom"] => {
+          config.profile = None;
+          continue;
+        }
+        ["profile", name] => {
+          let profile = Profile::ALL.iter().find(|profile| profile.name() == name);
+          config.profile = Some(*profile.ok_or_else(|| invalid("profile", name))?);
+          continue;
+        }
+        ["overflow", name] => {
+          config.overflow_policy =
+            parse_policy(name).ok_or_else(|| invalid("overflow policy", name))?;
+          continue;
+        }
+        ["watchdog", quiet_steps] => {
+          config.watchdog = Some(
+            quiet_steps
+              .parse()
+              .map_err(|_| invalid("watchdog", quiet_steps))?,
+          );
+          continue;
+        }
+        ["protection"] => {
+          config.protection = true;
+          continue;
+        }
+        ["devices"] => {
+          config.devices = true;
+          continue;
+        }
+        ["in", value] => ReplayEvent::Input(
+          value
+            .parse()
+            .map_err(|_| format!("Line {}: invalid input {:?}", line_number, value))?,
+        ),
+        ["out", checksum] => {
+          output_checksums = true;
+          ReplayEvent::Output(parse_hex(checksum, line_number)?)
+        }
+        ["halt"] => ReplayEvent::Halt,
+        ["fault", ref fields @ ..] => ReplayEvent::Fault(parse_fault(fields, line_number)?),
+        _ => {
+          return Err(format!(
+            "Line {}: unrecognized event {:?}",
+            line_number, line
+          ))
+        }
+      };
+      events.push(event);
+    }
+
+    Ok(Replay {
+      program_checksum,
+      output_checksums,
+      config,
+      events,
+    })
+  }
+}
+
+impl fmt::Display for Replay {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}", HEADER)?;
+    writeln!(f, "program {:016x}", self.program_checksum)?;
+    let profile = self.config.profile.map_or("custom", Profile::name);
+    writeln!(f, "profile {}", profile)?;
+    writeln!(f, "overflow {}", policy_name(self.config.overflow_policy))?;
+    if let Some(quiet_steps) = self.config.watchdog {
+      writeln!(f, "watchdog {}", quiet_steps)?;
+    }
+    if self.config.protection {
+      writeln!(f, "protection")?;
+    }
+    if self.config.devices {
+      writeln!(f, "devices")?;
+    }
+    for event in self.events.iter() {
+      match event {
+        ReplayEvent::Input(input) => writeln!(f, "in {}", input)?,
+        ReplayEvent::Output(checksum) => writeln!(f, "out {:016x}", checksum)?,
+        ReplayEvent::Halt => writeln!(f, "halt")?,
+        ReplayEvent::Fault(fault) => writeln!(f, "fault {}", fault_fields(fault))?,
+      }
+    }
+    Ok(())
+  }
+}
+
+fn policy_name(policy: OverflowPolicy) -> &'static str {
+  match policy {
+    OverflowPolicy::Fault => "fault",
+    OverflowPolicy::Wrap => "wrap",
+    OverflowPolicy::Saturate => "saturate",
+    OverflowPolicy::Promote => "promote",
+  }
+}
+
+fn parse_policy(name: &str) -> Option<OverflowPolicy> {
+  Some(match name {
+    "fault" => OverflowPolicy::Fault,
+    "wrap" => OverflowPolicy::Wrap,
+    "saturate" => OverflowPolicy::Saturate,
+    "promote" => OverflowPolicy::Promote,
+    _ => return None,
+  })
+}
+
+fn fault_fields(fault: &Fault) -> String {
+  match fault {
+    Fault::UnknownOpcode { opcode, pointer } => format!("unknown-opcode {} {}", opcode, pointer),
+    Fault::UnsupportedParameterMode { mode, pointer } => {
+      format!("parameter-mode {} {}", mode, pointer)
+    }
+    Fault::InfiniteLoop { start, end } => format!("infinite-loop {} {}", start, end),
+    Fault::Overflow { pointer } => format!("overflow {}", pointer),
+    Fault::OversizedInstruction { pointer } => format!("oversized-instruction {}", pointer),
+    Fault::ProtectionViolation {
+      address,
+      permission,
+      pointer,
+    } => format!("protection {} {} {}", address, permission, pointer),
+  }
+}
+
+fn parse_fault(fields: &[&str], line_number: usize) -> Result<Fault, String> {
+  let invalid = || format!("Line {}: invalid fault {:?}", line_number, fields.join(" "));
+  let number = |field: &str| field.parse::<usize>().map_err(|_| invalid());
+  Ok(match fields {
+    ["unknown-opcode", opcode, pointer] => Fault::UnknownOpcode {
+      opcode: opcode.parse().map_err(|_| invalid())?,
+      pointer: number(pointer)?,
+    },
+    ["parameter-mode", mode, pointer] => Fault::UnsupportedParameterMode {
+      mode: mode.parse().map_err(|_| invalid())?,
+      pointer: number(pointer)?,
+    },
+    ["infinite-loop", start, end] => Fault::InfiniteLoop {
+      start: number(start)?,
+      end: number(end)?,
+    },
+    ["overflow", pointer] => Fault::Overflow {
+      pointer: number(pointer)?,
+    },
+    ["oversized-instruction", pointer] => Fault::OversizedInstruction {
+      pointer: number(pointer)?,
+    },
+    ["protection", address, permission, pointer] => Fault::ProtectionViolation {
+      address: number(address)?,
+      permission: match *permission {
+        "read" => Permission::Read,
+        "write" => Permission::Write,
+        "execute" => Permission::Execute,
+        _ => return Err(invalid()),
+      },
+      pointer: number(pointer)?,
+    },
+    _ => return Err(invalid()),
+  })
+}
+
+fn parse_hex(input: &str, line_number: usize) -> Result<u64, String> {
+  u64::from_str_radix(input, 16)
+    .map_err(|_| format!("Line {}: invalid checksum {:?}", line_number, input))
+}
+
+fn checksum_word(checksum: u64, word: isize) -> u64 {
+  (word as i64)
+    .to_le_bytes()
+    .iter()
+    .fold(checksum, |hash, byte| {
+      (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn checksum_program(program: &IntcodeSequence) -> u64 {
+  program
+    .iter()
+    .fold(FNV_OFFSET, |hash, word| checksum_word(hash, *word))
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, IntcodeComputerState, Protection};
+  use super::*;
+
+  // Echoes inputs until it receives 0, then halts
+  const ECHO: &str = "3,9,4,9,1005,9,0,99,0,0";
+
+  fn record_echo(inputs: &[isize], output_checksums: bool) -> Replay {
+    let mut computer = IntcodeComputer::new(parse(ECHO))
+      .with_recording(output_checksums)
+      .start();
+    for input in inputs.iter() {
+      computer = computer.as_input().unwrap().execute(*input);
+      while let IntcodeComputer::Output(state) = computer {
+        computer = state.execute();
+      }
+    }
+    computer.recording().unwrap().clone()
+  }
+
+  #[test]
+  fn records_inputs() {
+    let replay = record_echo(&[5, 7, 0], false);
+    assert_eq!(
+      replay.events,
+      vec![
+        ReplayEvent::Input(5),
+        ReplayEvent::Input(7),
+        ReplayEvent::Input(0),
+        ReplayEvent::Halt
+      ]
+    );
+    assert_eq!(replay.inputs().collect::<Vec<_>>(), vec![5, 7, 0]);
+  }
+
+  #[test]
+  fn records_output_checksums() {
+    let replay = record_echo(&[5, 0], true);
+    match replay.events[..] {
+      [ReplayEvent::Input(5), ReplayEvent::Output(_), ReplayEvent::Input(0), ReplayEvent::Output(_), ReplayEvent::Halt] =>
+        {}
+      _ => panic!("Unexpected events: {:?}", replay.events),
+    }
+  }
+
+  #[test]
+  fn replays() {
+    for checksums in [false, true].iter() {
+      let replay = record_echo(&[5, 7, 0], *checksums);
+      let computer = replay.replay(parse(ECHO)).unwrap();
+      let computer = computer.as_halt().unwrap();
+      assert_eq!(computer.borrow_memory()[9], 0);
+    }
+  }
+
+  #[test]
+  fn round_trips_through_text() {
+    let replay = record_echo(&[5, -7, 0], true);
+    let text = replay.to_string();
+    assert!(text.starts_with("intcode-replay 3\nprogram "));
+    assert!(text.contains("\nin -7\n"));
+    assert_eq!(Replay::parse(&text), Ok(replay));
+  }
+
+  #[test]
+  fn reports_first_divergence() {
+    let replay = record_echo(&[5, 7, 0], true);
+    // Outputs double its input
+    let doubled = parse("3,12,2,12,14,13,4,13,1005,12,0,99,0,0,2");
+    let mut replay = replay;
+    replay.program_checksum = checksum_program(&doubled);
+    let divergence = replay.replay(doubled).unwrap_err();
+    assert_eq!(divergence.event, 1);
+    assert!(matches!(divergence.expected, Some(ReplayEvent::Output(_))));
+    assert!(matches!(divergence.observed, Observed::Output(_)));
+  }
+
+  #[test]
+  fn reports_early_halt() {
+    let replay = record_echo(&[5, 7, 0], false);
+    // Halts after the first input
+    let program = parse("3,9,4,9,99,0,0,99,0,0");
+    let mut replay = replay;
+    replay.program_checksum = checksum_program(&program);
+    assert_eq!(
+      replay.replay(program).unwrap_err(),
+      Divergence {
+        event: 1,
+        expected: Some(ReplayEvent::Input(7)),
+        observed: Observed::Halt
+      }
+    );
+  }
+
+  #[test]
+  fn rejects_different_program() {
+    let replay = record_echo(&[0], false);
+    let divergence = replay.replay(parse("3,9,4,9,1005,9,0,99,0,1")).unwrap_err();
+    assert!(matches!(divergence.observed, Observed::DifferentProgram(_)));
+  }
+
+  #[test]
+  fn replays_with_configuration() {
+    // Reads an input, which Day 02 computers don't know how to do
+    let program = parse("3,0,99");
+    let computer = IntcodeComputer::new(program.clone())
+      .with_recording(false)
+      .with_strict_profile(Profile::V02)
+      .with_overflow_policy(OverflowPolicy::Wrap)
+      .start();
+    let replay = computer.recording().unwrap().clone();
+    let fault = Fault::UnknownOpcode {
+      opcode: 3,
+      pointer: 0,
+    };
+    assert_eq!(replay.events, vec![ReplayEvent::Fault(fault.clone())]);
+    let text = replay.to_string();
+    assert!(text.contains("\nprofile v02\noverflow wrap\n"), "{}", text);
+    assert_eq!(Replay::parse(&text), Ok(replay.clone()));
+
+    let computer = replay.replay(program.clone()).unwrap();
+    assert_eq!(computer.as_fault().unwrap().fault, fault);
+    let divergence = replay
+      .replay_with(program.clone(), |start| {
+        start.with_overflow_policy(OverflowPolicy::Fault)
+      })
+      .unwrap_err();
+    assert!(matches!(
+      divergence.observed,
+      Observed::DifferentConfig(ReplayConfig {
+        profile: Some(Profile::V02),
+        overflow_policy: OverflowPolicy::Fault,
+        ..
+      })
+    ));
+
+    // The same fault at a different address is a different fault
+    let mut moved = replay;
+    moved.events = vec![ReplayEvent::Fault(Fault::UnknownOpcode {
+      opcode: 3,
+      pointer: 1,
+    })];
+    let divergence = moved.replay(program).unwrap_err();
+    assert_eq!(divergence.observed, Observed::Fault(fault));
+  }
+
+  #[test]
+  fn needs_help_with_what_it_cannot_record() {
+    // Outputs the word at 5, which is protected
+    let program = parse("4,5,99,0,0,42");
+    let protect = |start: IntcodeComputerStart| start.with_protection(5..6, Protection::WriteOnly);
+    let computer = protect(IntcodeComputer::new(program.clone()).with_recording(false))
+      .with_watchdog(1000)
+      .start();
+    let replay = computer.recording().unwrap().clone();
+    assert!(replay.to_string().contains("\nwatchdog 1000\nprotection\n"));
+    assert_eq!(Replay::parse(&replay.to_string()), Ok(replay.clone()));
+
+    let divergence = replay.replay(program.clone()).unwrap_err();
+    assert!(matches!(
+      divergence.observed,
+      Observed::DifferentConfig(ReplayConfig {
+        protection: false,
+        ..
+      })
+    ));
+    let computer = replay.replay_with(program, protect).unwrap();
+    assert!(matches!(
+      computer.as_fault().unwrap().fault,
+      Fault::ProtectionViolation { address: 5, .. }
+    ));
+  }
+
+  #[test]
+  fn round_trips_faults() {
+    let faults = vec![
+      Fault::UnknownOpcode {
+        opcode: -42,
+        pointer: 7,
+      },
+      Fault::UnsupportedParameterMode {
+        mode: 3,
+        pointer: 1,
+      },
+      Fault::InfiniteLoop { start: 2, end: 9 },
+      Fault::Overflow { pointer: 4 },
+      Fault::OversizedInstruction { pointer: 5 },
+      Fault::ProtectionViolation {
+        address: 12,
+        permission: Permission::Execute,
+        pointer: 6,
+      },
+    ];
+    for fault in faults {
+      let replay = Replay {
+        program_checksum: 0,
+        output_checksums: false,
+        config: ReplayConfig::default(),
+        events: vec![ReplayEvent::Fault(fault)],
+      };
+      assert_eq!(Replay::parse(&replay.to_string()), Ok(replay));
+    }
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert!(Replay::parse("in 5").is_err());
+    assert_eq!(
+      Replay::parse("intcode-replay 3\nprogram 00ff\nin five"),
+      Err("Line 3: invalid input \"five\"".into())
+    );
+    assert_eq!(
+      Replay::parse("intcode-replay 3\nprogram 00ff\nfault overflow x"),
+      Err("Line 3: invalid fault \"overflow x\"".into())
+    );
+    assert_eq!(
+      Replay::parse("intcode-replay 3\nprogram 00ff\nprofile v99"),
+      Err("Line 3: invalid profile \"v99\"".into())
+    );
+    assert_eq!(
+      Replay::parse("intcode-replay 3\nprogram 00ff\noverflow explode"),
+      Err("Line 3: invalid overflow policy \"explode\"".into())
+    );
+    // Older files don't say how the computer was set up
+    assert!(Replay::parse("intcode-replay 2\nprogram 00ff\nhalt").is_err());
+  }
+}
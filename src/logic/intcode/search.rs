@@ -0,0 +1,269 @@
+use super::batch::{Batch, LaneStatus};
+use super::patch::PatchError;
+use super::{IntcodeComputer, IntcodeComputerState, IntcodeSequence, Patch, PatchSet};
+use crate::prelude::*;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+/// A memory address to patch during a search, along with the values to try there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchSite {
+  pub address: usize,
+  pub values: Range<isize>,
+}
+
+impl SearchSite {
+  pub fn new(address: usize, values: Range<isize>) -> SearchSite {
+    SearchSite { address, values }
+  }
+
+  fn len(&self) -> u64 {
+    // Even the widest range of `isize` values has fewer than `u64::MAX`
+    let len = self.values.end as i128 - self.values.start as i128;
+    u64::try_from(len).unwrap_or(0)
+  }
+}
+
+/// The result of running one candidate to completion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchRun {
+  pub memory: IntcodeSequence,
+  pub outputs: Vec<isize>,
+}
+
+/// Runs a patched program to completion, feeding it `inputs` in order.
+/// Returns `None` if the program faults or asks for more input than was provided.
+/// Panics if a patch is outside of program memory.
+pub fn run_to_halt(
+  sequence: IntcodeSequence,
+  patches: &PatchSet,
+  inputs: &[isize],
+) -> Option<SearchRun> {
+  let mut inputs = inputs.iter();
+  let mut outputs = vec![];
+  let mut computer = IntcodeComputer::new_patched(sequence, patches)
+    .expect("Search site is outside of program memory")
+    .start();
+  loop {
+    match computer {
+      IntcodeComputer::Input(state) => {
+        computer = state.execute(*inputs.next()?);
+      }
+      IntcodeComputer::Output(state) => {
+        outputs.push(state.output);
+        computer = state.execute();
+      }
+      IntcodeComputer::Halt(state) => {
+        return Some(SearchRun {
+          memory: state.borrow_memory().clone(),
+          outputs,
+        });
+      }
+      IntcodeComputer::Fault(_) => return None,
+      IntcodeComputer::Break(_) => unreachable!("No debugger is attached"),
+    }
+  }
+}
+
+/// How many candidates run together in one batch.
+const BATCH_LANES: u64 = 256;
+
+/// Like `run_to_halt` for each set of patches, running them all together in one batch.
+fn run_batch(
+  sequence: &IntcodeSequence,
+  candidates: &[PatchSet],
+  inputs: &[isize],
+) -> Vec<Option<SearchRun>> {
+  let mut batch = Batch::new(sequence, candidates.len());
+  for (lane, patches) in candidates.iter().enumerate() {
+    batch
+      .patch(lane, patches)
+      .expect("Search site is outside of program memory");
+    for input in inputs.iter() {
+      batch.push_input(lane, *input);
+    }
+  }
+  batch.run();
+  (0..candidates.len())
+    .map(|lane| match batch.status(lane) {
+      LaneStatus::Halted => Some(SearchRun {
+        memory: batch.memory(lane),
+        outputs: batch.take_outputs(lane),
+      }),
+      _ => None,
+    })
+    .collect()
+}
+
+/// Tries every combination of values at `sites` in parallel batches, returning the patches for the
+/// first combination (earliest site varying slowest) whose run satisfies `goal`.
+/// Once a match is found, candidates that come after it are abandoned.
+/// Fails if a site is outside of program memory, two sites share an address, or there are
+/// too many combinations to count.
+pub fn search<F>(
+  sequence: &IntcodeSequence,
+  sites: &[SearchSite],
+  inputs: &[isize],
+  goal: F,
+) -> Result<Option<PatchSet>, PatchError>
+where
+  F: Fn(&SearchRun) -> bool + Sync,
+{
+  check_sites(sequence, sites)?;
+  let total = sites
+    .iter()
+    .try_fold(1u64, |total, site| total.checked_mul(site.len()))
+    .ok_or(PatchError::TooManyCandidates)?;
+  let batches = total.div_ceil(BATCH_LANES);
+
+  Ok((0..batches).into_par_iter().find_map_first(|batch| {
+    let start = batch * BATCH_LANES;
+    let candidates: Vec<_> = (start..total.min(start.saturating_add(BATCH_LANES)))
+      .map(|index| candidate_patches(sites, index))
+      .collect();
+    let runs = run_batch(sequence, &candidates, inputs);
+    candidates
+      .into_iter()
+      .zip(runs)
+      .find(|(_, run)| run.as_ref().is_some_and(&goal))
+      .map(|(patches, _)| patches)
+  }))
+}
+
+fn check_sites(sequence: &IntcodeSequence, sites: &[SearchSite]) -> Result<(), PatchError> {
+  let mut seen = HashSet::new();
+  for site in sites {
+    if site.address >= sequence.len() {
+      return Err(PatchError::OutOfBounds {
+        address: site.address,
+        len: sequence.len(),
+      });
+    }
+    if !seen.insert(site.address) {
+      return Err(PatchError::DuplicateAddress(site.address));
+    }
+  }
+  Ok(())
+}
+
+fn candidate_patches(sites: &[SearchSite], index: u64) -> PatchSet {
+  let mut remaining = index;
+  sites
+    .iter()
+    .rev()
+    .map(|site| {
+      let len = site.len();
+      let offset = remaining % len;
+      remaining /= len;
+      Patch {
+        address: site.address,
+        // Wraps through the offset's sign bit, but lands inside the range
+        value: site.values.start.wrapping_add(offset as isize),
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn candidate_order() {
+    let sites = vec![SearchSite::new(0, 0..2), SearchSite::new(1, 5..8)];
+    let candidates: Vec<_> = (0..6)
+      .map(|i| candidate_patches(&sites, i).to_string())
+      .collect();
+    assert_eq!(
+      candidates,
+      vec!["0=0,1=5", "0=0,1=6", "0=0,1=7", "0=1,1=5", "0=1,1=6", "0=1,1=7"]
+    );
+    let everything = [SearchSite::new(0, isize::MIN..isize::MAX)];
+    assert_eq!(everything[0].len(), u64::MAX);
+    let last = candidate_patches(&everything, u64::MAX - 1);
+    assert_eq!(last.get(0), Some(isize::MAX - 1));
+  }
+
+  #[test]
+  fn search_memory() {
+    // Multiplies addresses 5 and 6 into address 0
+    let sequence = super::super::parse("2,5,6,0,99,0,0");
+    let sites = vec![SearchSite::new(5, 0..10), SearchSite::new(6, 0..10)];
+    let result = search(&sequence, &sites, &[], |run| run.memory[0] == 42);
+    assert_eq!(result, Ok(Some("5=6,6=7".parse().unwrap())));
+  }
+
+  #[test]
+  fn search_outputs() {
+    // Outputs input + address 10
+    let sequence = super::super::parse("3,9,1,9,10,9,4,9,99,0,0");
+    let sites = vec![SearchSite::new(10, -10..10)];
+    let result = search(&sequence, &sites, &[3], |run| run.outputs == vec![-2]);
+    assert_eq!(result, Ok(Some("10=-5".parse().unwrap())));
+  }
+
+  #[test]
+  fn batched_runs_match() {
+    let sequence = super::super::parse("3,9,1,9,10,9,4,9,99,0,0");
+    let candidates: Vec<PatchSet> = vec!["10=4", "1=10", "10=-1"]
+      .into_iter()
+      .map(|patches| patches.parse().unwrap())
+      .collect();
+    let expected: Vec<_> = candidates
+      .iter()
+      .map(|patches| run_to_halt(sequence.clone(), patches, &[3]))
+      .collect();
+    assert_eq!(run_batch(&sequence, &candidates, &[3]), expected);
+    assert_eq!(run_batch(&sequence, &[PatchSet::new()], &[]), vec![None]);
+  }
+
+  #[test]
+  fn search_across_batches() {
+    // Multiplies addresses 5 and 6 into address 0, with the match in the third batch
+    let sequence = super::super::parse("2,5,6,0,99,0,0");
+    let sites = vec![SearchSite::new(5, 0..30), SearchSite::new(6, 0..30)];
+    let result = search(&sequence, &sites, &[], |run| run.memory[0] == 29 * 19);
+    assert_eq!(result, Ok(Some("5=19,6=29".parse().unwrap())));
+  }
+
+  #[test]
+  fn search_not_found() {
+    let sequence = super::super::parse("1,5,6,0,99,0,0");
+    let sites = vec![SearchSite::new(5, 0..3), SearchSite::new(6, 0..3)];
+    assert_eq!(
+      search(&sequence, &sites, &[], |run| run.memory[0] > 10),
+      Ok(None)
+    );
+  }
+
+  #[test]
+  fn search_missing_input() {
+    let sequence = super::super::parse("3,0,99");
+    let sites = vec![SearchSite::new(1, 0..3)];
+    assert_eq!(search(&sequence, &sites, &[], |_| true), Ok(None));
+  }
+
+  #[test]
+  fn search_invalid_sites() {
+    let sequence = super::super::parse("1,5,6,0,99,0,0");
+    let sites = vec![SearchSite::new(5, 0..3), SearchSite::new(7, 0..3)];
+    assert_eq!(
+      search(&sequence, &sites, &[], |_| true),
+      Err(PatchError::OutOfBounds { address: 7, len: 7 })
+    );
+    let sites = vec![SearchSite::new(5, 0..3), SearchSite::new(5, 3..6)];
+    assert_eq!(
+      search(&sequence, &sites, &[], |_| true),
+      Err(PatchError::DuplicateAddress(5))
+    );
+    let sites = vec![
+      SearchSite::new(5, 0..1 << 32),
+      SearchSite::new(6, 0..1 << 32),
+    ];
+    assert_eq!(
+      search(&sequence, &sites, &[], |_| true),
+      Err(PatchError::TooManyCandidates)
+    );
+  }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+/// Counters kept while a computer runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunStats {
+  pub instructions_executed: u64,
+  pub inputs_consumed: u64,
+  pub outputs_emitted: u64,
+  /// Largest the memory has been, in words
+  pub peak_memory_size: usize,
+  /// Address of the instruction that halted the program, once it has halted
+  pub halt_address: Option<usize>,
+}
+
+impl fmt::Display for RunStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} instructions, {} inputs, {} outputs, peak memory {} words",
+      self.instructions_executed, self.inputs_consumed, self.outputs_emitted, self.peak_memory_size
+    )?;
+    if let Some(address) = self.halt_address {
+      write!(f, ", halted at {}", address)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, IntcodeComputer, IntcodeComputerState};
+  use super::*;
+
+  #[test]
+  fn counts_run() {
+    let computer = IntcodeComputer::new(parse("3,9,8,9,10,9,4,9,99,-1,8"))
+      .start()
+      .as_input()
+      .unwrap()
+      .execute(8)
+      .as_output()
+      .unwrap()
+      .execute()
+      .as_halt()
+      .unwrap();
+    assert_eq!(
+      *computer.stats(),
+      RunStats {
+        instructions_executed: 4,
+        inputs_consumed: 1,
+        outputs_emitted: 1,
+        peak_memory_size: 11,
+        halt_address: Some(8),
+      }
+    );
+    assert_eq!(
+      computer.stats().to_string(),
+      "4 instructions, 1 inputs, 1 outputs, peak memory 11 words, halted at 8"
+    );
+  }
+
+  #[test]
+  fn counts_loops() {
+    // Counts address 11 down from 10
+    let computer = IntcodeComputer::new(parse("1001,11,-1,11,1005,11,0,4,11,99,0,10"))
+      .start()
+      .as_output()
+      .unwrap();
+    assert_eq!(computer.stats().instructions_executed, 21);
+    assert_eq!(computer.stats().halt_address, None);
+  }
+}
@@ -0,0 +1,322 @@
+// Translates Intcode programs into standalone C.
+//
+// The generated program reads inputs from stdin (whitespace or comma separated) and
+// writes each output to stdout on its own line. Passing `--memory` prints the final
+// memory as a `memory` line after the program halts. It exits with 0 on halt, 1 on a
+// fault (including arithmetic overflow, as under `OverflowPolicy::Fault`), 2 if it runs
+// out of input and 3 on an out of bounds address.
+//
+// Instructions reachable from address 0 are compiled with their parameters as constants.
+// Every write address is a constant too, so writes that land on compiled code are known
+// ahead of time; those hand the rest of the run to an embedded interpreter, as do jumps
+// to anything that wasn't compiled.
+
+use super::disasm::{CodeMap, DecodedInstruction};
+use super::{InstructionSet, IntcodeSequence};
+use std::fmt::Write;
+
+const HEADER: &str = r#"#include <ctype.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef int64_t word;
+"#;
+
+const RUNTIME: &str = r#"
+static int dump_memory = 0;
+
+static void out_of_bounds(word address) {
+  fprintf(stderr, "Address %lld is out of bounds\n", (long long)address);
+  exit(3);
+}
+
+static word load(word address) {
+  if (address < 0 || address >= MEMORY_SIZE) out_of_bounds(address);
+  return mem[address];
+}
+
+static void store(word address, word value) {
+  if (address < 0 || address >= MEMORY_SIZE) out_of_bounds(address);
+  mem[address] = value;
+}
+
+static void overflow(word pointer) {
+  fprintf(stderr, "Arithmetic overflow at instruction pointer %lld\n", (long long)pointer);
+  exit(1);
+}
+
+static word add(word a, word b, word pointer) {
+  word result;
+  if (__builtin_add_overflow(a, b, &result)) overflow(pointer);
+  return result;
+}
+
+static word mul(word a, word b, word pointer) {
+  word result;
+  if (__builtin_mul_overflow(a, b, &result)) overflow(pointer);
+  return result;
+}
+
+static word input(void) {
+  int c;
+  long long value;
+  fflush(stdout);
+  while ((c = getchar()) != EOF && (c == ',' || isspace(c))) {
+  }
+  if (c == EOF) {
+    fprintf(stderr, "Program needs more input\n");
+    exit(2);
+  }
+  ungetc(c, stdin);
+  if (scanf("%lld", &value) != 1) {
+    fprintf(stderr, "Invalid input\n");
+    exit(2);
+  }
+  return (word)value;
+}
+
+static void output(word value) { printf("%lld\n", (long long)value); }
+
+static int halt(void) {
+  if (dump_memory) {
+    printf("memory ");
+    for (word i = 0; i < MEMORY_SIZE; i++) {
+      printf(i == 0 ? "%lld" : ",%lld", (long long)mem[i]);
+    }
+    printf("\n");
+  }
+  return 0;
+}
+
+static int fault_opcode(word opcode, word pointer) {
+  fprintf(stderr, "Unrecognized opcode %lld at instruction pointer %lld\n", (long long)opcode,
+          (long long)pointer);
+  return 1;
+}
+
+static int fault_mode(word mode, word pointer) {
+  fprintf(stderr, "Unrecognized parameter mode %lld at instruction pointer %lld\n",
+          (long long)mode, (long long)pointer);
+  return 1;
+}
+
+static int arity(word opcode) {
+  switch (opcode) {
+    case 1: case 2: case 7: case 8: return 3;
+    case 5: case 6: return 2;
+    case 3: case 4: return 1;
+    case 99: return 0;
+    default: return -1;
+  }
+}
+
+/* Runs the rest of the program from `pointer` one instruction at a time. */
+static int interpret(word pointer) {
+  for (;;) {
+    word instruction = load(pointer);
+    word opcode = instruction % 100;
+    int params = arity(opcode);
+    if (params < 0) return fault_opcode(opcode, pointer);
+    word mode[3], value[3], raw[3];
+    word place = 100;
+    for (int i = 0; i < params; i++, place *= 10) {
+      mode[i] = (instruction / place) % 10;
+      if (mode[i] != 0 && mode[i] != 1) return fault_mode(mode[i], pointer);
+    }
+    for (int i = 0; i < params; i++) {
+      raw[i] = load(pointer + 1 + i);
+      /* Parameters that are only written to are never read */
+      value[i] = mode[i] == 1 || i == 2 || opcode == 3 ? raw[i] : load(raw[i]);
+    }
+    word next = pointer + 1 + params;
+    switch (opcode) {
+      case 1: store(raw[2], add(value[0], value[1], pointer)); break;
+      case 2: store(raw[2], mul(value[0], value[1], pointer)); break;
+      case 3: store(raw[0], input()); break;
+      case 4: output(value[0]); break;
+      case 5: if (value[0] != 0) next = value[1]; break;
+      case 6: if (value[0] == 0) next = value[1]; break;
+      case 7: store(raw[2], value[0] < value[1]); break;
+      case 8: store(raw[2], value[0] == value[1]); break;
+      case 99: return halt();
+    }
+    if (next < 0) out_of_bounds(next);
+    pointer = next;
+  }
+}
+"#;
+
+fn literal(value: isize) -> String {
+  if value == isize::MIN {
+    format!("({}LL - 1)", isize::MIN + 1)
+  } else {
+    format!("{}LL", value)
+  }
+}
+
+struct Emitter<'a> {
+  code_map: &'a CodeMap,
+  /// Addresses covered by compiled instructions, including their parameters
+  code: Vec<bool>,
+}
+
+impl Emitter<'_> {
+  fn read(&self, instruction: &DecodedInstruction, i: usize) -> String {
+    let param = instruction.parameters[i];
+    match instruction.parameter_modes[i] {
+      1 => literal(param),
+      _ => format!("load({})", literal(param)),
+    }
+  }
+
+  fn write(&self, instruction: &DecodedInstruction, i: usize, value: String, out: &mut String) {
+    let address = instruction.parameters[i];
+    writeln!(out, "  store({}, {});", literal(address), value).unwrap();
+    let overwrites_code = address >= 0 && self.code.get(address as usize) == Some(&true);
+    if overwrites_code {
+      writeln!(
+        out,
+        "  return interpret({});",
+        instruction.address + instruction.len()
+      )
+      .unwrap();
+    }
+  }
+
+  fn jump(&self, target: String, instruction: &DecodedInstruction) -> String {
+    match instruction.static_jump_target() {
+      Some(target) if self.code_map.instructions.contains_key(&target) => {
+        format!("goto L{};", target)
+      }
+      _ => format!(
+        "{{ pointer = {}; if (pointer < 0) out_of_bounds(pointer); goto dispatch; }}",
+        target
+      ),
+    }
+  }
+
+  fn instruction(&self, instruction: &DecodedInstruction, out: &mut String) {
+    writeln!(out, "L{}: /* {} */", instruction.address, instruction).unwrap();
+    match instruction.opcode {
+      1 | 2 | 7 | 8 => {
+        let (a, b) = (self.read(instruction, 0), self.read(instruction, 1));
+        let value = match instruction.opcode {
+          1 => format!("add({}, {}, {})", a, b, instruction.address),
+          2 => format!("mul({}, {}, {})", a, b, instruction.address),
+          7 => format!("{} < {}", a, b),
+          _ => format!("{} == {}", a, b),
+        };
+        self.write(instruction, 2, value, out);
+      }
+      3 => self.write(instruction, 0, "input()".into(), out),
+      4 => writeln!(out, "  output({});", self.read(instruction, 0)).unwrap(),
+      5 | 6 => {
+        let comparison = if instruction.opcode == 5 { "!=" } else { "==" };
+        writeln!(
+          out,
+          "  if ({} {} 0) {}",
+          self.read(instruction, 0),
+          comparison,
+          self.jump(self.read(instruction, 1), instruction)
+        )
+        .unwrap();
+      }
+      _ => writeln!(out, "  return halt();").unwrap(),
+    }
+  }
+}
+
+/// Returns C source for a program that runs `sequence`.
+pub fn transpile_to_c(sequence: &IntcodeSequence) -> String {
+  let code_map = CodeMap::new(sequence, &InstructionSet::standard());
+  let mut code = vec![false; sequence.len()];
+  for instruction in code_map.instructions.values() {
+    code[instruction.address..instruction.address + instruction.len()].fill(true);
+  }
+  let emitter = Emitter {
+    code_map: &code_map,
+    code,
+  };
+
+  let mut out = String::new();
+  writeln!(out, "/* Generated from an Intcode program */").unwrap();
+  out.push_str(HEADER);
+  writeln!(out, "\n#define MEMORY_SIZE {}", sequence.len()).unwrap();
+  writeln!(out, "static word mem[{}] = {{", sequence.len().max(1)).unwrap();
+  for chunk in sequence.chunks(16) {
+    let words: Vec<String> = chunk.iter().map(|word| literal(*word)).collect();
+    writeln!(out, "  {},", words.join(", ")).unwrap();
+  }
+  writeln!(out, "}};").unwrap();
+  out.push_str(RUNTIME);
+
+  writeln!(out, "\nstatic int run(void) {{").unwrap();
+  writeln!(out, "  word pointer = 0;").unwrap();
+  writeln!(out, "dispatch:").unwrap();
+  writeln!(out, "  switch (pointer) {{").unwrap();
+  for address in code_map.instructions.keys() {
+    writeln!(out, "    case {}: goto L{};", address, address).unwrap();
+  }
+  writeln!(out, "    default: return interpret(pointer);").unwrap();
+  writeln!(out, "  }}").unwrap();
+
+  let instructions: Vec<_> = code_map.instructions.values().collect();
+  for (i, instruction) in instructions.iter().enumerate() {
+    emitter.instruction(instruction, &mut out);
+    if instruction.falls_through() {
+      let next = instruction.address + instruction.len();
+      let next_emitted = instructions.get(i + 1).map(|next| next.address);
+      if next_emitted != Some(next) {
+        writeln!(out, "  pointer = {};\n  goto dispatch;", next).unwrap();
+      }
+    }
+  }
+  writeln!(out, "}}").unwrap();
+
+  writeln!(
+    out,
+    r#"
+int main(int argc, char **argv) {{
+  for (int i = 1; i < argc; i++) {{
+    if (strcmp(argv[i], "--memory") == 0) dump_memory = 1;
+  }}
+  return run();
+}}"#
+  )
+  .unwrap();
+  out
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::parse;
+  use super::*;
+
+  #[test]
+  fn compiles_reachable_code() {
+    let c = transpile_to_c(&parse("1,9,10,3,2,3,11,0,99,30,40,50"));
+    assert!(c.contains("#define MEMORY_SIZE 12"));
+    assert!(c.contains("L0: /* add [9], [10], [3] */\n  store(3LL, add(load(9LL), load(10LL), 0));\n  return interpret(4);"));
+    assert!(c.contains("L8: /* halt */\n  return halt();"));
+    assert!(!c.contains("L9:"));
+  }
+
+  #[test]
+  fn jumps() {
+    let c = transpile_to_c(&parse("3,11,1005,11,7,0,0,104,1,99,0,0"));
+    assert!(c.contains("  if (load(11LL) != 0) goto L7;"));
+    // The jump can fall through into data, which is left to the interpreter
+    assert!(c.contains("  pointer = 5;\n  goto dispatch;"));
+
+    let c = transpile_to_c(&parse("3,7,6,7,7,99,0,0"));
+    assert!(c.contains("if (load(7LL) == 0) { pointer = load(7LL);"));
+  }
+
+  #[test]
+  fn literals() {
+    assert_eq!(literal(-5), "-5LL");
+    assert_eq!(literal(isize::MIN), "(-9223372036854775807LL - 1)");
+  }
+}
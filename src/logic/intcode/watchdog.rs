@@ -0,0 +1,185 @@
+use super::{Fault, IntcodeMemory};
+
+/// Watches for programs that are stuck in an infinite loop.
+///
+/// Without input or output, a program's next step depends only on its pointer, relative
+/// base and memory. So once a program has gone `quiet_steps` instructions without I/O, the
+/// watchdog looks for a repeated (pointer, relative base, memory) with Brent's algorithm:
+/// it keeps one snapshot and compares every step against it, moving the snapshot forward
+/// after 1, 2, 4, ... steps. If a step ever matches, the program will repeat the same steps
+/// forever. Memory is only compared when the pointer and relative base match, and the
+/// snapshot is the only copy kept, so slow programs that do finish aren't held up much.
+#[derive(Debug)]
+pub(super) struct Watchdog<W> {
+  quiet_steps: u64,
+  steps_since_io: u64,
+  snapshot: Option<Snapshot<W>>,
+  /// How many steps the snapshot is kept before it's moved forward
+  power: u64,
+  steps_since_snapshot: u64,
+  /// The lowest and highest pointers since the snapshot was taken
+  lowest: usize,
+  highest: usize,
+}
+
+#[derive(Debug)]
+struct Snapshot<W> {
+  pointer: usize,
+  relative_base: isize,
+  memory: IntcodeMemory<W>,
+}
+
+impl<W: Clone + Eq> Watchdog<W> {
+  pub(super) fn new(quiet_steps: u64) -> Watchdog<W> {
+    Watchdog {
+      quiet_steps,
+      steps_since_io: 0,
+      snapshot: None,
+      power: 1,
+      steps_since_snapshot: 0,
+      lowest: 0,
+      highest: 0,
+    }
+  }
+
+  pub(super) fn quiet_steps(&self) -> u64 {
+    self.quiet_steps
+  }
+
+  /// A watchdog that waits as long as this one does, from the start.
+  pub(super) fn restarted<V: Clone + Eq>(&self) -> Watchdog<V> {
+    Watchdog::new(self.quiet_steps)
+  }
+
+  /// Called before each instruction executes.
+  pub(super) fn check(
+    &mut self,
+    sequence: &IntcodeMemory<W>,
+    pointer: usize,
+    relative_base: isize,
+  ) -> Result<(), Fault> {
+    self.steps_since_io += 1;
+    if self.steps_since_io <= self.quiet_steps {
+      return Ok(());
+    }
+
+    match self.snapshot.as_mut() {
+      Some(snapshot)
+        if snapshot.pointer == pointer
+          && snapshot.relative_base == relative_base
+          && snapshot.memory == *sequence =>
+      {
+        return Err(Fault::InfiniteLoop {
+          start: self.lowest,
+          end: self.highest,
+        });
+      }
+      Some(snapshot) if self.steps_since_snapshot == self.power => {
+        snapshot.pointer = pointer;
+        snapshot.relative_base = relative_base;
+        snapshot.memory.clone_from(sequence);
+        self.power *= 2;
+        self.steps_since_snapshot = 0;
+        self.lowest = pointer;
+        self.highest = pointer;
+      }
+      Some(_) => (),
+      None => {
+        self.snapshot = Some(Snapshot {
+          pointer,
+          relative_base,
+          memory: sequence.clone(),
+        });
+        self.lowest = pointer;
+        self.highest = pointer;
+      }
+    }
+    self.steps_since_snapshot += 1;
+    self.lowest = self.lowest.min(pointer);
+    self.highest = self.highest.max(pointer);
+    Ok(())
+  }
+
+  /// Called whenever the program does input or output.
+  pub(super) fn reset(&mut self) {
+    self.steps_since_io = 0;
+    self.snapshot = None;
+    self.power = 1;
+    self.steps_since_snapshot = 0;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, InstructionSet, IntcodeComputer, IntcodeComputerState};
+  use super::*;
+
+  #[test]
+  fn detects_tight_loop() {
+    let computer = IntcodeComputer::new(parse("1105,1,0"))
+      .with_watchdog(10)
+      .start()
+      .as_fault()
+      .unwrap();
+    assert_eq!(computer.fault, Fault::InfiniteLoop { start: 0, end: 0 });
+  }
+
+  #[test]
+  fn detects_repeating_memory() {
+    // Flips the sign of address 7 forever
+    let computer = IntcodeComputer::new(parse("1002,7,-1,7,1105,1,0,1"))
+      .with_watchdog(100)
+      .start()
+      .as_fault()
+      .unwrap();
+    assert_eq!(computer.fault, Fault::InfiniteLoop { start: 0, end: 4 });
+  }
+
+  #[test]
+  fn detects_long_cycles() {
+    // Counts address 20 from 0 up to 100, then starts again from 0
+    let computer = IntcodeComputer::new(parse(
+      "1001,20,1,20,1008,20,100,21,1006,21,0,1101,0,0,20,1105,1,0,99,99,0,0",
+    ))
+    .with_watchdog(10)
+    .start()
+    .as_fault()
+    .unwrap();
+    assert_eq!(computer.fault, Fault::InfiniteLoop { start: 0, end: 15 });
+    assert!(computer.stats().instructions_executed < 2000);
+  }
+
+  #[test]
+  fn allows_long_running_programs() {
+    // Counts address 11 down from 5000, then outputs it
+    let computer = IntcodeComputer::new(parse("1001,11,-1,11,1005,11,0,4,11,99,0,5000"))
+      .with_watchdog(10)
+      .start()
+      .as_output()
+      .unwrap();
+    assert_eq!(computer.output, 0);
+  }
+
+  #[test]
+  fn tells_relative_bases_apart() {
+    // Walks the relative base down from 13 until it finds a 0, then outputs it. Memory
+    // never changes, so only the relative base says it isn't looping.
+    let computer = IntcodeComputer::new(parse("109,13,109,-1,1205,0,2,204,0,99,0,1,1,1"))
+      .with_instruction_set(InstructionSet::relative())
+      .with_watchdog(1)
+      .start();
+    assert!(computer.as_output().is_ok());
+  }
+
+  #[test]
+  fn resets_on_io() {
+    // Outputs forever, which isn't something the watchdog should flag
+    let mut computer = IntcodeComputer::new(parse("104,1,1105,1,0"))
+      .with_watchdog(1)
+      .start();
+    for _ in 0..100 {
+      computer = computer.as_output().unwrap().execute();
+    }
+    assert_eq!(computer.get_pointer(), 2);
+  }
+}
@@ -0,0 +1,239 @@
+// The types a computer's memory can be made of.
+//
+// Programs are plain `isize` words unless asked otherwise, which is as wide as the
+// platform goes. `i64` and `i128` give the same results on every platform, and `BigInt`
+// never overflows at all.
+
+use super::bigint::BigInt;
+use super::instructions::{InstructionSet, STANDARD_INSTRUCTION_SET};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A memory word. Addresses, opcodes and parameter modes always fit in an `isize`, but
+/// values can be as wide as the type allows.
+pub trait Word:
+  Clone + Eq + Ord + Hash + fmt::Debug + fmt::Display + FromStr + Send + Sync + 'static
+{
+  fn from_isize(value: isize) -> Self;
+
+  /// The word as an `isize`, if it fits.
+  fn to_isize(&self) -> Option<isize>;
+
+  fn checked_add(&self, other: &Self) -> Option<Self>;
+  fn checked_mul(&self, other: &Self) -> Option<Self>;
+  fn wrapping_add(&self, other: &Self) -> Self;
+  fn wrapping_mul(&self, other: &Self) -> Self;
+  fn saturating_add(&self, other: &Self) -> Self;
+  fn saturating_mul(&self, other: &Self) -> Self;
+
+  /// The standard instruction set for this word type, shared where possible.
+  fn standard_instruction_set() -> Arc<InstructionSet<Self>> {
+    Arc::new(InstructionSet::standard_for_words())
+  }
+
+  fn is_zero(&self) -> bool {
+    self.to_isize() == Some(0)
+  }
+
+  /// The word as a memory address, if it is one.
+  fn to_address(&self) -> Option<usize> {
+    self
+      .to_isize()
+      .and_then(|value| usize::try_from(value).ok())
+  }
+
+  /// The same value as another kind of word, if it fits in one.
+  fn convert<V: Word>(&self) -> Option<V> {
+    match self.to_isize() {
+      Some(value) => Some(V::from_isize(value)),
+      None => self.to_string().parse().ok(),
+    }
+  }
+
+  /// Like `to_address`, but panics on a bad address the way indexing memory would.
+  fn expect_address(&self) -> usize {
+    self
+      .to_address()
+      .unwrap_or_else(|| panic!("{} is not a valid address", self))
+  }
+}
+
+macro_rules! impl_fixed_word {
+  ( $x:ty ) => {
+    impl_fixed_word!($x, {});
+  };
+  ( $x:ty, { $( $extra:item )* } ) => {
+    impl Word for $x {
+      fn from_isize(value: isize) -> Self {
+        value as $x
+      }
+      fn to_isize(&self) -> Option<isize> {
+        isize::try_from(*self).ok()
+      }
+      fn checked_add(&self, other: &Self) -> Option<Self> {
+        <$x>::checked_add(*self, *other)
+      }
+      fn checked_mul(&self, other: &Self) -> Option<Self> {
+        <$x>::checked_mul(*self, *other)
+      }
+      fn wrapping_add(&self, other: &Self) -> Self {
+        <$x>::wrapping_add(*self, *other)
+      }
+      fn wrapping_mul(&self, other: &Self) -> Self {
+        <$x>::wrapping_mul(*self, *other)
+      }
+      fn saturating_add(&self, other: &Self) -> Self {
+        <$x>::saturating_add(*self, *other)
+      }
+      fn saturating_mul(&self, other: &Self) -> Self {
+        <$x>::saturating_mul(*self, *other)
+      }
+      fn is_zero(&self) -> bool {
+        *self == 0
+      }
+      $( $extra )*
+    }
+  };
+}
+
+impl_fixed_word!(i64);
+impl_fixed_word!(i128);
+
+impl_fixed_word!(isize, {
+  fn standard_instruction_set() -> Arc<InstructionSet<Self>> {
+    STANDARD_INSTRUCTION_SET.clone()
+  }
+});
+
+/// Big integers never overflow, so every `OverflowPolicy` keeps the exact result.
+impl Word for BigInt {
+  fn from_isize(value: isize) -> Self {
+    BigInt::from(value)
+  }
+  fn to_isize(&self) -> Option<isize> {
+    isize::try_from(self).ok()
+  }
+  fn checked_add(&self, other: &Self) -> Option<Self> {
+    Some(self + other)
+  }
+  fn checked_mul(&self, other: &Self) -> Option<Self> {
+    Some(self * other)
+  }
+  fn wrapping_add(&self, other: &Self) -> Self {
+    self + other
+  }
+  fn wrapping_mul(&self, other: &Self) -> Self {
+    self * other
+  }
+  fn saturating_add(&self, other: &Self) -> Self {
+    self + other
+  }
+  fn saturating_mul(&self, other: &Self) -> Self {
+    self * other
+  }
+  fn is_zero(&self) -> bool {
+    BigInt::is_zero(self)
+  }
+}
+
+/// Parses a program in the text format into any word type.
+pub fn parse_words<W: Word>(input: &str) -> Result<Vec<W>, String> {
+  input
+    .trim()
+    .split(',')
+    .enumerate()
+    .map(|(index, word)| {
+      word
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid word {:?} at index {}", word.trim(), index))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{parse, Fault, IntcodeComputer, IntcodeComputerState};
+  use super::*;
+
+  // Squares address 11 twice, then outputs it
+  const SQUARE_TWICE: &str = "2,11,11,11,2,11,11,11,4,11,99";
+
+  fn square_twice<W: Word>(start: &str) -> IntcodeComputer<W> {
+    let program = parse_words(&format!("{},{}", SQUARE_TWICE, start)).unwrap();
+    IntcodeComputer::from_words(program).start()
+  }
+
+  #[test]
+  fn fixed_words() {
+    assert_eq!(<i64 as Word>::checked_add(&i64::MAX, &1), None);
+    assert_eq!(<i128 as Word>::checked_mul(&(1 << 100), &2), Some(1 << 101));
+    assert_eq!((1i128 << 100).to_isize(), None);
+    assert_eq!((-5i64).to_address(), None);
+    assert_eq!(7i128.to_address(), Some(7));
+    assert!(Word::is_zero(&0i128));
+  }
+
+  #[test]
+  fn parses_words() {
+    assert_eq!(parse_words::<i128>("1, -2,3\n"), Ok(vec![1, -2, 3]));
+    let big = parse_words::<BigInt>("170141183460469231731687303715884105728").unwrap();
+    assert_eq!(
+      big[0].to_string(),
+      "170141183460469231731687303715884105728"
+    );
+    assert_eq!(
+      parse_words::<i64>("1,x"),
+      Err("Invalid word \"x\" at index 1".to_string())
+    );
+  }
+
+  #[test]
+  fn runs_programs_too_wide_for_isize() {
+    let fault = square_twice::<isize>("1048576").as_fault().unwrap();
+    assert_eq!(fault.fault, Fault::Overflow { pointer: 4 });
+
+    let computer = square_twice::<i128>("1048576").as_output().unwrap();
+    assert_eq!(computer.output, 1 << 80);
+
+    let computer = square_twice::<BigInt>("1099511627776").as_output().unwrap();
+    assert_eq!(
+      computer.output.to_string(),
+      "1461501637330902918203684832716283019655932542976"
+    );
+    assert!(computer.execute().as_halt().is_ok());
+  }
+
+  #[test]
+  fn runs_io_and_jumps() {
+    // Counts an input down to zero, outputting each value
+    let program = parse_words("3,12,4,12,1001,12,-1,12,1005,12,2,99,0").unwrap();
+    let mut computer = IntcodeComputer::<BigInt>::from_words(program)
+      .start()
+      .as_input()
+      .unwrap()
+      .execute(BigInt::from(3isize));
+    let mut outputs = vec![];
+    while let IntcodeComputer::Output(state) = computer {
+      outputs.push(state.output.to_string());
+      computer = state.execute();
+    }
+    assert_eq!(outputs, vec!["3", "2", "1"]);
+    assert_eq!(computer.as_halt().unwrap().get_pointer(), 11);
+  }
+
+  #[test]
+  fn faults_on_oversized_instructions() {
+    let program = parse_words::<i128>("170141183460469231731687303715884105727").unwrap();
+    let fault = IntcodeComputer::from_words(program)
+      .start()
+      .as_fault()
+      .unwrap();
+    assert_eq!(fault.fault, Fault::OversizedInstruction { pointer: 0 });
+    // Still the standard instruction set, shared like it is for isize
+    assert!(IntcodeComputer::new(parse("99")).start().as_halt().is_ok());
+  }
+}
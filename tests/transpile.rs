@@ -0,0 +1,159 @@
+// Checks that programs transpiled to C behave like `IntcodeComputer`, by building them
+// with the system C compiler. Skipped when there is no `cc`.
+
+use advent_of_code_2019::intcode::fuzz::{
+  run_catching, HaltKind, Outputs, ProgramGenerator, Rng, StateMachineEngine,
+};
+use advent_of_code_2019::intcode::{parse, transpile, IntcodeSequence};
+use rayon::prelude::*;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+fn cc_available() -> bool {
+  Command::new("cc")
+    .arg("--version")
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .status()
+    .is_ok()
+}
+
+fn work_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("intcode-transpile-{}-{}", name, process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+fn build(dir: &Path, name: &str, sequence: &IntcodeSequence) -> PathBuf {
+  let source = dir.join(format!("{}.c", name));
+  let binary = dir.join(name);
+  fs::write(&source, transpile::transpile_to_c(sequence)).unwrap();
+  let status = Command::new("cc")
+    .args(["-O1", "-w", "-o"])
+    .arg(&binary)
+    .arg(&source)
+    .status()
+    .unwrap();
+  assert!(status.success(), "cc failed on {}", source.display());
+  binary
+}
+
+/// Runs the native program the way `StateMachineEngine` would run the sequence, returning
+/// its outputs, its final memory if it halted, and how it stopped.
+fn run_native(binary: &Path, inputs: &[isize]) -> (Vec<isize>, Option<IntcodeSequence>, i32) {
+  let mut child = Command::new(binary)
+    .arg("--memory")
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+  let inputs: Vec<String> = inputs.iter().map(|input| input.to_string()).collect();
+  // The program may halt without reading all of its inputs
+  let _ = child
+    .stdin
+    .take()
+    .unwrap()
+    .write_all(inputs.join("\n").as_bytes());
+  let output = child.wait_with_output().unwrap();
+
+  let mut outputs = vec![];
+  let mut memory = None;
+  for line in String::from_utf8(output.stdout).unwrap().lines() {
+    match line.strip_prefix("memory ") {
+      Some(words) => memory = Some(parse(words)),
+      None => outputs.push(line.parse().unwrap()),
+    }
+  }
+  (outputs, memory, output.status.code().unwrap())
+}
+
+fn check(dir: &Path, name: &str, sequence: &IntcodeSequence, inputs: &[isize]) {
+  let binary = build(dir, name, sequence);
+  let expected = run_catching(&StateMachineEngine, sequence, inputs);
+  let (outputs, memory, status) = run_native(&binary, inputs);
+
+  let context = format!("{}: {:?} with inputs {:?}", name, sequence, inputs);
+  if let HaltKind::Panicked(_) = expected.halt {
+    // The computer panics on a bad address, so there is nothing else to compare
+    assert_eq!(status, 3, "{}", context);
+    return;
+  }
+  assert_eq!(Outputs::All(outputs), expected.outputs, "{}", context);
+  match expected.halt {
+    HaltKind::Halted => {
+      assert_eq!(status, 0, "{}", context);
+      assert_eq!(memory, expected.memory, "{}", context);
+    }
+    HaltKind::Faulted(_) => assert_eq!(status, 1, "{}", context),
+    HaltKind::NeedsInput => assert_eq!(status, 2, "{}", context),
+    HaltKind::Panicked(_) => unreachable!(),
+  }
+}
+
+#[test]
+fn matches_intcode_computer() {
+  if !cc_available() {
+    eprintln!("Skipping: no C compiler");
+    return;
+  }
+  let dir = work_dir("programs");
+  let comparisons = parse(
+    "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,\
+     1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,\
+     999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99",
+  );
+  let countdown = parse("3,13,4,13,1001,13,-1,13,1005,13,2,99,0,0");
+  let cases: Vec<(&str, IntcodeSequence, Vec<isize>)> = vec![
+    ("compare_low", comparisons.clone(), vec![7]),
+    ("compare_equal", comparisons.clone(), vec![8]),
+    ("compare_high", comparisons, vec![9]),
+    ("countdown", countdown.clone(), vec![1000]),
+    ("needs_input", countdown, vec![]),
+    ("fault", parse("1,0,0,0,42"), vec![]),
+    ("overflow", parse("1002,5,2,5,99,4611686018427387904"), vec![]),
+    ("bad_mode", parse("204,1,99"), vec![]),
+    ("out_of_bounds", parse("4,100,99"), vec![]),
+    (
+      "dynamic_jump",
+      parse("3,11,1105,1,9,104,1,99,0,1006,12,11,0"),
+      vec![5],
+    ),
+  ];
+  cases
+    .par_iter()
+    .for_each(|(name, sequence, inputs)| check(&dir, name, sequence, inputs));
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn self_modifying_code_falls_back_to_interpreter() {
+  if !cc_available() {
+    eprintln!("Skipping: no C compiler");
+    return;
+  }
+  let dir = work_dir("self-modifying");
+  // Writes its halt instruction, then turns a halt into an output
+  check(&dir, "write_halt", &parse("1002,4,3,4,33"), &[]);
+  check(&dir, "write_output", &parse("1101,100,4,4,99,7,99"), &[]);
+  // Overwrites a parameter of a later instruction with an input
+  check(&dir, "write_parameter", &parse("3,5,1,0,0,7,99,0"), &[5]);
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fuzz() {
+  if !cc_available() {
+    eprintln!("Skipping: no C compiler");
+    return;
+  }
+  let dir = work_dir("fuzz");
+  let generator = ProgramGenerator::default();
+  (0..64u64).into_par_iter().for_each(|seed| {
+    let case = generator.generate(&mut Rng::new(seed));
+    check(&dir, &format!("case{}", seed), &case.sequence, &case.inputs);
+  });
+  fs::remove_dir_all(&dir).unwrap();
+}